@@ -0,0 +1,198 @@
+//! Decode, validate and replay captured Application Insights envelopes. Run with `--help` for
+//! usage.
+//!
+//! Input files may contain a `v2/track` request body (a JSON array, optionally gzip compressed), or
+//! envelopes as newline-delimited JSON like the ones written by the exporter's `EnvelopeSink`.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::{Request, Response};
+use opentelemetry_application_insights::{
+    cli_support::{parse_envelopes, replay, validate, ReplayTarget},
+    DeadLetter, Error,
+};
+use opentelemetry_http::{HttpClient, HttpError};
+use serde_json::Value;
+use std::{
+    error::Error as StdError,
+    fs,
+    io::{self, Read, Write},
+    process::ExitCode,
+    time::Duration,
+};
+
+const USAGE: &str = "\
+Usage: appinsights-envelopes <COMMAND> [FILE...]
+
+Reads envelopes from the given files, or stdin if no file is given. Files may contain a v2/track
+request body (a JSON array, optionally gzip compressed) or newline-delimited JSON envelopes.
+
+Commands:
+  decode           Print envelopes as pretty JSON
+  validate         Report fields, which exceed the Application Insights schema limits and would be
+                   truncated by the exporter
+  replay <TARGET>  Send envelopes to an ingestion endpoint with the retry policy of the exporter.
+                   TARGET is either a connection string (envelopes are re-keyed to its
+                   instrumentation key) or an ingestion endpoint URL. Connection strings with
+                   Authorization=AAD are not supported";
+
+/// Returns responses as they are. The `HttpClient` implementation of reqwest turns error status
+/// codes into errors and follows redirects on its own, which would bypass the retry logic.
+#[derive(Debug)]
+struct Client(reqwest::blocking::Client);
+
+impl Client {
+    fn new() -> Self {
+        Self(
+            reqwest::blocking::Client::builder()
+                .redirect(reqwest::redirect::Policy::none())
+                .build()
+                .expect("HTTP client can be created"),
+        )
+    }
+}
+
+#[async_trait]
+impl HttpClient for Client {
+    async fn send_bytes(&self, request: Request<Bytes>) -> Result<Response<Bytes>, HttpError> {
+        let response = self.0.execute(request.try_into()?)?;
+        let mut builder = Response::builder().status(response.status());
+        for (name, value) in response.headers() {
+            builder = builder.header(name, value);
+        }
+        Ok(builder.body(response.bytes()?)?)
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args, &Client::new()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Returns whether the command succeeded, i.e. all envelopes are valid or were accepted.
+fn run(args: &[String], client: &dyn HttpClient) -> Result<bool, Box<dyn StdError>> {
+    match args.first().map(String::as_str) {
+        Some("decode") => {
+            let mut stdout = io::stdout().lock();
+            for envelope in read_envelopes(&args[1..])? {
+                let json = serde_json::to_string_pretty(&envelope)?;
+                match writeln!(stdout, "{json}") {
+                    // E.g. piped into `head`.
+                    Err(err) if err.kind() == io::ErrorKind::BrokenPipe => break,
+                    result => result?,
+                }
+            }
+            Ok(true)
+        }
+        Some("validate") => {
+            let envelopes = read_envelopes(&args[1..])?;
+            let mut truncated = 0;
+            for (index, envelope) in envelopes.iter().enumerate() {
+                let name = envelope["name"].as_str().unwrap_or("<unknown>");
+                for field in validate(envelope) {
+                    println!(
+                        "envelope {index} ({name}): {} is {} bytes and would be truncated to {}",
+                        field.path, field.len, field.max_len
+                    );
+                    truncated += 1;
+                }
+            }
+            println!(
+                "{} envelopes, {truncated} fields would be truncated",
+                envelopes.len()
+            );
+            Ok(truncated == 0)
+        }
+        Some("replay") => {
+            let target: ReplayTarget = args.get(1).ok_or(USAGE)?.parse()?;
+            let envelopes = read_envelopes(&args[2..])?;
+            Ok(replay_envelopes(&target, envelopes, client))
+        }
+        Some("-h" | "--help") => {
+            println!("{USAGE}");
+            Ok(true)
+        }
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn read_envelopes(paths: &[String]) -> Result<Vec<Value>, Box<dyn StdError>> {
+    let mut envelopes = Vec::new();
+    if paths.is_empty() {
+        let mut input = Vec::new();
+        io::stdin().read_to_end(&mut input)?;
+        parse_envelopes(&input, &mut envelopes).map_err(|err| format!("stdin: {err}"))?;
+    }
+    for path in paths {
+        let input = fs::read(path).map_err(|err| format!("{path}: {err}"))?;
+        parse_envelopes(&input, &mut envelopes).map_err(|err| format!("{path}: {err}"))?;
+    }
+    Ok(envelopes)
+}
+
+/// Returns whether all envelopes were accepted.
+fn replay_envelopes(target: &ReplayTarget, envelopes: Vec<Value>, client: &dyn HttpClient) -> bool {
+    let count = envelopes.len();
+    let (stats, result) = futures_executor::block_on(replay(
+        client,
+        target,
+        envelopes,
+        |err: &Error, delay: Duration| eprintln!("retrying in {delay:?}: {err}"),
+        |items: &[DeadLetter]| {
+            for item in items {
+                eprintln!(
+                    "rejected with {}: {} {}",
+                    item.status_code,
+                    item.message.as_deref().unwrap_or("(no message)"),
+                    item.item
+                );
+            }
+        },
+    ));
+    println!(
+        "{count} envelopes: {} accepted, {} rejected, {} retried, {} dropped",
+        stats.accepted,
+        stats.rejected.values().sum::<u64>(),
+        stats.retried,
+        stats.dropped,
+    );
+    if let Err(err) = result {
+        eprintln!("error: {err}");
+        return false;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry_application_insights::test_util::{
+        FakeBreeze, FakeResponse, INSTRUMENTATION_KEY,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn replay_to_connection_string() {
+        let server = FakeBreeze::start();
+        server.respond_to_track(FakeResponse::status(503));
+        let envelopes = vec![
+            json!({ "name": "a", "time": "2025-01-01T00:00:00Z", "iKey": "other" }),
+            json!({ "name": "b", "time": "2025-01-01T00:00:00Z" }),
+        ];
+
+        let target = server.connection_string().parse().unwrap();
+        assert!(replay_envelopes(&target, envelopes, &Client::new()));
+        let received = server.envelopes();
+        assert_eq!(received.len(), 4);
+        assert!(received
+            .iter()
+            .all(|envelope| envelope.i_key.as_deref() == Some(INSTRUMENTATION_KEY)));
+    }
+}
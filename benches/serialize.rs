@@ -0,0 +1,272 @@
+//! Measures CPU time and memory of exporting a batch of spans with different compression levels.
+//!
+//! Run with `cargo bench --bench serialize`. Requests are sent to an HTTP client, which discards
+//! them, so only serialization and compression are measured. Memory is measured with a counting
+//! allocator: "peak" is the highest amount of memory allocated during an export on top of the
+//! memory allocated before it, "allocated" is the total of all allocations during an export.
+//!
+//! Each compression level is measured in four scenarios:
+//! - "export": every request succeeds.
+//! - "retry": the first request of every export fails partially with half of the items
+//!   retryable, so they are sent again in a second request.
+//! - "baseline": the JSON of a request body is serialized with `serde_json::to_vec` and then
+//!   compressed as a whole, like request bodies were built before buffers were reused.
+//! - "buffered": the same JSON is turned into a request body by the exporter's own code, which
+//!   serializes items one after another into a reused buffer and writes slices of it into the
+//!   encoder. Compare this to "baseline", since neither includes converting spans to telemetry
+//!   items.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use flate2::read::GzDecoder;
+use http::{Request, Response};
+use opentelemetry::{
+    trace::{SpanContext, SpanId, SpanKind, Status, TraceFlags, TraceId, TraceState},
+    InstrumentationScope, KeyValue,
+};
+use opentelemetry_application_insights::{bench, Compression, Exporter, RetryPolicy};
+use opentelemetry_http::{HttpClient, HttpError};
+use opentelemetry_sdk::trace::{SpanData, SpanEvents, SpanExporter, SpanLinks};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io::Read,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+const CONNECTION_STRING: &str = "InstrumentationKey=0fdcec70-0ce5-4085-89d9-9ae8ead9af66";
+const BATCH_SIZE: usize = 512;
+const ITERATIONS: usize = 50;
+
+struct CountingAllocator;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(current, Ordering::Relaxed);
+            ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Discards requests. If `partial` is set, every other request fails for half of its items.
+#[derive(Debug, Default)]
+struct DiscardClient {
+    partial: bool,
+    fail_next: AtomicBool,
+    last_body: Arc<Mutex<Bytes>>,
+}
+
+impl DiscardClient {
+    fn capturing(last_body: Arc<Mutex<Bytes>>) -> Self {
+        Self {
+            last_body,
+            ..Self::default()
+        }
+    }
+
+    fn partial() -> Self {
+        Self {
+            partial: true,
+            fail_next: AtomicBool::new(true),
+            ..Self::default()
+        }
+    }
+}
+
+#[async_trait]
+impl HttpClient for DiscardClient {
+    async fn send_bytes(&self, req: Request<Bytes>) -> Result<Response<Bytes>, HttpError> {
+        *self.last_body.lock().unwrap() = req.body().clone();
+        if self.partial && self.fail_next.fetch_xor(true, Ordering::Relaxed) {
+            let errors = (0..BATCH_SIZE)
+                .step_by(2)
+                .map(|index| {
+                    format!(r#"{{"index":{index},"statusCode":500,"message":"Internal"}}"#)
+                })
+                .collect::<Vec<_>>()
+                .join(",");
+            let body = format!(
+                r#"{{"itemsReceived":{BATCH_SIZE},"itemsAccepted":{},"errors":[{errors}]}}"#,
+                BATCH_SIZE / 2
+            );
+            return Ok(Response::builder()
+                .status(206)
+                .body(Bytes::from(body))
+                .expect("response is valid"));
+        }
+        Ok(Response::builder()
+            .status(200)
+            .body(Bytes::new())
+            .expect("response is valid"))
+    }
+}
+
+fn span(index: usize) -> SpanData {
+    let now = SystemTime::now();
+    SpanData {
+        span_context: SpanContext::new(
+            TraceId::from(index as u128 + 1),
+            SpanId::from(index as u64 + 1),
+            TraceFlags::SAMPLED,
+            false,
+            TraceState::default(),
+        ),
+        parent_span_id: SpanId::INVALID,
+        parent_span_is_remote: false,
+        span_kind: SpanKind::Server,
+        name: "GET /api/users/:id".into(),
+        start_time: now - Duration::from_millis(25),
+        end_time: now,
+        attributes: vec![
+            KeyValue::new("http.request.method", "GET"),
+            KeyValue::new("url.full", format!("https://example.com/api/users/{index}")),
+            KeyValue::new("http.response.status_code", 200),
+            KeyValue::new("client.address", "10.1.2.3"),
+            KeyValue::new("user_agent.original", "Mozilla/5.0 (X11; Linux x86_64)"),
+            KeyValue::new("app.user.id", format!("user-{index}")),
+        ],
+        dropped_attributes_count: 0,
+        events: SpanEvents::default(),
+        links: SpanLinks::default(),
+        status: Status::Unset,
+        instrumentation_scope: InstrumentationScope::builder("bench").build(),
+    }
+}
+
+fn batch() -> Vec<SpanData> {
+    (0..BATCH_SIZE).map(span).collect()
+}
+
+#[derive(Default)]
+struct Measurement {
+    elapsed: Duration,
+    peak: usize,
+    allocated: usize,
+}
+
+impl Measurement {
+    /// Runs `f` a number of times and records time and memory of each run.
+    fn run(mut f: impl FnMut()) -> Self {
+        // The first run fills the buffer pool.
+        f();
+
+        let mut measurement = Self::default();
+        for _ in 0..ITERATIONS {
+            let base = CURRENT.load(Ordering::Relaxed);
+            PEAK.store(base, Ordering::Relaxed);
+            let allocated_before = ALLOCATED.load(Ordering::Relaxed);
+            let start = Instant::now();
+            f();
+            measurement.elapsed += start.elapsed();
+            measurement.peak = measurement
+                .peak
+                .max(PEAK.load(Ordering::Relaxed).saturating_sub(base));
+            measurement.allocated += ALLOCATED.load(Ordering::Relaxed) - allocated_before;
+        }
+        measurement
+    }
+
+    fn print(&self, compression: Compression, scenario: &str) {
+        println!(
+            "{:<12} {:<10} {:>12.2?} {:>8} KiB {:>12} KiB",
+            format!("{compression:?}"),
+            scenario,
+            self.elapsed / ITERATIONS as u32,
+            self.peak / 1024,
+            self.allocated / ITERATIONS / 1024,
+        );
+    }
+}
+
+/// Builds a request body like before buffers were reused: the whole JSON first, then
+/// compressed in one go.
+fn baseline_body(items: &serde_json::Value, compression: Compression) -> Vec<u8> {
+    let serialized = serde_json::to_vec(items).expect("items can be serialized");
+    bench::compress(&serialized, compression).expect("compress")
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .expect("runtime can be created");
+
+    println!(
+        "{:<12} {:<10} {:>12} {:>12} {:>16}",
+        "compression", "scenario", "time/export", "peak", "allocated"
+    );
+    for compression in [
+        Compression::None,
+        Compression::Fast,
+        Compression::Default,
+        Compression::Best,
+    ] {
+        let last_body = Arc::default();
+        let exporter = Exporter::new_from_connection_string(
+            CONNECTION_STRING,
+            DiscardClient::capturing(Arc::clone(&last_body)),
+        )
+        .expect("connection string is valid")
+        .with_compression(compression);
+        Measurement::run(|| {
+            let batch = batch();
+            runtime.block_on(exporter.export(batch)).expect("export");
+        })
+        .print(compression, "export");
+
+        let retrying_exporter =
+            Exporter::new_from_connection_string(CONNECTION_STRING, DiscardClient::partial())
+                .expect("connection string is valid")
+                .with_compression(compression)
+                .with_retry_policy(RetryPolicy::new().with_min_delay(Duration::ZERO));
+        Measurement::run(|| {
+            let batch = batch();
+            runtime
+                .block_on(retrying_exporter.export(batch))
+                .expect("export");
+        })
+        .print(compression, "retry");
+
+        // Take the JSON of the last request body of the first exporter as the input.
+        let body = last_body.lock().unwrap().clone();
+        let mut json = Vec::new();
+        if compression == Compression::None {
+            json.extend_from_slice(&body);
+        } else {
+            GzDecoder::new(&body[..])
+                .read_to_end(&mut json)
+                .expect("decompress");
+        }
+        let items: serde_json::Value = serde_json::from_slice(&json).expect("valid JSON");
+        Measurement::run(|| {
+            std::hint::black_box(baseline_body(&items, compression));
+        })
+        .print(compression, "baseline");
+        let items = items.as_array().expect("request body is an array");
+        let buffers = bench::Buffers::default();
+        Measurement::run(|| {
+            let body = bench::request_body(&buffers, items, compression).expect("serialize");
+            buffers.give(std::hint::black_box(body));
+        })
+        .print(compression, "buffered");
+    }
+}
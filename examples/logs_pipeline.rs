@@ -33,7 +33,7 @@ impl opentelemetry_sdk::logs::LogProcessor for ErrorAsExceptionLogProcessor {
 fn any_value_to_string(v: &opentelemetry::logs::AnyValue) -> String {
     match v {
         opentelemetry::logs::AnyValue::String(v) => v.to_string(),
-        _ => format!("{:?}", v).into(),
+        _ => format!("{:?}", v),
     }
 }
 
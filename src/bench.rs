@@ -0,0 +1,34 @@
+//! Hooks for `benches/serialize.rs`, so it measures the request bodies of the exporter instead of
+//! a copy of its code. Not part of the public API.
+
+use crate::{
+    payload::{self, BufferPool},
+    uploader, Compression, Error,
+};
+use serde::Serialize;
+
+/// Buffers, which are reused across request bodies like the buffers of an exporter.
+#[derive(Debug, Default)]
+pub struct Buffers(BufferPool);
+
+impl Buffers {
+    /// Returns a request body to the pool after it was sent.
+    pub fn give(&self, body: Vec<u8>) {
+        self.0.give(body);
+    }
+}
+
+/// Builds the request body of the items like the exporter does: items are serialized one after
+/// another into a buffer, and slices of it are written into the encoder.
+pub fn request_body<T: Serialize>(
+    buffers: &Buffers,
+    items: &[T],
+    compression: Compression,
+) -> Result<Vec<u8>, Error> {
+    uploader::serialize(&buffers.0, items)?.into_request_body(&buffers.0, compression)
+}
+
+/// Compresses JSON, which is already serialized, like the exporter does for live metrics.
+pub fn compress(json: &[u8], compression: Compression) -> Result<Vec<u8>, Error> {
+    payload::serialize_request_body(json, compression)
+}
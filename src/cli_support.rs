@@ -0,0 +1,656 @@
+//! Helpers for the `appinsights-envelopes` binary, which decodes, validates and replays captured
+//! envelopes. Enabled with the **cli** feature. The binary lives in its own package, which also
+//! picks the HTTP client and TLS backend used for replaying.
+//!
+//! Input files may contain a `v2/track` request body (a JSON array, optionally gzip compressed), or
+//! envelopes as newline-delimited JSON like the ones written by [`EnvelopeSink`](crate::EnvelopeSink).
+
+use crate::{
+    append_path,
+    connection_string::ConnectionString,
+    dead_letter::{DeadLetter, DeadLetterNotify},
+    models::{
+        context_tag_keys::TAG_KEY_LOOKUP, AvailabilityData, DataPoint, Envelope, EventData,
+        ExceptionData, ExceptionDetails, MessageData, PageViewData, PageViewPerfData,
+        RemoteDependencyData, RequestData, StackFrame, MAX_PROPERTY_KEY_LEN,
+        MAX_PROPERTY_VALUE_LEN,
+    },
+    payload::{self, BufferPool, Compression},
+    stats::Counters,
+    uploader::{self, Endpoint, RetryNotify, Throttle, Upload},
+    Error, RetryPolicy, SignalStats,
+};
+use opentelemetry_http::HttpClient;
+use serde_json::Value;
+use std::{
+    error::Error as StdError,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Maximum lengths in bytes of string fields in the data of each telemetry type, taken from the
+/// `LimitedLenString` types in `models`. A `*` matches every element of an array.
+const DATA_LIMITS: &[(&str, &[&str], usize)] = &[
+    ("AvailabilityData", &["id"], AvailabilityData::MAX_ID_LEN),
+    (
+        "AvailabilityData",
+        &["name"],
+        AvailabilityData::MAX_NAME_LEN,
+    ),
+    (
+        "AvailabilityData",
+        &["runLocation"],
+        AvailabilityData::MAX_RUN_LOCATION_LEN,
+    ),
+    (
+        "AvailabilityData",
+        &["message"],
+        AvailabilityData::MAX_MESSAGE_LEN,
+    ),
+    ("EventData", &["name"], EventData::MAX_NAME_LEN),
+    (
+        "ExceptionData",
+        &["exceptions", "*", "typeName"],
+        ExceptionDetails::MAX_TYPE_NAME_LEN,
+    ),
+    (
+        "ExceptionData",
+        &["exceptions", "*", "message"],
+        ExceptionDetails::MAX_MESSAGE_LEN,
+    ),
+    (
+        "ExceptionData",
+        &["exceptions", "*", "stack"],
+        ExceptionDetails::MAX_STACK_LEN,
+    ),
+    (
+        "ExceptionData",
+        &["exceptions", "*", "parsedStack", "*", "method"],
+        StackFrame::MAX_METHOD_LEN,
+    ),
+    (
+        "ExceptionData",
+        &["exceptions", "*", "parsedStack", "*", "assembly"],
+        StackFrame::MAX_ASSEMBLY_LEN,
+    ),
+    (
+        "ExceptionData",
+        &["exceptions", "*", "parsedStack", "*", "fileName"],
+        StackFrame::MAX_FILE_NAME_LEN,
+    ),
+    (
+        "ExceptionData",
+        &["problemId"],
+        ExceptionData::MAX_PROBLEM_ID_LEN,
+    ),
+    ("MessageData", &["message"], MessageData::MAX_MESSAGE_LEN),
+    ("MetricData", &["metrics", "*", "ns"], DataPoint::MAX_NS_LEN),
+    (
+        "MetricData",
+        &["metrics", "*", "name"],
+        DataPoint::MAX_NAME_LEN,
+    ),
+    ("PageViewData", &["name"], PageViewData::MAX_NAME_LEN),
+    ("PageViewData", &["id"], PageViewData::MAX_ID_LEN),
+    ("PageViewData", &["url"], PageViewData::MAX_URL_LEN),
+    (
+        "PageViewData",
+        &["referrerUri"],
+        PageViewData::MAX_REFERRER_URI_LEN,
+    ),
+    (
+        "PageviewPerformanceData",
+        &["name"],
+        PageViewPerfData::MAX_NAME_LEN,
+    ),
+    (
+        "PageviewPerformanceData",
+        &["id"],
+        PageViewPerfData::MAX_ID_LEN,
+    ),
+    (
+        "PageviewPerformanceData",
+        &["url"],
+        PageViewPerfData::MAX_URL_LEN,
+    ),
+    (
+        "PageviewPerformanceData",
+        &["referrerUri"],
+        PageViewPerfData::MAX_REFERRER_URI_LEN,
+    ),
+    (
+        "RemoteDependencyData",
+        &["name"],
+        RemoteDependencyData::MAX_NAME_LEN,
+    ),
+    (
+        "RemoteDependencyData",
+        &["id"],
+        RemoteDependencyData::MAX_ID_LEN,
+    ),
+    (
+        "RemoteDependencyData",
+        &["resultCode"],
+        RemoteDependencyData::MAX_RESULT_CODE_LEN,
+    ),
+    (
+        "RemoteDependencyData",
+        &["data"],
+        RemoteDependencyData::MAX_DATA_LEN,
+    ),
+    (
+        "RemoteDependencyData",
+        &["target"],
+        RemoteDependencyData::MAX_TARGET_LEN,
+    ),
+    (
+        "RemoteDependencyData",
+        &["type"],
+        RemoteDependencyData::MAX_TYPE_LEN,
+    ),
+    ("RequestData", &["id"], RequestData::MAX_ID_LEN),
+    ("RequestData", &["source"], RequestData::MAX_SOURCE_LEN),
+    ("RequestData", &["name"], RequestData::MAX_NAME_LEN),
+    (
+        "RequestData",
+        &["responseCode"],
+        RequestData::MAX_RESPONSE_CODE_LEN,
+    ),
+    ("RequestData", &["url"], RequestData::MAX_URL_LEN),
+];
+const ENVELOPE_LIMITS: &[(&str, usize)] = &[
+    ("time", Envelope::MAX_TIME_LEN),
+    ("iKey", Envelope::MAX_I_KEY_LEN),
+];
+
+/// Parses a request body or a sequence of envelopes (one per line or pretty-printed).
+pub fn parse_envelopes(input: &[u8], envelopes: &mut Vec<Value>) -> Result<(), Box<dyn StdError>> {
+    let json = payload::decode(input)?;
+    for value in serde_json::Deserializer::from_slice(&json).into_iter::<Value>() {
+        match value? {
+            Value::Array(items) => envelopes.extend(items),
+            item => envelopes.push(item),
+        }
+    }
+    Ok(())
+}
+
+/// A field, which exceeds its maximum length and would be truncated by the exporter.
+#[derive(Debug, PartialEq)]
+pub struct TruncatedField {
+    /// Path of the field in the envelope, e.g. `baseData.properties.key`.
+    pub path: String,
+    /// Length of the field in bytes.
+    pub len: usize,
+    /// Maximum length of the field in bytes.
+    pub max_len: usize,
+}
+
+/// Returns the fields of the envelope, which exceed their maximum length.
+pub fn validate(envelope: &Value) -> Vec<TruncatedField> {
+    let mut truncated = Vec::new();
+    let mut check = |path: String, value: &str, max_len: usize| {
+        if value.len() > max_len {
+            truncated.push(TruncatedField {
+                path,
+                len: value.len(),
+                max_len,
+            });
+        }
+    };
+
+    for (field, max_len) in ENVELOPE_LIMITS {
+        if let Some(value) = envelope[field].as_str() {
+            check(field.to_string(), value, *max_len);
+        }
+    }
+    if let Some(tags) = envelope["tags"].as_object() {
+        for (key, value) in tags {
+            if let (Some(tag), Some(value)) = (TAG_KEY_LOOKUP.get(key.as_str()), value.as_str()) {
+                check(format!("tags.{key}"), value, tag.max_len());
+            }
+        }
+    }
+
+    let data = &envelope["data"];
+    let base_type = data["baseType"].as_str().unwrap_or_default();
+    let base_data = &data["baseData"];
+    for (_, path, max_len) in DATA_LIMITS
+        .iter()
+        .filter(|(limit_type, _, _)| *limit_type == base_type)
+    {
+        let mut values = Vec::new();
+        collect(base_data, path, "baseData".into(), &mut values);
+        for (path, value) in values {
+            check(path, value, *max_len);
+        }
+    }
+    if let Some(properties) = base_data["properties"].as_object() {
+        for (key, value) in properties {
+            check(
+                format!("baseData.properties.{key} (key)"),
+                key,
+                MAX_PROPERTY_KEY_LEN,
+            );
+            if let Some(value) = value.as_str() {
+                check(
+                    format!("baseData.properties.{key}"),
+                    value,
+                    MAX_PROPERTY_VALUE_LEN,
+                );
+            }
+        }
+    }
+    if let Some(measurements) = base_data["measurements"].as_object() {
+        for key in measurements.keys() {
+            check(
+                format!("baseData.measurements.{key} (key)"),
+                key,
+                MAX_PROPERTY_KEY_LEN,
+            );
+        }
+    }
+
+    truncated
+}
+
+/// Collects the string values at the given path, expanding `*` to all array elements.
+fn collect<'a>(value: &'a Value, path: &[&str], prefix: String, out: &mut Vec<(String, &'a str)>) {
+    match path.split_first() {
+        None => {
+            if let Some(value) = value.as_str() {
+                out.push((prefix, value));
+            }
+        }
+        Some((&"*", rest)) => {
+            for (index, item) in value.as_array().into_iter().flatten().enumerate() {
+                collect(item, rest, format!("{prefix}[{index}]"), out);
+            }
+        }
+        Some((field, rest)) => collect(&value[field], rest, format!("{prefix}.{field}"), out),
+    }
+}
+
+/// Where envelopes are replayed to: either a connection string, or an ingestion endpoint URL.
+#[derive(Debug)]
+pub struct ReplayTarget {
+    endpoint: http::Uri,
+    instrumentation_key: Option<String>,
+}
+
+impl FromStr for ReplayTarget {
+    type Err = Box<dyn StdError>;
+
+    fn from_str(target: &str) -> Result<Self, Self::Err> {
+        if !target.contains('=') {
+            return Ok(Self {
+                endpoint: append_path(target, "v2/track")?,
+                instrumentation_key: None,
+            });
+        }
+
+        let connection_string: ConnectionString = target.parse()?;
+        if connection_string.aad_scope.is_some() {
+            return Err("connection strings with Authorization=AAD are not supported, replay to the ingestion endpoint of a resource with local authentication enabled instead".into());
+        }
+        Ok(Self {
+            endpoint: append_path(&connection_string.ingestion_endpoint, "v2/track")?,
+            instrumentation_key: Some(connection_string.instrumentation_key),
+        })
+    }
+}
+
+impl ReplayTarget {
+    /// Sets the instrumentation key of the connection string on all envelopes.
+    fn rekey(&self, envelopes: &mut [Value]) {
+        let Some(ref instrumentation_key) = self.instrumentation_key else {
+            return;
+        };
+        for envelope in envelopes {
+            if let Some(envelope) = envelope.as_object_mut() {
+                envelope.insert("iKey".into(), instrumentation_key.clone().into());
+            }
+        }
+    }
+}
+
+/// Sends envelopes to the target with the default retry policy of the exporter. Returns the
+/// counters of the sent envelopes and the error of the last failed request, if any.
+pub async fn replay<R, D>(
+    client: &dyn HttpClient,
+    target: &ReplayTarget,
+    mut envelopes: Vec<Value>,
+    retry_notify: R,
+    dead_letter: D,
+) -> (SignalStats, Result<(), Error>)
+where
+    R: FnMut(&Error, Duration) + Send + 'static,
+    D: FnMut(&[DeadLetter]) + Send + 'static,
+{
+    target.rekey(&mut envelopes);
+    let endpoint = Endpoint::new(target.endpoint.clone());
+    let retry_policy = RetryPolicy::default();
+    let retry_notify: RetryNotify = Arc::new(Mutex::new(retry_notify));
+    let dead_letter: DeadLetterNotify = Arc::new(Mutex::new(dead_letter));
+    let throttle = Throttle::default();
+    let counters = Counters::default();
+    let buffers = BufferPool::default();
+    let upload = Upload {
+        client,
+        endpoint: &endpoint,
+        retry_policy: &retry_policy,
+        retry_notify: Some(&retry_notify),
+        dead_letter: Some(&dead_letter),
+        storage: None,
+        credential: None,
+        throttle: &throttle,
+        counters: &counters,
+        #[cfg(feature = "statsbeat")]
+        statsbeat: None,
+        compression: Compression::default(),
+        buffers: &buffers,
+    };
+
+    let result = match uploader::serialize(&buffers, &envelopes) {
+        Ok(serialized) => uploader::send_serialized(&upload, serialized).await,
+        Err(err) => Err(err),
+    };
+    (counters.snapshot(), result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression as GzCompression};
+    use serde_json::json;
+    use std::io::Write;
+
+    #[test]
+    fn parse_request_body_and_ndjson() {
+        let mut envelopes = Vec::new();
+        parse_envelopes(br#"[{"name":"a"},{"name":"b"}]"#, &mut envelopes).unwrap();
+        parse_envelopes(
+            b"{\"name\":\"c\"}\n{\n  \"name\": \"d\"\n}\n",
+            &mut envelopes,
+        )
+        .unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), GzCompression::default());
+        encoder.write_all(br#"[{"name":"e"}]"#).unwrap();
+        parse_envelopes(&encoder.finish().unwrap(), &mut envelopes).unwrap();
+
+        let names: Vec<_> = envelopes
+            .iter()
+            .map(|envelope| envelope["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["a", "b", "c", "d", "e"]);
+    }
+
+    #[test]
+    fn replay_target() {
+        let target: ReplayTarget = "InstrumentationKey=ikey;IngestionEndpoint=https://example.com"
+            .parse()
+            .unwrap();
+        let mut envelopes = vec![
+            json!({ "name": "a", "iKey": "other" }),
+            json!({ "name": "b" }),
+        ];
+        target.rekey(&mut envelopes);
+        assert_eq!(target.endpoint.to_string(), "https://example.com/v2/track");
+        assert!(envelopes.iter().all(|envelope| envelope["iKey"] == "ikey"));
+
+        let target: ReplayTarget = "http://localhost:1234/".parse().unwrap();
+        assert_eq!(
+            target.endpoint.to_string(),
+            "http://localhost:1234/v2/track"
+        );
+        assert_eq!(target.instrumentation_key, None);
+    }
+
+    #[test]
+    fn replay_target_with_aad_is_rejected() {
+        let err = "InstrumentationKey=ikey;Authorization=AAD"
+            .parse::<ReplayTarget>()
+            .unwrap_err();
+        assert!(err.to_string().contains("Authorization=AAD"), "{err}");
+    }
+
+    #[test]
+    fn validate_limits() {
+        let envelope = json!({
+            "name": "Microsoft.ApplicationInsights.Exception",
+            "time": "2025-01-01T00:00:00Z",
+            "iKey": "0fdcec70-0ce5-4085-89d9-9ae8ead9af66",
+            "tags": {
+                "ai.session.id": "s".repeat(65),
+                "ai.operation.id": "o".repeat(128),
+                "custom": "c".repeat(10000),
+            },
+            "data": {
+                "baseType": "ExceptionData",
+                "baseData": {
+                    "ver": 2,
+                    "exceptions": [
+                        { "typeName": "Error", "message": "ok" },
+                        { "typeName": "t".repeat(1025), "message": "ok" },
+                    ],
+                    "properties": {
+                        "k".repeat(151): "v",
+                        "long": "v".repeat(8193),
+                    },
+                },
+            },
+        });
+
+        assert_eq!(
+            validate(&envelope),
+            vec![
+                TruncatedField {
+                    path: "tags.ai.session.id".into(),
+                    len: 65,
+                    max_len: 64,
+                },
+                TruncatedField {
+                    path: "baseData.exceptions[1].typeName".into(),
+                    len: 1025,
+                    max_len: 1024,
+                },
+                TruncatedField {
+                    path: format!("baseData.properties.{} (key)", "k".repeat(151)),
+                    len: 151,
+                    max_len: 150,
+                },
+                TruncatedField {
+                    path: "baseData.properties.long".into(),
+                    len: 8193,
+                    max_len: 8192,
+                },
+            ]
+        );
+    }
+
+    /// Builds telemetry of every type with over-long strings, so the models truncate every limited
+    /// field, and checks that `validate` reports exactly these fields with the same limits.
+    #[test]
+    fn limits_match_exporter_truncation() {
+        use crate::models::{Data, MetricData};
+
+        const LONG: usize = 100_000;
+        fn long<T: From<String>>() -> T {
+            "x".repeat(LONG).into()
+        }
+        let frame = || StackFrame {
+            level: 0,
+            method: long(),
+            assembly: Some(long()),
+            file_name: Some(long()),
+            line: None,
+        };
+        let data = vec![
+            Data::Availability(AvailabilityData {
+                ver: 2,
+                id: long(),
+                name: long(),
+                duration: "00.00:00:00.000000".into(),
+                success: true,
+                run_location: Some(long()),
+                message: Some(long()),
+                properties: None,
+                measurements: None,
+            }),
+            Data::Event(EventData {
+                ver: 2,
+                name: long(),
+                properties: None,
+                measurements: None,
+            }),
+            Data::Exception(ExceptionData {
+                ver: 2,
+                exceptions: vec![ExceptionDetails {
+                    id: None,
+                    outer_id: None,
+                    type_name: long(),
+                    message: long(),
+                    has_full_stack: None,
+                    stack: Some(long()),
+                    parsed_stack: Some(vec![frame(), frame()]),
+                }],
+                severity_level: None,
+                problem_id: Some(long()),
+                properties: None,
+                measurements: None,
+            }),
+            Data::Message(MessageData {
+                ver: 2,
+                message: long(),
+                severity_level: None,
+                properties: None,
+            }),
+            Data::Metric(MetricData {
+                ver: 2,
+                metrics: vec![DataPoint {
+                    ns: Some(long()),
+                    name: long(),
+                    kind: None,
+                    value: 1.0,
+                }],
+                properties: None,
+            }),
+            Data::PageView(PageViewData {
+                ver: 2,
+                name: long(),
+                id: Some(long()),
+                url: Some(long()),
+                duration: None,
+                referrer_uri: Some(long()),
+                properties: None,
+                measurements: None,
+            }),
+            Data::PageViewPerf(PageViewPerfData {
+                ver: 2,
+                name: long(),
+                id: Some(long()),
+                url: Some(long()),
+                duration: None,
+                referrer_uri: Some(long()),
+                perf_total: None,
+                network_connect: None,
+                sent_request: None,
+                received_response: None,
+                dom_processing: None,
+                properties: None,
+                measurements: None,
+            }),
+            Data::RemoteDependency(RemoteDependencyData {
+                ver: 2,
+                name: long(),
+                id: Some(long()),
+                result_code: Some(long()),
+                duration: "00.00:00:00.000000".into(),
+                success: None,
+                data: Some(long()),
+                target: Some(long()),
+                type_: Some(long()),
+                properties: None,
+                measurements: None,
+            }),
+            Data::Request(RequestData {
+                ver: 2,
+                id: long(),
+                source: Some(long()),
+                name: Some(long()),
+                duration: "00.00:00:00.000000".into(),
+                response_code: long(),
+                success: true,
+                url: Some(long()),
+                properties: None,
+                measurements: None,
+            }),
+        ];
+
+        for data in data {
+            let envelope = Envelope {
+                name: "Microsoft.ApplicationInsights.Test",
+                time: long(),
+                sample_rate: None,
+                i_key: Some(long()),
+                tags: None,
+                data: Some(data),
+            };
+            let sent = serde_json::to_value(&envelope).unwrap();
+
+            // Every string the exporter shortened, with the length it was shortened to.
+            let mut expected = Vec::new();
+            truncated_strings(&sent, String::new(), LONG, &mut expected);
+            assert!(!expected.is_empty());
+
+            // Undo the truncation and check that `validate` finds the same fields and limits.
+            let mut original = sent.clone();
+            restore_strings(&mut original, LONG);
+            let mut reported: Vec<_> = validate(&original)
+                .into_iter()
+                .map(|field| (field.path, field.max_len))
+                .collect();
+            reported.sort();
+            expected.sort();
+            assert_eq!(reported, expected, "{}", sent["data"]["baseType"]);
+        }
+    }
+
+    fn truncated_strings(value: &Value, path: String, long: usize, out: &mut Vec<(String, usize)>) {
+        match value {
+            Value::String(value) if value.len() < long && value.bytes().all(|b| b == b'x') => {
+                out.push((path.replacen("data.baseData", "baseData", 1), value.len()));
+            }
+            Value::Array(items) => {
+                for (index, item) in items.iter().enumerate() {
+                    truncated_strings(item, format!("{path}[{index}]"), long, out);
+                }
+            }
+            Value::Object(fields) => {
+                for (key, item) in fields {
+                    let path = if path.is_empty() {
+                        key.clone()
+                    } else {
+                        format!("{path}.{key}")
+                    };
+                    truncated_strings(item, path, long, out);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn restore_strings(value: &mut Value, long: usize) {
+        match value {
+            Value::String(value) if value.bytes().all(|b| b == b'x') => *value = "x".repeat(long),
+            Value::Array(items) => items
+                .iter_mut()
+                .for_each(|item| restore_strings(item, long)),
+            Value::Object(fields) => fields
+                .values_mut()
+                .for_each(|item| restore_strings(item, long)),
+            _ => {}
+        }
+    }
+}
@@ -1,8 +1,9 @@
-use std::{borrow::Cow, collections::HashMap, convert::TryInto, str::FromStr};
+use std::{borrow::Cow, collections::HashMap, convert::TryInto, net::IpAddr, str::FromStr};
 
 pub(crate) const DEFAULT_BREEZE_ENDPOINT: &str = "https://dc.services.visualstudio.com";
 #[cfg(feature = "live-metrics")]
 pub(crate) const DEFAULT_LIVE_ENDPOINT: &str = "https://rt.services.visualstudio.com";
+pub(crate) const DEFAULT_AAD_SCOPE: &str = "https://monitor.azure.com/.default";
 const FIELDS_SEPARATOR: char = ';';
 const FIELD_KEY_VALUE_SEPARATOR: char = '=';
 
@@ -12,6 +13,9 @@ pub(crate) struct ConnectionString {
     #[cfg(feature = "live-metrics")]
     pub(crate) live_endpoint: http::Uri,
     pub(crate) instrumentation_key: String,
+    /// Scope of the Microsoft Entra ID token, if the connection string requires token
+    /// authentication (`Authorization=AAD`).
+    pub(crate) aad_scope: Option<String>,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -20,7 +24,7 @@ pub(crate) enum ParseError {
     InvalidFormat,
     #[error("missing instrumentation key")]
     MissingInstrumentationKey,
-    #[error("unsupported authorization; only \"ikey\" is supported")]
+    #[error("unsupported authorization; only \"ikey\" and \"aad\" are supported")]
     UnsupportedAuthorization,
     #[error("invalid endpoint: {0}")]
     InvalidEndpoint(http::uri::InvalidUri),
@@ -75,11 +79,18 @@ impl FromStr for ConnectionString {
             http::Uri::from_static(DEFAULT_LIVE_ENDPOINT)
         };
 
-        if let Some(authorization) = result.remove("authorization") {
-            if !authorization.eq_ignore_ascii_case("ikey") {
+        let aad_scope = match result.remove("authorization") {
+            Some(authorization) if authorization.eq_ignore_ascii_case("aad") => Some(
+                result
+                    .remove("aadaudience")
+                    .map(|audience| format!("{}/.default", audience.trim_end_matches('/')))
+                    .unwrap_or_else(|| DEFAULT_AAD_SCOPE.into()),
+            ),
+            Some(authorization) if !authorization.eq_ignore_ascii_case("ikey") => {
                 return Err(ParseError::UnsupportedAuthorization);
             }
-        }
+            _ => None,
+        };
         let instrumentation_key = result
             .remove("instrumentationkey")
             .ok_or(ParseError::MissingInstrumentationKey)?;
@@ -89,13 +100,19 @@ impl FromStr for ConnectionString {
             #[cfg(feature = "live-metrics")]
             live_endpoint,
             instrumentation_key,
+            aad_scope,
         })
     }
 }
 
 fn sanitize_url(url: String) -> Result<http::Uri, ParseError> {
     let mut new_url: Cow<str> = url.trim().into();
-    if !new_url.starts_with("https://") {
+    // Plain HTTP is only kept for the fake ingestion server of the `test-util` feature.
+    let keep_http = cfg!(feature = "test-util")
+        && new_url
+            .parse::<http::Uri>()
+            .is_ok_and(|uri| is_loopback(&uri));
+    if !new_url.starts_with("https://") && !keep_http {
         new_url = new_url.replace("http://", "https://").into();
     }
 
@@ -105,6 +122,18 @@ fn sanitize_url(url: String) -> Result<http::Uri, ParseError> {
         .map_err(ParseError::InvalidEndpoint)
 }
 
+pub(crate) fn is_loopback(uri: &http::Uri) -> bool {
+    match uri.host() {
+        Some("localhost") => true,
+        Some(host) => host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback()),
+        None => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -168,6 +197,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn loopback_endpoint() {
+        let result: ConnectionString =
+            "InstrumentationKey=instr_key;IngestionEndpoint=http://127.0.0.1:8080/"
+                .parse()
+                .unwrap();
+        let expected = if cfg!(feature = "test-util") {
+            "http://127.0.0.1:8080"
+        } else {
+            "https://127.0.0.1:8080"
+        };
+        assert_eq!(
+            http::Uri::try_from(expected).unwrap(),
+            result.ingestion_endpoint
+        );
+    }
+
+    #[test_case("InstrumentationKey=instr_key", None ; "no authorization")]
+    #[test_case("Authorization=ikey;InstrumentationKey=instr_key", None ; "ikey")]
+    #[test_case(
+        "Authorization=AAD;InstrumentationKey=instr_key",
+        Some(DEFAULT_AAD_SCOPE) ; "aad")]
+    #[test_case(
+        "Authorization=aad;InstrumentationKey=instr_key;AADAudience=https://monitor.azure.us/",
+        Some("https://monitor.azure.us/.default") ; "aad with audience")]
+    fn parse_authorization(
+        connection_string: &'static str,
+        expected_aad_scope: Option<&'static str>,
+    ) {
+        let result: ConnectionString = connection_string.parse().unwrap();
+        assert_eq!(expected_aad_scope, result.aad_scope.as_deref());
+    }
+
     #[test_case("Authorization=foo;InstrumentationKey=instr_key" ; "authorization != ikey")]
     #[test_case("InstrumentationKey=instr_key;NoValue" ; "field without value")]
     #[test_case("InstrumentationKey=instr_key;InvalidValue=foo=bar" ; "2 equals signs")]
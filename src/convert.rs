@@ -1,5 +1,5 @@
 #[cfg(any(feature = "trace", feature = "logs"))]
-use crate::models::Properties;
+use crate::models::{context_tag_keys::attrs::CONNECTION_STRING, LimitedLenString, Properties};
 #[cfg(feature = "trace")]
 use crate::models::{serialize_ms_links, SeverityLevel, MS_LINKS_KEY};
 use chrono::{DateTime, SecondsFormat, Utc};
@@ -40,6 +40,7 @@ pub(crate) fn time_to_string(time: SystemTime) -> String {
 pub(crate) fn attrs_to_properties<'a, A, T: 'a>(
     attributes: A,
     resource: Option<&Resource>,
+    max_value_len: usize,
     #[cfg(feature = "trace")] links: &[Link],
 ) -> Option<Properties>
 where
@@ -55,8 +56,13 @@ where
                 .iter()
                 .flat_map(|r| r.iter().map(|(k, v)| (k.as_str(), v as &dyn AttrValue))),
         )
-        .filter(|(k, _)| !k.starts_with("_MS."))
-        .map(|(k, v)| (k.into(), v.as_str().into()))
+        .filter(|(k, _)| !k.starts_with("_MS.") && *k != CONNECTION_STRING)
+        .map(|(k, v)| {
+            (
+                k.into(),
+                LimitedLenString::with_max_len(&v.as_str(), max_value_len),
+            )
+        })
         .collect();
 
     #[cfg(feature = "trace")]
@@ -83,6 +89,7 @@ where
 pub(crate) fn attrs_map_to_properties(
     attributes: HashMap<&str, &dyn AttrValue>,
     resource: Option<&Resource>,
+    max_value_len: usize,
 ) -> Option<Properties> {
     let properties: Properties = attributes
         .iter()
@@ -92,8 +99,13 @@ pub(crate) fn attrs_map_to_properties(
                 .iter()
                 .flat_map(|r| r.iter().map(|(k, v)| (k.as_str(), v as &dyn AttrValue))),
         )
-        .filter(|(k, _)| !k.starts_with("_MS."))
-        .map(|(k, v)| (k.into(), v.as_str().into()))
+        .filter(|(k, _)| !k.starts_with("_MS.") && *k != CONNECTION_STRING)
+        .map(|(k, v)| {
+            (
+                k.into(),
+                LimitedLenString::with_max_len(&v.as_str(), max_value_len),
+            )
+        })
         .collect();
 
     Some(properties).filter(|x| !x.is_empty())
@@ -126,7 +138,7 @@ pub(crate) fn value_to_severity_level(value: &dyn AttrValue) -> Option<SeverityL
 }
 
 #[cfg(any(feature = "trace", feature = "logs"))]
-pub(crate) struct AttrKeyValue<'a>(&'a str, &'a dyn AttrValue);
+pub(crate) struct AttrKeyValue<'a>(pub(crate) &'a str, pub(crate) &'a dyn AttrValue);
 
 #[cfg(any(feature = "trace", feature = "logs"))]
 impl<'a> From<&'a KeyValue> for AttrKeyValue<'a> {
@@ -144,12 +156,25 @@ impl<'a> From<&'a (Key, AnyValue)> for AttrKeyValue<'a> {
 
 pub(crate) trait AttrValue {
     fn as_str(&self) -> Cow<'_, str>;
+
+    /// The value as a number, if it is an integer or a float.
+    #[cfg(any(feature = "trace", feature = "logs"))]
+    fn as_f64(&self) -> Option<f64>;
 }
 
 impl AttrValue for Value {
     fn as_str(&self) -> Cow<'_, str> {
         self.as_str()
     }
+
+    #[cfg(any(feature = "trace", feature = "logs"))]
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::I64(v) => Some(*v as f64),
+            Value::F64(v) => Some(*v),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(feature = "logs")]
@@ -204,12 +229,20 @@ impl AttrValue for AnyValue {
             _ => format!("{:?}", self).into(),
         }
     }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            AnyValue::Int(v) => Some(*v as f64),
+            AnyValue::Double(v) => Some(*v),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::MS_LINKS_MAX_LEN;
+    use crate::models::{MAX_PROPERTY_VALUE_LEN, MS_LINKS_MAX_LEN};
     use opentelemetry::trace::SpanContext;
     use test_case::test_case;
 
@@ -224,17 +257,30 @@ mod tests {
         let resource = Resource::builder_empty()
             .with_attributes([KeyValue::new("c", "d"), KeyValue::new("_MS.c", "d")])
             .build();
-        let props = attrs_to_properties(attrs.iter(), Some(&resource), &[]).unwrap();
+        let props = attrs_to_properties(attrs.iter(), Some(&resource), MAX_PROPERTY_VALUE_LEN, &[])
+            .unwrap();
         assert_eq!(props.len(), 2);
         assert_eq!(props.get(&"a".into()).unwrap().as_ref(), "b");
         assert_eq!(props.get(&"c".into()).unwrap().as_ref(), "d");
     }
 
+    #[test]
+    fn attrs_to_properties_filters_connection_string() {
+        let attrs = [
+            KeyValue::new("a", "b"),
+            KeyValue::new(CONNECTION_STRING, "InstrumentationKey=123"),
+        ];
+        let props = attrs_to_properties(attrs.iter(), None, MAX_PROPERTY_VALUE_LEN, &[]).unwrap();
+        assert_eq!(props.len(), 1);
+        assert_eq!(props.get(&"a".into()).unwrap().as_ref(), "b");
+    }
+
     #[test]
     fn attrs_to_properties_encodes_links() {
         let attrs: Vec<KeyValue> = Vec::new();
         let links = vec![Link::new(SpanContext::empty_context(), Vec::new(), 0)];
-        let props = attrs_to_properties(attrs.iter(), None, &links).unwrap();
+        let props =
+            attrs_to_properties(attrs.iter(), None, MAX_PROPERTY_VALUE_LEN, &links).unwrap();
         assert_eq!(props.len(), 1);
         assert_eq!(
             props.get(&"_MS.links".into()).unwrap().as_ref(),
@@ -250,7 +296,8 @@ mod tests {
         for _ in 0..input_len {
             links.push(Link::new(SpanContext::empty_context(), Vec::new(), 0));
         }
-        let props = attrs_to_properties(attrs.iter(), None, &links).unwrap();
+        let props =
+            attrs_to_properties(attrs.iter(), None, MAX_PROPERTY_VALUE_LEN, &links).unwrap();
         assert_eq!(props.len(), 1);
         let encoded_links = props.get(&"_MS.links".into()).unwrap();
         let deserialized: serde_json::Value = serde_json::from_str(encoded_links.as_ref()).unwrap();
@@ -268,7 +315,8 @@ mod tests {
         let resource = Resource::builder_empty()
             .with_attributes([KeyValue::new("c", "d"), KeyValue::new("_MS.c", "d")])
             .build();
-        let props = attrs_map_to_properties(attrs_map, Some(&resource)).unwrap();
+        let props =
+            attrs_map_to_properties(attrs_map, Some(&resource), MAX_PROPERTY_VALUE_LEN).unwrap();
         assert_eq!(props.len(), 2);
         assert_eq!(props.get(&"a".into()), Some(&"b".into()));
         assert_eq!(props.get(&"c".into()).unwrap().as_ref(), "d");
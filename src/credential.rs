@@ -0,0 +1,207 @@
+use crate::{connection_string::is_loopback, Error};
+use async_trait::async_trait;
+use std::{
+    error::Error as StdError,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+/// Tokens are refreshed this long before they expire, so requests never use a token that expires
+/// while in flight.
+const REFRESH_BEFORE_EXPIRY: Duration = Duration::from_secs(5 * 60);
+
+/// A bearer token used to authenticate with Microsoft Entra ID.
+#[derive(Clone)]
+pub struct AccessToken {
+    /// The token, which is sent in the `Authorization: Bearer` header.
+    pub token: String,
+    /// Point in time when the token expires.
+    pub expires_on: SystemTime,
+}
+
+impl Debug for AccessToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccessToken")
+            .field("token", &"<redacted>")
+            .field("expires_on", &self.expires_on)
+            .finish()
+    }
+}
+
+/// Provides Microsoft Entra ID (formerly Azure Active Directory) tokens.
+///
+/// This is required for Application Insights resources with local authentication disabled, i.e.
+/// with connection strings containing `Authorization=AAD`. Implement this for the credential of
+/// your choice, e.g. by wrapping a credential from the `azure_identity` crate.
+///
+/// ```
+/// use opentelemetry_application_insights::{AccessToken, TokenCredential};
+/// use std::time::{Duration, SystemTime};
+///
+/// #[derive(Debug)]
+/// struct StaticCredential(String);
+///
+/// #[async_trait::async_trait]
+/// impl TokenCredential for StaticCredential {
+///     async fn get_token(
+///         &self,
+///         _scopes: &[&str],
+///     ) -> Result<AccessToken, Box<dyn std::error::Error + Send + Sync + 'static>> {
+///         Ok(AccessToken {
+///             token: self.0.clone(),
+///             expires_on: SystemTime::now() + Duration::from_secs(3600),
+///         })
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait TokenCredential: Debug + Send + Sync {
+    /// Get a token for the given scopes. The exporter requests the
+    /// `https://monitor.azure.com/.default` scope, unless the connection string specifies a
+    /// different `AADAudience`.
+    ///
+    /// Tokens are cached by the exporter and only requested again shortly before they expire.
+    async fn get_token(
+        &self,
+        scopes: &[&str],
+    ) -> Result<AccessToken, Box<dyn StdError + Send + Sync + 'static>>;
+}
+
+#[derive(Debug)]
+pub(crate) struct TokenCache {
+    credential: Arc<dyn TokenCredential>,
+    scope: String,
+    cached: Mutex<Option<AccessToken>>,
+}
+
+impl TokenCache {
+    pub(crate) fn new(credential: Arc<dyn TokenCredential>, scope: String) -> Self {
+        Self {
+            credential,
+            scope,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Creates an empty cache, which gets tokens for another scope from the same credential.
+    pub(crate) fn for_scope(&self, scope: &str) -> Self {
+        Self::new(self.credential.clone(), scope.into())
+    }
+
+    /// Returns a cached token or requests a new one if there is no token or it is about to expire.
+    pub(crate) async fn get_token(&self) -> Result<String, Error> {
+        if let Some(ref cached) = *self.cached.lock().unwrap() {
+            if !needs_refresh(cached, SystemTime::now()) {
+                return Ok(cached.token.clone());
+            }
+        }
+
+        let token = self
+            .credential
+            .get_token(&[&self.scope])
+            .await
+            .map_err(Error::TokenCredential)?;
+        let result = token.token.clone();
+        *self.cached.lock().unwrap() = Some(token);
+        Ok(result)
+    }
+
+    /// Forget the cached token, e.g. because the server rejected it.
+    pub(crate) fn invalidate(&self) {
+        *self.cached.lock().unwrap() = None;
+    }
+}
+
+/// Checks that a token may be sent to the given endpoint. Tokens are only sent over HTTPS, except
+/// to the fake ingestion server of the `test-util` feature.
+pub(crate) fn check_endpoint(endpoint: &http::Uri) -> Result<(), Error> {
+    let secure = endpoint.scheme() == Some(&http::uri::Scheme::HTTPS)
+        || (cfg!(feature = "test-util") && is_loopback(endpoint));
+    if secure {
+        Ok(())
+    } else {
+        Err(Error::TokenCredential(
+            format!("refusing to send a Microsoft Entra ID token to {endpoint} without HTTPS")
+                .into(),
+        ))
+    }
+}
+
+/// Error for connection strings with `Authorization=AAD` when no token credential is configured.
+pub(crate) fn missing_credential_error() -> Error {
+    Error::TokenCredential(
+        "connection string requires Microsoft Entra ID authentication, but no token credential is configured".into(),
+    )
+}
+
+fn needs_refresh(token: &AccessToken, now: SystemTime) -> bool {
+    token
+        .expires_on
+        .duration_since(now)
+        .map(|remaining| remaining < REFRESH_BEFORE_EXPIRY)
+        .unwrap_or(true)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Debug)]
+    pub(crate) struct TestCredential {
+        pub(crate) calls: AtomicUsize,
+        pub(crate) valid_for: Duration,
+    }
+
+    impl TestCredential {
+        pub(crate) fn new(valid_for: Duration) -> Self {
+            Self {
+                calls: AtomicUsize::new(0),
+                valid_for,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl TokenCredential for TestCredential {
+        async fn get_token(
+            &self,
+            scopes: &[&str],
+        ) -> Result<AccessToken, Box<dyn StdError + Send + Sync + 'static>> {
+            assert_eq!(scopes, &["https://monitor.azure.com/.default"]);
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(AccessToken {
+                token: format!("token{}", call),
+                expires_on: SystemTime::now() + self.valid_for,
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn caches_token() {
+        let credential = Arc::new(TestCredential::new(Duration::from_secs(3600)));
+        let cache = TokenCache::new(
+            credential.clone(),
+            "https://monitor.azure.com/.default".into(),
+        );
+        assert_eq!(cache.get_token().await.unwrap(), "token0");
+        assert_eq!(cache.get_token().await.unwrap(), "token0");
+        assert_eq!(credential.calls.load(Ordering::SeqCst), 1);
+
+        cache.invalidate();
+        assert_eq!(cache.get_token().await.unwrap(), "token1");
+    }
+
+    #[tokio::test]
+    async fn refreshes_token_before_expiry() {
+        let credential = Arc::new(TestCredential::new(Duration::from_secs(60)));
+        let cache = TokenCache::new(
+            credential.clone(),
+            "https://monitor.azure.com/.default".into(),
+        );
+        assert_eq!(cache.get_token().await.unwrap(), "token0");
+        assert_eq!(cache.get_token().await.unwrap(), "token1");
+        assert_eq!(credential.calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,19 @@
+use std::sync::{Arc, Mutex};
+
+pub(crate) type DeadLetterNotify = Arc<Mutex<dyn FnMut(&[DeadLetter]) + Send + 'static>>;
+
+/// A telemetry item, which Application Insights rejected with a status code that is not retried.
+///
+/// Passed to the function configured with
+/// [`Exporter::with_dead_letter`](crate::Exporter::with_dead_letter). Rejected items are usually
+/// caused by invalid data, e.g. missing required fields or values that are out of range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DeadLetter {
+    /// The rejected telemetry item (envelope) serialized as JSON.
+    pub item: String,
+    /// The HTTP status code for this item.
+    pub status_code: u16,
+    /// The error message returned by Application Insights for this item, if any.
+    pub message: Option<String>,
+}
@@ -0,0 +1,267 @@
+use crate::{
+    append_v2_track,
+    connection_string::ConnectionString,
+    credential::TokenCache,
+    models::Envelope,
+    stats::Stats,
+    uploader::{Endpoint, Throttle},
+};
+#[cfg(feature = "logs")]
+use opentelemetry_sdk::logs::SdkLogRecord;
+#[cfg(feature = "trace")]
+use opentelemetry_sdk::trace::SpanData;
+#[cfg(any(feature = "trace", feature = "logs"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+#[cfg(feature = "trace")]
+pub(crate) type SpanRouter = Arc<dyn Fn(&SpanData) -> Option<String> + Send + Sync + 'static>;
+#[cfg(feature = "logs")]
+pub(crate) type LogRouter = Arc<dyn Fn(&SdkLogRecord) -> Option<String> + Send + Sync + 'static>;
+
+/// An Application Insights resource other than the one of the exporter's connection string. It
+/// either receives the same telemetry (additional connection strings) or the telemetry routed to
+/// it.
+///
+/// Each destination has its own endpoint, throttling state and stats, so a slow or throttled
+/// resource doesn't affect the others. Destinations with `Authorization=AAD` also cache their own
+/// tokens for their scope.
+#[derive(Debug)]
+pub(crate) struct Destination {
+    pub(crate) instrumentation_key: String,
+    pub(crate) aad_scope: Option<String>,
+    pub(crate) track_endpoint: Endpoint,
+    pub(crate) throttle: Throttle,
+    pub(crate) stats: Stats,
+    token_cache: OnceLock<TokenCache>,
+}
+
+impl Destination {
+    pub(crate) fn new(connection_string: ConnectionString) -> Self {
+        Self {
+            track_endpoint: Endpoint::new(append_v2_track(&connection_string.ingestion_endpoint)),
+            instrumentation_key: connection_string.instrumentation_key,
+            aad_scope: connection_string.aad_scope,
+            throttle: Throttle::default(),
+            stats: Stats::default(),
+            token_cache: OnceLock::new(),
+        }
+    }
+
+    /// Token cache for the scope of this destination, which gets tokens from the exporter's
+    /// credential. `None` if the destination doesn't use Microsoft Entra ID authentication.
+    pub(crate) fn credential(&self, credential: Option<&TokenCache>) -> Option<&TokenCache> {
+        let scope = self.aad_scope.as_deref()?;
+        let credential = credential?;
+        Some(self.token_cache.get_or_init(|| credential.for_scope(scope)))
+    }
+}
+
+/// Maximum number of connection strings, which telemetry can be routed to. Telemetry routed to
+/// further connection strings is dropped, so a router returning many different connection strings
+/// can't make the exporter grow without bounds.
+#[cfg(any(feature = "trace", feature = "logs"))]
+const MAX_ROUTES: usize = 100;
+
+/// Where a telemetry item is sent.
+#[cfg(any(feature = "trace", feature = "logs"))]
+pub(crate) enum Route {
+    /// The exporter's own (and additional) connection strings.
+    Default,
+    /// The resource of the connection string the item was routed to.
+    Destination(Arc<Destination>),
+    /// The item was routed to an invalid connection string or to more than [`MAX_ROUTES`]
+    /// connection strings and is dropped.
+    Dropped,
+}
+
+/// Destinations chosen for individual telemetry items, cached by connection string.
+#[derive(Debug, Default)]
+pub(crate) struct Routes {
+    /// `None` for invalid connection strings, so the error is only reported once.
+    destinations: Mutex<HashMap<String, Option<Arc<Destination>>>>,
+    #[cfg(any(feature = "trace", feature = "logs"))]
+    full: AtomicBool,
+}
+
+impl Routes {
+    /// Returns the route for a connection string.
+    #[cfg(any(feature = "trace", feature = "logs"))]
+    pub(crate) fn get(&self, connection_string: &str) -> Route {
+        let mut destinations = self.destinations.lock().unwrap();
+        if let Some(destination) = destinations.get(connection_string) {
+            return destination
+                .clone()
+                .map_or(Route::Dropped, Route::Destination);
+        }
+
+        if destinations.len() >= MAX_ROUTES {
+            if !self.full.swap(true, Ordering::Relaxed) {
+                opentelemetry::otel_warn!(name: "ApplicationInsights.Routing.TooManyConnectionStrings", max = MAX_ROUTES as i64);
+            }
+            return Route::Dropped;
+        }
+
+        let destination = match connection_string.parse::<ConnectionString>() {
+            Ok(parsed) => Some(Arc::new(Destination::new(parsed))),
+            Err(err) => {
+                opentelemetry::otel_warn!(name: "ApplicationInsights.Routing.InvalidConnectionString", error = err.to_string().as_str());
+                None
+            }
+        };
+        destinations.insert(connection_string.into(), destination.clone());
+        destination.map_or(Route::Dropped, Route::Destination)
+    }
+
+    /// All destinations, which telemetry was routed to so far.
+    pub(crate) fn all(&self) -> Vec<Arc<Destination>> {
+        self.destinations
+            .lock()
+            .unwrap()
+            .values()
+            .flatten()
+            .cloned()
+            .collect()
+    }
+}
+
+/// Envelopes of an export grouped by destination.
+#[derive(Default)]
+pub(crate) struct RoutedEnvelopes {
+    /// Envelopes for the exporter's own (and additional) connection strings.
+    pub(crate) default: Vec<Envelope>,
+    /// Envelopes routed to other destinations.
+    pub(crate) routed: Vec<(Arc<Destination>, Vec<Envelope>)>,
+}
+
+impl RoutedEnvelopes {
+    /// Adds the envelopes of a telemetry item for its route. Returns the number of envelopes,
+    /// which were dropped because of the route.
+    #[cfg(any(feature = "trace", feature = "logs"))]
+    pub(crate) fn push(
+        &mut self,
+        route: Route,
+        envelopes: impl IntoIterator<Item = Envelope>,
+    ) -> usize {
+        let destination = match route {
+            Route::Default => {
+                self.default.extend(envelopes);
+                return 0;
+            }
+            Route::Destination(destination) => destination,
+            Route::Dropped => return envelopes.into_iter().count(),
+        };
+        let envelopes = envelopes.into_iter().map(|mut envelope| {
+            envelope.i_key = Some(destination.instrumentation_key.clone().into());
+            envelope
+        });
+        match self
+            .routed
+            .iter_mut()
+            .find(|(routed, _)| Arc::ptr_eq(routed, &destination))
+        {
+            Some((_, routed)) => routed.extend(envelopes),
+            None => {
+                let envelopes = envelopes.collect();
+                self.routed.push((destination, envelopes));
+            }
+        }
+        0
+    }
+
+    /// Keeps only the envelopes for which `keep` returns true. Destinations without envelopes are
+    /// removed.
+    pub(crate) fn retain_mut(&mut self, mut keep: impl FnMut(&mut Envelope) -> bool) {
+        self.default.retain_mut(&mut keep);
+        for (_, envelopes) in &mut self.routed {
+            envelopes.retain_mut(&mut keep);
+        }
+        self.routed.retain(|(_, envelopes)| !envelopes.is_empty());
+    }
+}
+
+impl From<Vec<Envelope>> for RoutedEnvelopes {
+    fn from(envelopes: Vec<Envelope>) -> Self {
+        Self {
+            default: envelopes,
+            routed: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONNECTION_STRING: &str = "InstrumentationKey=5c3a7f3e-2d4b-4e3f-9c1a-8b7d6e5f4a3b";
+
+    fn envelope() -> Envelope {
+        Envelope {
+            name: "Test",
+            time: "2025-01-01T00:00:00Z".into(),
+            sample_rate: None,
+            i_key: Some("default".into()),
+            tags: None,
+            data: None,
+        }
+    }
+
+    #[test]
+    fn routes_are_cached() {
+        let routes = Routes::default();
+        let Route::Destination(destination) = routes.get(CONNECTION_STRING) else {
+            panic!("expected destination");
+        };
+        assert!(matches!(
+            routes.get(CONNECTION_STRING),
+            Route::Destination(cached) if Arc::ptr_eq(&destination, &cached)
+        ));
+        assert!(matches!(routes.get("invalid"), Route::Dropped));
+        assert!(matches!(routes.get("invalid"), Route::Dropped));
+        assert_eq!(routes.all().len(), 1);
+    }
+
+    #[test]
+    fn routes_are_bounded() {
+        let routes = Routes::default();
+        for i in 0..MAX_ROUTES {
+            routes.get(&format!("InstrumentationKey={i}"));
+        }
+        assert!(matches!(
+            routes.get("InstrumentationKey=0"),
+            Route::Destination(_)
+        ));
+        assert!(matches!(routes.get(CONNECTION_STRING), Route::Dropped));
+        assert_eq!(routes.all().len(), MAX_ROUTES);
+    }
+
+    #[test]
+    fn group_and_rekey_envelopes() {
+        let routes = Routes::default();
+        let destination = || routes.get(CONNECTION_STRING);
+        let mut envelopes = RoutedEnvelopes::default();
+        assert_eq!(envelopes.push(Route::Default, [envelope()]), 0);
+        assert_eq!(envelopes.push(destination(), [envelope(), envelope()]), 0);
+        assert_eq!(envelopes.push(destination(), [envelope()]), 0);
+        assert_eq!(
+            envelopes.push(routes.get("invalid"), [envelope(), envelope()]),
+            2
+        );
+
+        assert_eq!(envelopes.default.len(), 1);
+        assert_eq!(
+            envelopes.default[0].i_key.as_ref().unwrap().as_ref(),
+            "default"
+        );
+        assert_eq!(envelopes.routed.len(), 1);
+        let (_, routed) = &envelopes.routed[0];
+        assert_eq!(routed.len(), 3);
+        assert!(routed
+            .iter()
+            .all(|envelope| envelope.i_key.as_ref().unwrap().as_ref()
+                == "5c3a7f3e-2d4b-4e3f-9c1a-8b7d6e5f4a3b"));
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{
+    convert::AttrValue,
+    models::{context_tag_keys::attrs::EXCEPTION_PROBLEM_ID, ExceptionDetails, LimitedLenString},
+};
+use opentelemetry::KeyValue;
+use opentelemetry_semantic_conventions as semcov;
+use std::{collections::HashMap, error::Error};
+
+/// Prefix of the attributes describing the causes of an exception, e.g.
+/// `exception.cause.0.message`.
+const EXCEPTION_CAUSE_PREFIX: &str = "exception.cause";
+
+/// Crates of the standard library and of capturing backtraces, which are skipped when computing
+/// the problem id.
+const RUNTIME_CRATES: &[&str] = &["alloc", "backtrace", "core", "std"];
+
+/// Attributes describing an error and its chain of [sources](Error::source) for an exception span
+/// event or log record.
+///
+/// The type of the error is its Rust type name if the concrete type is known. Trait objects like
+/// `&dyn Error` or the content of a `Box<dyn Error + Send + Sync>` have no `exception.type`
+/// attribute; add one if the type is known otherwise. The sources are recorded as indexed
+/// `exception.cause.<N>.message` attributes, which are sent as inner exceptions.
+///
+/// ```
+/// use opentelemetry::{
+///     trace::{Span as _, Tracer as _},
+///     KeyValue,
+/// };
+/// use opentelemetry_application_insights::exception_attributes;
+/// use std::error::Error;
+///
+/// fn load_config() -> Result<String, Box<dyn Error + Send + Sync>> {
+///     Ok(std::fs::read_to_string("config.toml")?)
+/// }
+///
+/// # let tracer = opentelemetry::global::tracer("example");
+/// let mut span = tracer.start("read config");
+/// if let Err(error) = std::fs::read_to_string("config.toml") {
+///     span.add_event("exception", exception_attributes(&error));
+/// }
+/// if let Err(error) = load_config() {
+///     let mut attributes = exception_attributes(&*error);
+///     attributes.push(KeyValue::new("exception.type", "ConfigError"));
+///     span.add_event("exception", attributes);
+/// }
+/// ```
+#[cfg_attr(docsrs, doc(cfg(any(feature = "trace", feature = "logs"))))]
+pub fn exception_attributes<E: Error + ?Sized + 'static>(error: &E) -> Vec<KeyValue> {
+    let mut attrs = Vec::new();
+    let type_name = std::any::type_name::<E>();
+    if !type_name.starts_with("dyn ") {
+        attrs.push(KeyValue::new(semcov::trace::EXCEPTION_TYPE, type_name));
+    }
+    attrs.push(KeyValue::new(
+        semcov::trace::EXCEPTION_MESSAGE,
+        error.to_string(),
+    ));
+    let causes = std::iter::successors(error.source(), |&cause| cause.source());
+    for (i, cause) in causes.enumerate() {
+        attrs.push(KeyValue::new(
+            format!("{EXCEPTION_CAUSE_PREFIX}.{i}.message"),
+            cause.to_string(),
+        ));
+    }
+    attrs
+}
+
+/// Removes the exception attributes and converts them into the exception and its causes, linked
+/// by `id` and `outerId`.
+pub(crate) fn exceptions_from_attrs(
+    attrs: &mut HashMap<&str, &dyn AttrValue>,
+    default_type_name: &str,
+    default_message: &str,
+) -> Vec<ExceptionDetails> {
+    let mut exceptions = vec![exception_from_attrs(
+        attrs,
+        semcov::trace::EXCEPTION_TYPE,
+        semcov::trace::EXCEPTION_MESSAGE,
+        semcov::trace::EXCEPTION_STACKTRACE,
+        default_type_name,
+        default_message,
+    )];
+    loop {
+        let prefix = format!("{EXCEPTION_CAUSE_PREFIX}.{}", exceptions.len() - 1);
+        let type_key = format!("{prefix}.type");
+        let message_key = format!("{prefix}.message");
+        if !attrs.contains_key(type_key.as_str()) && !attrs.contains_key(message_key.as_str()) {
+            break;
+        }
+        exceptions.push(exception_from_attrs(
+            attrs,
+            &type_key,
+            &message_key,
+            &format!("{prefix}.stacktrace"),
+            default_type_name,
+            default_message,
+        ));
+    }
+
+    if exceptions.len() > 1 {
+        // Ids start at 1, because an `outerId` of 0 means that there is no outer exception.
+        for (i, exception) in exceptions.iter_mut().enumerate() {
+            exception.id = Some(i as i32 + 1);
+            exception.outer_id = Some(i as i32).filter(|&id| id > 0);
+        }
+    }
+    exceptions
+}
+
+/// Removes the problem id attribute or computes the problem id from the type of the outermost
+/// exception and the first function of its call stack outside of the standard library.
+pub(crate) fn problem_id(
+    attrs: &mut HashMap<&str, &dyn AttrValue>,
+    exceptions: &[ExceptionDetails],
+) -> Option<LimitedLenString<1024>> {
+    if let Some(problem_id) = attrs.remove(EXCEPTION_PROBLEM_ID) {
+        return Some(problem_id.into());
+    }
+    computed_problem_id(exceptions.first()?)
+}
+
+pub(crate) fn computed_problem_id(exception: &ExceptionDetails) -> Option<LimitedLenString<1024>> {
+    let frame = exception.parsed_stack.as_ref()?.iter().find(|frame| {
+        frame
+            .assembly
+            .as_ref()
+            .is_some_and(|assembly| !RUNTIME_CRATES.contains(&assembly.as_ref()))
+    })?;
+    Some(
+        format!(
+            "{} at {}",
+            exception.type_name.as_ref(),
+            frame.method.as_ref()
+        )
+        .into(),
+    )
+}
+
+fn exception_from_attrs(
+    attrs: &mut HashMap<&str, &dyn AttrValue>,
+    type_key: &str,
+    message_key: &str,
+    stack_key: &str,
+    default_type_name: &str,
+    default_message: &str,
+) -> ExceptionDetails {
+    ExceptionDetails::new(
+        attrs
+            .remove(type_key)
+            .map(Into::into)
+            .unwrap_or_else(|| default_type_name.into()),
+        attrs
+            .remove(message_key)
+            .map(Into::into)
+            .unwrap_or_else(|| default_message.into()),
+        attrs
+            .remove(stack_key)
+            .map(|stack| stack.as_str())
+            .as_deref(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::attrs_to_map;
+    use std::{fmt, io};
+
+    #[derive(Debug)]
+    struct ConfigError(io::Error);
+
+    impl fmt::Display for ConfigError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("cannot read config")
+        }
+    }
+
+    impl Error for ConfigError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            Some(&self.0)
+        }
+    }
+
+    #[test]
+    fn exception_chain() {
+        let error = ConfigError(io::Error::new(io::ErrorKind::NotFound, "no such file"));
+        let attrs = exception_attributes(&error);
+        let mut attrs_map = attrs_to_map(attrs.iter());
+        attrs_map.insert("other", &opentelemetry::Value::Bool(true) as &dyn AttrValue);
+
+        let exceptions = exceptions_from_attrs(&mut attrs_map, "<no type>", "<no message>");
+        assert_eq!(attrs_map.len(), 1);
+        assert_eq!(exceptions.len(), 2);
+        assert_eq!(exceptions[0].id, Some(1));
+        assert_eq!(exceptions[0].outer_id, None);
+        assert!(exceptions[0].type_name.as_ref().ends_with("::ConfigError"));
+        assert_eq!(exceptions[0].message.as_ref(), "cannot read config");
+        assert_eq!(exceptions[1].id, Some(2));
+        assert_eq!(exceptions[1].outer_id, Some(1));
+        assert_eq!(exceptions[1].type_name.as_ref(), "<no type>");
+        assert_eq!(exceptions[1].message.as_ref(), "no such file");
+    }
+
+    #[test]
+    fn problem_ids() {
+        let stack =
+            "   0: std::backtrace::Backtrace::capture\n   1: app::checkout::pay\n   2: app::main\n";
+        let attrs = [
+            KeyValue::new(semcov::trace::EXCEPTION_TYPE, "PaymentError"),
+            KeyValue::new(semcov::trace::EXCEPTION_STACKTRACE, stack),
+        ];
+        let mut attrs_map = attrs_to_map(attrs.iter());
+        let exceptions = exceptions_from_attrs(&mut attrs_map, "", "");
+        assert_eq!(
+            problem_id(&mut attrs_map, &exceptions).unwrap().as_ref(),
+            "PaymentError at app::checkout::pay"
+        );
+
+        let attrs = [KeyValue::new(EXCEPTION_PROBLEM_ID, "payment")];
+        let mut attrs_map = attrs_to_map(attrs.iter());
+        assert_eq!(
+            problem_id(&mut attrs_map, &exceptions).unwrap().as_ref(),
+            "payment"
+        );
+        assert!(attrs_map.is_empty());
+
+        let attrs = [KeyValue::new(
+            semcov::trace::EXCEPTION_STACKTRACE,
+            "at checkout",
+        )];
+        let mut attrs_map = attrs_to_map(attrs.iter());
+        let exceptions = exceptions_from_attrs(&mut attrs_map, "", "");
+        assert!(problem_id(&mut attrs_map, &exceptions).is_none());
+    }
+
+    #[test]
+    fn single_exception() {
+        let attrs = [KeyValue::new(semcov::trace::EXCEPTION_MESSAGE, "failed")];
+        let mut attrs_map = attrs_to_map(attrs.iter());
+        let exceptions = exceptions_from_attrs(&mut attrs_map, "", "");
+        assert_eq!(exceptions.len(), 1);
+        assert_eq!(exceptions[0].id, None);
+        assert_eq!(exceptions[0].outer_id, None);
+        assert_eq!(exceptions[0].type_name.as_ref(), "");
+    }
+
+    #[test]
+    fn trait_object_has_no_type() {
+        let error: Box<dyn Error + Send + Sync> = Box::new(ConfigError(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no such file",
+        )));
+        let attrs = exception_attributes(&*error);
+        let mut attrs_map = attrs_to_map(attrs.iter());
+        let exceptions = exceptions_from_attrs(&mut attrs_map, "<no type>", "<no message>");
+        assert_eq!(exceptions.len(), 2);
+        assert_eq!(exceptions[0].type_name.as_ref(), "<no type>");
+        assert_eq!(exceptions[0].message.as_ref(), "cannot read config");
+
+        let error: &(dyn Error + 'static) = &io::Error::other("failed");
+        let attrs = exception_attributes(error);
+        assert_eq!(attrs.len(), 1);
+    }
+}
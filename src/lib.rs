@@ -164,10 +164,10 @@ async fn main() {
 //! Map async/sync clients with the appropriate builder methods:
 //!
 //! - Sync clients with `{SdkTracerProvider,SdkLoggerProvider}.with_batch_exporter`/`PeriodicReader::builder`. If you're already in an
-//! async context when creating the client, you might need to create it using
-//! `std::thread::spawn(reqwest::blocking::Client::new).join().unwrap()`.
+//!   async context when creating the client, you might need to create it using
+//!   `std::thread::spawn(reqwest::blocking::Client::new).join().unwrap()`.
 //! - Async clients with the corresponding experimental async APIs. _Or_ with the pipeline API and
-//! `build_batch`/`install_batch`.
+//!   `build_batch`/`install_batch`.
 //!
 //! # Attribute mapping
 //!
@@ -249,10 +249,25 @@ async fn main() {
 //! | `network.peer.address`                                                     | Request Source                                           |
 //! | `http.response.status_code`                                                | Request Response code                                    |
 //!
-//! All other attributes are directly converted to custom properties.
+//! All other attributes are directly converted to custom properties. Use
+//! [`Exporter::with_measurement_attributes`] to send numeric attributes as custom measurements
+//! instead.
 //!
 //! For Requests the attributes `http.request.method` and `http.route` override the Name.
 //!
+//! Spans with an `ai.availability.run_location` attribute are converted into [Availability]
+//! telemetry instead, regardless of their SpanKind:
+//!
+//! | OpenTelemetry attribute key    | Application Insights field                  |
+//! | ------------------------------ | ------------------------------------------- |
+//! | `ai.availability.run_location` | Availability Run location                   |
+//! | `ai.availability.success`      | Availability Success (default: status)      |
+//! | `ai.availability.message`      | Availability Message (default: status)      |
+//! | `ai.availability.id`           | Availability Id (default: span id)          |
+//! | `ai.availability.duration`     | Availability Duration in ms (default: span) |
+//!
+//! [Availability]: https://learn.microsoft.com/en-us/azure/azure-monitor/app/availability-overview
+//!
 //! ### Deprecated attributes
 //!
 //! The following deprecated attributes also work:
@@ -288,11 +303,28 @@ async fn main() {
 //! Events are converted into [Exception] telemetry if the event name equals `"exception"` (see
 //! OpenTelemetry semantic conventions for [exceptions]) with the following mapping:
 //!
-//! | OpenTelemetry attribute key | Application Insights field |
-//! | --------------------------- | -------------------------- |
-//! | `exception.type`            | Exception type             |
-//! | `exception.message`         | Exception message          |
-//! | `exception.stacktrace`      | Exception call stack       |
+//! | OpenTelemetry attribute key  | Application Insights field             |
+//! | ---------------------------- | -------------------------------------- |
+//! | `exception.type`             | Exception type                         |
+//! | `exception.message`          | Exception message                      |
+//! | `exception.stacktrace`       | Exception call stack                   |
+//! | `level` ([`tracing::Level`]) | Severity level                         |
+//! | `exception.escaped`          | Severity level (if `level` is not set) |
+//! | `ai.exception.problemId`     | Problem id                             |
+//!
+//! Escaped exceptions have the severity level critical, other exceptions with an
+//! `exception.escaped` attribute have the severity level error. The problem id, which groups
+//! related exceptions, defaults to the exception type and the first function of the call stack
+//! outside of the standard library.
+//!
+//! Call stacks in the format of [`std::backtrace::Backtrace`] are also sent as parsed stack
+//! frames with the method, crate, file name and line of each frame. Large stacks are trimmed from
+//! the middle.
+//!
+//! Indexed `exception.cause.<N>.type`, `exception.cause.<N>.message` and
+//! `exception.cause.<N>.stacktrace` attributes are sent as inner exceptions of the exception, with
+//! `N` starting at 0 for the direct cause. Use [`exception_attributes`] to create the attributes
+//! for an error and its sources.
 //!
 //! Events are converted into [Event] telemetry if the event name equals `"ai.custom"` with the
 //! following mapping:
@@ -301,6 +333,23 @@ async fn main() {
 //! | --------------------------- | -------------------------- |
 //! | `ai.customEvent.name`       | Event name                 |
 //!
+//! Events with an `ai.availability.run_location` attribute are converted into [Availability]
+//! telemetry with the event name as the test name and the same attribute mapping as spans. The id
+//! defaults to a new random id for every event, the duration to zero and success to `true`.
+//!
+//! Events are converted into [Page view] telemetry if the event name equals `"ai.pageView"` with
+//! the following mapping:
+//!
+//! | OpenTelemetry attribute key | Application Insights field                  |
+//! | --------------------------- | ------------------------------------------- |
+//! | `ai.pageView.name`          | Page view name (default: span name)         |
+//! | `ai.pageView.url`           | Page view URL (default: span `url.full`)    |
+//! | `ai.pageView.duration`      | Page view duration in ms (default: span)    |
+//! | `ai.pageView.referrerUri`   | Page view referrer URI                      |
+//!
+//! Page views share the operation id of the span. Context tag attributes on the event, like
+//! `ai.session.id` or `ai.user.id`, override the tags of the span.
+//!
 //! All other events are converted into [Trace] telemetry with the following mapping:
 //!
 //! | OpenTelemetry attribute key  | Application Insights field |
@@ -313,6 +362,7 @@ async fn main() {
 //! [Exception]: https://learn.microsoft.com/en-us/azure/azure-monitor/app/data-model-exception-telemetry
 //! [Event]: https://learn.microsoft.com/en-us/azure/azure-monitor/app/data-model-event-telemetry
 //! [Trace]: https://learn.microsoft.com/en-us/azure/azure-monitor/app/data-model-trace-telemetry
+//! [Page view]: https://learn.microsoft.com/en-us/azure/azure-monitor/app/data-model-complete#pageview
 //! [`tracing::Level`]: https://docs.rs/tracing/0.1.37/tracing/struct.Level.html
 //!
 //! ## Logs
@@ -343,19 +393,45 @@ async fn main() {
 #![cfg_attr(test, deny(warnings))]
 #![cfg_attr(test, allow(deprecated))]
 
+#[doc(hidden)]
+pub mod bench;
+#[cfg(feature = "cli")]
+#[doc(hidden)]
+pub mod cli_support;
 mod connection_string;
 mod convert;
+mod credential;
+mod dead_letter;
+mod destination;
+#[cfg(any(feature = "trace", feature = "logs"))]
+mod exception;
 #[cfg(feature = "logs")]
 mod logs;
+#[cfg(any(feature = "trace", feature = "logs"))]
+mod measurement;
 #[cfg(feature = "metrics")]
 mod metrics;
 mod models;
+mod overflow;
+mod payload;
+mod processor;
 #[cfg(feature = "live-metrics")]
 mod quick_pulse;
 #[cfg(doctest)]
 mod readme_test;
+mod retry;
+mod sink;
+mod stats;
+#[cfg(feature = "statsbeat")]
+mod statsbeat;
+mod storage;
 mod tags;
 #[cfg(feature = "trace")]
+mod telemetry_client;
+#[cfg(feature = "test-util")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub mod test_util;
+#[cfg(feature = "trace")]
 mod trace;
 mod uploader;
 #[cfg(feature = "live-metrics")]
@@ -363,15 +439,49 @@ mod uploader_quick_pulse;
 
 #[cfg(feature = "live-metrics")]
 use connection_string::DEFAULT_LIVE_ENDPOINT;
-use connection_string::{ConnectionString, DEFAULT_BREEZE_ENDPOINT};
+use connection_string::{ConnectionString, DEFAULT_AAD_SCOPE, DEFAULT_BREEZE_ENDPOINT};
+use credential::TokenCache;
+pub use credential::{AccessToken, TokenCredential};
+pub use dead_letter::DeadLetter;
+use dead_letter::DeadLetterNotify;
+#[cfg(feature = "logs")]
+use destination::LogRouter;
+#[cfg(feature = "trace")]
+use destination::SpanRouter;
+use destination::{Destination, RoutedEnvelopes, Routes};
+#[cfg(any(feature = "trace", feature = "logs"))]
+pub use exception::exception_attributes;
+use futures_util::future::join_all;
+#[cfg(any(feature = "trace", feature = "logs"))]
+pub use measurement::MeasurementAttributes;
 pub use models::context_tag_keys::attrs;
+use models::{
+    context_tag_keys::{Tags, TAG_KEY_LOOKUP},
+    Envelope, Properties,
+};
 pub use opentelemetry_http::HttpClient;
 use opentelemetry_sdk::error::OTelSdkError;
 use opentelemetry_sdk::ExportError;
 #[cfg(any(feature = "trace", feature = "logs"))]
 use opentelemetry_sdk::Resource;
+pub use overflow::OverflowPolicy;
+use payload::BufferPool;
+pub use payload::Compression;
+use processor::Processors;
+pub use processor::{
+    Decision, ExceptionInfo, SeverityLevel, TelemetryItem, TelemetryKind, TelemetryProcessor,
+};
 #[cfg(feature = "live-metrics")]
 pub use quick_pulse::LiveMetricsSpanProcessor;
+pub use retry::RetryPolicy;
+pub use sink::EnvelopeSink;
+use sink::SinkWriter;
+use stats::Stats;
+pub use stats::{ExporterStats, SignalStats};
+#[cfg(feature = "statsbeat")]
+pub use statsbeat::Statsbeat;
+#[cfg(feature = "statsbeat")]
+use statsbeat::StatsbeatState;
 use std::{
     convert::TryInto,
     error::Error as StdError,
@@ -379,6 +489,14 @@ use std::{
     sync::{Arc, Mutex},
     time::Duration,
 };
+use storage::LocalStorage;
+pub use storage::OfflineStorage;
+#[cfg(feature = "trace")]
+pub use telemetry_client::{
+    AvailabilityTelemetry, EventTelemetry, ExceptionTelemetry, MetricTelemetry,
+    PageViewPerformanceTelemetry, PageViewTelemetry, TelemetryClient, TraceTelemetry,
+};
+use uploader::{Endpoint, RetryNotify, Throttle};
 #[cfg(feature = "live-metrics")]
 use uploader_quick_pulse::PostOrPing;
 
@@ -386,19 +504,43 @@ use uploader_quick_pulse::PostOrPing;
 #[derive(Clone)]
 pub struct Exporter<C> {
     client: Arc<C>,
-    track_endpoint: Arc<http::Uri>,
+    track_endpoint: Arc<Endpoint>,
     #[cfg(feature = "live-metrics")]
     live_post_endpoint: http::Uri,
     #[cfg(feature = "live-metrics")]
     live_ping_endpoint: http::Uri,
     instrumentation_key: String,
-    retry_notify: Option<Arc<Mutex<dyn FnMut(&Error, Duration) + Send + 'static>>>,
+    aad_scope: Option<String>,
+    credential: Option<Arc<TokenCache>>,
+    retry_policy: RetryPolicy,
+    retry_notify: Option<RetryNotify>,
+    dead_letter: Option<DeadLetterNotify>,
+    storage: Option<Arc<LocalStorage>>,
+    sink: Option<Arc<SinkWriter>>,
+    telemetry_processors: Processors,
+    context_tags: Tags,
+    common_properties: Properties,
+    throttle: Arc<Throttle>,
+    compression: Compression,
+    overflow_policy: OverflowPolicy,
+    buffers: Arc<BufferPool>,
+    stats: Arc<Stats>,
+    #[cfg(feature = "statsbeat")]
+    statsbeat: Option<Arc<StatsbeatState>>,
+    destinations: Vec<Arc<Destination>>,
+    routes: Arc<Routes>,
+    #[cfg(feature = "trace")]
+    span_router: Option<SpanRouter>,
+    #[cfg(feature = "logs")]
+    log_router: Option<LogRouter>,
     #[cfg(feature = "trace")]
     sample_rate: f64,
     #[cfg(any(feature = "trace", feature = "logs"))]
     resource: Resource,
     #[cfg(any(feature = "trace", feature = "logs"))]
     resource_attributes_in_events_and_logs: bool,
+    #[cfg(any(feature = "trace", feature = "logs"))]
+    measurement_attributes: Option<MeasurementAttributes>,
 }
 
 impl<C: Debug> Debug for Exporter<C> {
@@ -407,14 +549,31 @@ impl<C: Debug> Debug for Exporter<C> {
         debug
             .field("client", &self.client)
             .field("track_endpoint", &self.track_endpoint)
-            .field("instrumentation_key", &self.instrumentation_key);
+            .field("instrumentation_key", &self.instrumentation_key)
+            .field("aad_scope", &self.aad_scope)
+            .field("credential", &self.credential)
+            .field("retry_policy", &self.retry_policy)
+            .field("compression", &self.compression)
+            .field("overflow_policy", &self.overflow_policy)
+            .field("destinations", &self.destinations)
+            .field("routes", &self.routes)
+            .field("storage", &self.storage)
+            .field("sink", &self.sink)
+            .field("context_tags", &self.context_tags)
+            .field("common_properties", &self.common_properties)
+            .field("throttle", &self.throttle);
+        #[cfg(feature = "statsbeat")]
+        debug.field("statsbeat", &self.statsbeat);
         #[cfg(feature = "trace")]
         debug.field("sample_rate", &self.sample_rate);
         #[cfg(any(feature = "trace", feature = "logs"))]
-        debug.field("resource", &self.resource).field(
-            "resource_attributes_in_events_and_logs",
-            &self.resource_attributes_in_events_and_logs,
-        );
+        debug
+            .field("resource", &self.resource)
+            .field(
+                "resource_attributes_in_events_and_logs",
+                &self.resource_attributes_in_events_and_logs,
+            )
+            .field("measurement_attributes", &self.measurement_attributes);
         #[cfg(feature = "live-metrics")]
         debug
             .field("live_post_endpoint", &self.live_post_endpoint)
@@ -429,7 +588,7 @@ impl<C> Exporter<C> {
     pub fn new(instrumentation_key: String, client: C) -> Self {
         Self {
             client: Arc::new(client),
-            track_endpoint: Arc::new(append_v2_track(DEFAULT_BREEZE_ENDPOINT)),
+            track_endpoint: Arc::new(Endpoint::new(append_v2_track(DEFAULT_BREEZE_ENDPOINT))),
             #[cfg(feature = "live-metrics")]
             live_post_endpoint: append_quick_pulse(
                 DEFAULT_LIVE_ENDPOINT,
@@ -443,13 +602,37 @@ impl<C> Exporter<C> {
                 &instrumentation_key,
             ),
             instrumentation_key,
+            aad_scope: None,
+            credential: None,
+            retry_policy: RetryPolicy::default(),
             retry_notify: None,
+            dead_letter: None,
+            storage: None,
+            sink: None,
+            telemetry_processors: Vec::new(),
+            context_tags: Tags::new(),
+            common_properties: Properties::new(),
+            throttle: Arc::new(Throttle::default()),
+            compression: Compression::default(),
+            overflow_policy: OverflowPolicy::default(),
+            buffers: Arc::new(BufferPool::default()),
+            stats: Arc::new(Stats::default()),
+            #[cfg(feature = "statsbeat")]
+            statsbeat: None,
+            destinations: Vec::new(),
+            routes: Arc::new(Routes::default()),
+            #[cfg(feature = "trace")]
+            span_router: None,
+            #[cfg(feature = "logs")]
+            log_router: None,
             #[cfg(feature = "trace")]
             sample_rate: 100.0,
             #[cfg(any(feature = "trace", feature = "logs"))]
             resource: Resource::builder_empty().build(),
             #[cfg(any(feature = "trace", feature = "logs"))]
             resource_attributes_in_events_and_logs: false,
+            #[cfg(any(feature = "trace", feature = "logs"))]
+            measurement_attributes: None,
         }
     }
 
@@ -469,7 +652,9 @@ impl<C> Exporter<C> {
         let connection_string: ConnectionString = connection_string.as_ref().parse()?;
         Ok(Self {
             client: Arc::new(client),
-            track_endpoint: Arc::new(append_v2_track(&connection_string.ingestion_endpoint)),
+            track_endpoint: Arc::new(Endpoint::new(append_v2_track(
+                &connection_string.ingestion_endpoint,
+            ))),
             #[cfg(feature = "live-metrics")]
             live_post_endpoint: append_quick_pulse(
                 &connection_string.live_endpoint,
@@ -483,18 +668,93 @@ impl<C> Exporter<C> {
                 &connection_string.instrumentation_key,
             ),
             instrumentation_key: connection_string.instrumentation_key,
+            aad_scope: connection_string.aad_scope,
+            credential: None,
+            retry_policy: RetryPolicy::default(),
             retry_notify: None,
+            dead_letter: None,
+            storage: None,
+            sink: None,
+            telemetry_processors: Vec::new(),
+            context_tags: Tags::new(),
+            common_properties: Properties::new(),
+            throttle: Arc::new(Throttle::default()),
+            compression: Compression::default(),
+            overflow_policy: OverflowPolicy::default(),
+            buffers: Arc::new(BufferPool::default()),
+            stats: Arc::new(Stats::default()),
+            #[cfg(feature = "statsbeat")]
+            statsbeat: None,
+            destinations: Vec::new(),
+            routes: Arc::new(Routes::default()),
+            #[cfg(feature = "trace")]
+            span_router: None,
+            #[cfg(feature = "logs")]
+            log_router: None,
             #[cfg(feature = "trace")]
             sample_rate: 100.0,
             #[cfg(any(feature = "trace", feature = "logs"))]
             resource: Resource::builder_empty().build(),
             #[cfg(any(feature = "trace", feature = "logs"))]
             resource_attributes_in_events_and_logs: false,
+            #[cfg(any(feature = "trace", feature = "logs"))]
+            measurement_attributes: None,
         })
     }
 
+    /// Set the policy for retrying requests to upload telemetry, which failed with a retryable
+    /// error.
+    ///
+    /// Default: [`RetryPolicy::default()`].
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Set the compression of request bodies.
+    ///
+    /// Default: [`Compression::Default`].
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Set how custom property values longer than the maximum length of 8192 bytes are sent.
+    ///
+    /// Default: [`OverflowPolicy::Truncate`].
+    pub fn with_overflow_policy(mut self, overflow_policy: OverflowPolicy) -> Self {
+        self.overflow_policy = overflow_policy;
+        self
+    }
+
+    /// Send telemetry to an additional Application Insights resource.
+    ///
+    /// Telemetry items are converted once and sent to every resource with the respective
+    /// instrumentation key. Requests to the resources are sent concurrently and retried
+    /// independently, so a slow or throttled resource doesn't delay the others. The export fails
+    /// if sending to any of the resources fails.
+    ///
+    /// All other configuration applies to all resources, e.g. the token credential is used for all
+    /// resources that require Microsoft Entra ID authentication, with tokens for the scope of each
+    /// resource. No token is sent to resources without `Authorization=AAD`. If offline storage is configured,
+    /// telemetry for an additional resource is stored in the subdirectory
+    /// `destinations/<instrumentation key>`. The maximum size of the offline storage applies to
+    /// all resources together.
+    pub fn with_additional_connection_string(
+        mut self,
+        connection_string: impl AsRef<str>,
+    ) -> Result<Self, Box<dyn StdError + Send + Sync + 'static>> {
+        let connection_string: ConnectionString = connection_string.as_ref().parse()?;
+        self.destinations
+            .push(Arc::new(Destination::new(connection_string)));
+        Ok(self)
+    }
+
     /// Set a retry notification function that is called when a request to upload telemetry to
     /// Application Insights failed and will be retried.
+    ///
+    /// It's also called with [`Error::UploadThrottled`] when an export is skipped, because
+    /// Application Insights asked to wait before sending more telemetry.
     pub fn with_retry_notify<N>(mut self, retry_notify: N) -> Self
     where
         N: FnMut(&Error, Duration) + Send + 'static,
@@ -503,6 +763,207 @@ impl<C> Exporter<C> {
         self
     }
 
+    /// Set a function that is called with telemetry items, which Application Insights rejected with
+    /// a status code that is not retried.
+    ///
+    /// Without it, rejected items are only reported as an [`Error::Upload`] from the export and
+    /// counted in [`Exporter::stats`]. Rejected items are usually caused by invalid data, so this
+    /// helps to find out which items failed and why.
+    pub fn with_dead_letter<D>(mut self, dead_letter: D) -> Self
+    where
+        D: FnMut(&[DeadLetter]) + Send + 'static,
+    {
+        self.dead_letter = Some(Arc::new(Mutex::new(dead_letter)));
+        self
+    }
+
+    /// Set the credential used to get Microsoft Entra ID tokens, which are sent in an
+    /// `Authorization: Bearer` header with every request.
+    ///
+    /// This is required if the connection string contains `Authorization=AAD`, i.e. if local
+    /// authentication is disabled for the Application Insights resource. Tokens are requested for
+    /// the `https://monitor.azure.com/.default` scope, or the scope derived from `AADAudience` in
+    /// the connection string. Without a credential, exports fail with [`Error::TokenCredential`]
+    /// and live metrics are not sent.
+    pub fn with_token_credential<T>(mut self, credential: T) -> Self
+    where
+        T: TokenCredential + 'static,
+    {
+        let scope = self
+            .aad_scope
+            .clone()
+            .unwrap_or_else(|| DEFAULT_AAD_SCOPE.into());
+        self.credential = Some(Arc::new(TokenCache::new(Arc::new(credential), scope)));
+        self
+    }
+
+    /// Persist telemetry on disk, which could not be uploaded because of a retryable error (e.g.
+    /// network issues or throttling), instead of dropping it.
+    ///
+    /// Persisted telemetry is sent again after later successful exports. Expired files are cleaned
+    /// up when the exporter shuts down.
+    ///
+    /// Default: disabled.
+    pub fn with_offline_storage(mut self, offline_storage: OfflineStorage) -> Self {
+        self.storage = Some(Arc::new(LocalStorage::new(offline_storage)));
+        self
+    }
+
+    /// Write telemetry to stdout or files instead of sending it to Application Insights. See
+    /// [`EnvelopeSink`] for details.
+    ///
+    /// Envelopes are the same as the ones that would have been sent, including the instrumentation
+    /// keys of additional and routed connection strings. Nothing is sent to Application Insights,
+    /// so no token credential is required and statsbeat is not sent.
+    ///
+    /// Default: disabled.
+    pub fn with_sink(mut self, sink: EnvelopeSink) -> Self {
+        self.sink = Some(Arc::new(SinkWriter::new(sink)));
+        self
+    }
+
+    /// Set context tags, e.g. [`attrs::CLOUD_ROLE`] or [`attrs::APPLICATION_VERSION`], for all
+    /// telemetry items. Keys, which are not one of the [`attrs`] constants for context tags, are
+    /// ignored. Calling this multiple times adds to the previously set tags.
+    ///
+    /// These tags override tags derived from the resource (e.g. `ai.cloud.role` from
+    /// `service.name`). `ai.*` attributes of individual spans, log records and metric data points
+    /// and tags derived from them (e.g. the operation id) take precedence over these tags.
+    ///
+    /// Default: none.
+    pub fn with_context_tags<K, V>(mut self, context_tags: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: AsRef<str>,
+        V: Into<String>,
+    {
+        for (key, value) in context_tags {
+            if let Some(key) = TAG_KEY_LOOKUP.get(key.as_ref()) {
+                self.context_tags.insert(key.clone(), value.into());
+            }
+        }
+        self
+    }
+
+    /// Set custom properties for all telemetry items, e.g. the region or build id of a deployment.
+    /// Calling this multiple times adds to the previously set properties.
+    ///
+    /// Properties of a telemetry item, e.g. from attributes of a span or log record, take
+    /// precedence over these properties. Unlike resource attributes with
+    /// [`with_resource_attributes_in_events_and_logs`](Exporter::with_resource_attributes_in_events_and_logs),
+    /// only the given properties are added.
+    ///
+    /// Default: none.
+    pub fn with_common_properties<K, V>(
+        mut self,
+        common_properties: impl IntoIterator<Item = (K, V)>,
+    ) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.common_properties.extend(
+            common_properties
+                .into_iter()
+                .map(|(key, value)| (key.into().into(), value.into().into())),
+        );
+        self
+    }
+
+    /// Add a processor, which can modify or drop telemetry items before they are sent. See
+    /// [`TelemetryProcessor`] for details.
+    ///
+    /// Processors run in the order they were added, for all signals, and before telemetry is
+    /// written to a [sink](Exporter::with_sink).
+    pub fn with_telemetry_processor<P>(mut self, processor: P) -> Self
+    where
+        P: TelemetryProcessor,
+    {
+        self.telemetry_processors.push(Arc::new(processor));
+        self
+    }
+
+    /// Send statsbeat, i.e. metrics about the requests this exporter sends, to a separate
+    /// Application Insights resource. See [`Statsbeat`] for details.
+    ///
+    /// Statsbeat is sent by a task spawned on the given runtime, independent of exports. The task
+    /// stops when the exporter and all of its clones are dropped. It's not enabled if the
+    /// `APPLICATIONINSIGHTS_STATSBEAT_DISABLED_ALL` environment variable is set to `true`.
+    ///
+    /// Default: disabled.
+    #[cfg(feature = "statsbeat")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "statsbeat")))]
+    pub fn with_statsbeat<R>(mut self, statsbeat: Statsbeat, runtime: R) -> Self
+    where
+        C: HttpClient + 'static,
+        R: opentelemetry_sdk::runtime::Runtime,
+    {
+        if statsbeat::is_disabled() {
+            self.statsbeat = None;
+            return self;
+        }
+        let state = Arc::new(StatsbeatState::new(
+            statsbeat,
+            self.instrumentation_key.clone(),
+        ));
+        statsbeat::spawn(&state, self.client.clone(), runtime);
+        self.statsbeat = Some(state);
+        self
+    }
+
+    /// Get counters of the telemetry items and bytes this exporter (and all of its clones) sent.
+    pub fn stats(&self) -> ExporterStats {
+        self.stats.snapshot()
+    }
+
+    /// Get counters of the telemetry items and bytes this exporter (and all of its clones) sent to
+    /// the additional or routed Application Insights resource with the given instrumentation key.
+    ///
+    /// Returns `None` if no resource with this instrumentation key was added using
+    /// [`Exporter::with_additional_connection_string`] and no telemetry was routed to it yet.
+    /// Telemetry sent to the resource of the exporter's connection string is counted in
+    /// [`Exporter::stats`].
+    pub fn destination_stats(&self, instrumentation_key: &str) -> Option<ExporterStats> {
+        self.destinations
+            .iter()
+            .chain(&self.routes.all())
+            .find(|destination| destination.instrumentation_key == instrumentation_key)
+            .map(|destination| destination.stats.snapshot())
+    }
+
+    /// Report the exporter [stats](Exporter::stats) as observable counters using the given meter
+    /// provider:
+    ///
+    /// - `applicationinsights.exporter.items` with the attributes `signal` (`traces`, `logs`,
+    ///   `metrics` or `stored` for items sent from the offline storage), `outcome` (`sent`,
+    ///   `accepted`, `rejected`, `retried`, `dropped`, `persisted` or `truncated`) and
+    ///   `http.response.status_code` (only for `rejected`)
+    /// - `applicationinsights.exporter.request.body.size` with the attributes `signal` and `body`
+    ///   (`uncompressed` for the size of the JSON or `sent` for the size after compression, if
+    ///   enabled)
+    ///
+    /// Counters of [additional](Exporter::with_additional_connection_string) and routed resources
+    /// (see [`Exporter::destination_stats`]) are reported with the additional attribute
+    /// `destination`, which contains their instrumentation key. Additional connection strings must
+    /// be added before calling this, routed resources are reported once telemetry was routed to
+    /// them.
+    pub fn register_stats<P>(&self, meter_provider: &P)
+    where
+        P: opentelemetry::metrics::MeterProvider + ?Sized,
+    {
+        let stats = self.stats.clone();
+        let destinations = self.destinations.clone();
+        let routes = self.routes.clone();
+        stats::register(
+            Arc::new(move |observe: &mut dyn FnMut(Option<&str>, &Stats)| {
+                observe(None, &stats);
+                for destination in destinations.iter().chain(&routes.all()) {
+                    observe(Some(&destination.instrumentation_key), &destination.stats);
+                }
+            }),
+            meter_provider,
+        );
+    }
+
     /// Set endpoint used to ingest telemetry. This should consist of scheme and authrity. The
     /// exporter will call `/v2/track` on the specified endpoint.
     ///
@@ -512,7 +973,7 @@ impl<C> Exporter<C> {
         mut self,
         endpoint: &str,
     ) -> Result<Self, Box<dyn StdError + Send + Sync + 'static>> {
-        self.track_endpoint = Arc::new(append_v2_track(endpoint));
+        self.track_endpoint = Arc::new(Endpoint::new(append_v2_track(endpoint)));
         Ok(self)
     }
 
@@ -528,6 +989,51 @@ impl<C> Exporter<C> {
         self
     }
 
+    /// Set a function, which chooses the Application Insights resource for a span by returning its
+    /// connection string.
+    ///
+    /// Spans, for which the function returns `None`, are sent to the resource of the exporter's
+    /// connection string (and any additional connection strings). Other spans, including their
+    /// events, are only sent to the returned resource. Requests to different resources are sent
+    /// concurrently.
+    ///
+    /// Spans routed to an invalid connection string, or to a new connection string after spans
+    /// and log records were already routed to 100 different ones, are dropped and counted in
+    /// [`SignalStats::dropped`].
+    ///
+    /// Default: use the [`attrs::CONNECTION_STRING`] attribute of the span or resource.
+    #[cfg(feature = "trace")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "trace")))]
+    pub fn with_span_router<R>(mut self, router: R) -> Self
+    where
+        R: Fn(&opentelemetry_sdk::trace::SpanData) -> Option<String> + Send + Sync + 'static,
+    {
+        self.span_router = Some(Arc::new(router));
+        self
+    }
+
+    /// Set a function, which chooses the Application Insights resource for a log record by
+    /// returning its connection string.
+    ///
+    /// Log records, for which the function returns `None`, are sent to the resource of the
+    /// exporter's connection string (and any additional connection strings). Other log records are
+    /// only sent to the returned resource. Requests to different resources are sent concurrently.
+    ///
+    /// Log records routed to an invalid connection string, or to a new connection string after
+    /// spans and log records were already routed to 100 different ones, are dropped and counted in
+    /// [`SignalStats::dropped`].
+    ///
+    /// Default: use the [`attrs::CONNECTION_STRING`] attribute of the log record or resource.
+    #[cfg(feature = "logs")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "logs")))]
+    pub fn with_log_router<R>(mut self, router: R) -> Self
+    where
+        R: Fn(&opentelemetry_sdk::logs::SdkLogRecord) -> Option<String> + Send + Sync + 'static,
+    {
+        self.log_router = Some(Arc::new(router));
+        self
+    }
+
     /// Set whether resource attributes should be included in events.
     ///
     /// This affects both trace events and logs.
@@ -542,6 +1048,197 @@ impl<C> Exporter<C> {
         self.resource_attributes_in_events_and_logs = resource_attributes_in_events_and_logs;
         self
     }
+
+    /// Send attributes with numeric values, or attributes with a prefix, as custom measurements
+    /// instead of custom properties.
+    ///
+    /// Measurements are sent for requests, dependencies, custom events, exceptions, availability
+    /// test results and page views. They are taken from the attributes of the span, span event or
+    /// log record, not from the resource.
+    ///
+    /// Default: none, all attributes are sent as custom properties.
+    #[cfg(any(feature = "trace", feature = "logs"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "trace", feature = "logs"))))]
+    pub fn with_measurement_attributes(
+        mut self,
+        measurement_attributes: MeasurementAttributes,
+    ) -> Self {
+        self.measurement_attributes = Some(measurement_attributes);
+        self
+    }
+}
+
+#[cfg(any(feature = "trace", feature = "logs", feature = "metrics"))]
+impl<C: HttpClient + 'static> Exporter<C> {
+    async fn upload(
+        &self,
+        signal: fn(&Stats) -> &stats::Counters,
+        mut envelopes: RoutedEnvelopes,
+    ) -> Result<(), Error> {
+        envelopes.retain_mut(|envelope| {
+            self.add_common_properties(envelope);
+            if !processor::process(&self.telemetry_processors, envelope) {
+                signal(&self.stats).record_dropped(1);
+                return false;
+            }
+            if self.overflow_policy.apply(envelope) {
+                signal(&self.stats).record_truncated(1);
+            }
+            true
+        });
+
+        if let Some(ref sink) = self.sink {
+            return self.write_to_sink(sink, envelopes);
+        }
+
+        let RoutedEnvelopes {
+            default: mut envelopes,
+            routed,
+        } = envelopes;
+        let (routed_destinations, routed_envelopes): (Vec<_>, Vec<_>) = routed.into_iter().unzip();
+        let destinations: Vec<&Destination> = self
+            .destinations
+            .iter()
+            .chain(&routed_destinations)
+            .map(AsRef::as_ref)
+            .collect();
+
+        let requires_credential = self.aad_scope.is_some()
+            || destinations
+                .iter()
+                .any(|destination| destination.aad_scope.is_some());
+        if requires_credential && self.credential.is_none() {
+            return Err(credential::missing_credential_error());
+        }
+
+        let upload = uploader::Upload {
+            client: self.client.as_ref(),
+            endpoint: &self.track_endpoint,
+            retry_policy: &self.retry_policy,
+            retry_notify: self.retry_notify.as_ref(),
+            dead_letter: self.dead_letter.as_ref(),
+            storage: self.storage.as_deref(),
+            credential: self.credential.as_deref(),
+            throttle: &self.throttle,
+            counters: signal(&self.stats),
+            #[cfg(feature = "statsbeat")]
+            statsbeat: self.statsbeat.as_deref(),
+            compression: self.compression,
+            buffers: &self.buffers,
+        };
+        let storages: Vec<_> = destinations
+            .iter()
+            .map(|destination| self.destination_storage(destination))
+            .collect();
+        let stored_counters: Vec<_> = std::iter::once(&self.stats.stored)
+            .chain(
+                destinations
+                    .iter()
+                    .map(|destination| &destination.stats.stored),
+            )
+            .collect();
+        let mut uploads = vec![upload];
+        uploads.extend(
+            destinations
+                .iter()
+                .zip(&storages)
+                .map(|(destination, storage)| uploader::Upload {
+                    endpoint: &destination.track_endpoint,
+                    storage: storage.as_ref(),
+                    credential: destination.credential(self.credential.as_deref()),
+                    throttle: &destination.throttle,
+                    counters: signal(&destination.stats),
+                    ..upload
+                }),
+        );
+
+        // Envelopes are converted once and only re-keyed for each additional destination.
+        let mut serialized = Vec::with_capacity(uploads.len());
+        serialized.push(uploader::serialize(&self.buffers, &envelopes)?);
+        for destination in &self.destinations {
+            for envelope in &mut envelopes {
+                envelope.i_key = Some(destination.instrumentation_key.clone().into());
+            }
+            serialized.push(uploader::serialize(&self.buffers, &envelopes)?);
+        }
+        drop(envelopes);
+        for envelopes in routed_envelopes {
+            serialized.push(uploader::serialize(&self.buffers, &envelopes)?);
+        }
+
+        let results = join_all(uploads.iter().zip(serialized).zip(stored_counters).map(
+            |((upload, serialized), stored_counters)| async move {
+                let result = uploader::send_serialized(upload, serialized).await;
+                if result.is_ok() {
+                    // Stored batches may contain items of any signal.
+                    uploader::send_stored(&uploader::Upload {
+                        counters: stored_counters,
+                        ..*upload
+                    })
+                    .await;
+                }
+                result
+            },
+        ))
+        .await;
+
+        results.into_iter().collect()
+    }
+
+    fn add_common_properties(&self, envelope: &mut Envelope) {
+        let Some(ref mut data) = envelope.data else {
+            return;
+        };
+        if self.common_properties.is_empty() {
+            return;
+        }
+        let properties = data.properties_mut().get_or_insert_with(Properties::new);
+        for (key, value) in &self.common_properties {
+            properties
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+    }
+
+    fn write_to_sink(&self, sink: &SinkWriter, envelopes: RoutedEnvelopes) -> Result<(), Error> {
+        let RoutedEnvelopes {
+            default: mut envelopes,
+            routed,
+        } = envelopes;
+        sink.write(&envelopes).map_err(Error::Sink)?;
+        for destination in &self.destinations {
+            for envelope in &mut envelopes {
+                envelope.i_key = Some(destination.instrumentation_key.clone().into());
+            }
+            sink.write(&envelopes).map_err(Error::Sink)?;
+        }
+        for (_, envelopes) in routed {
+            sink.write(&envelopes).map_err(Error::Sink)?;
+        }
+
+        Ok(())
+    }
+
+    fn shutdown_storage(&self) -> Result<(), Error> {
+        if let Some(ref storage) = self.storage {
+            storage.cleanup().map_err(Error::OfflineStorage)?;
+        }
+        for destination in self.destinations.iter().chain(&self.routes.all()) {
+            if let Some(storage) = self.destination_storage(destination) {
+                storage.cleanup().map_err(Error::OfflineStorage)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Offline storage of a destination other than the exporter's connection string, if
+    /// configured.
+    fn destination_storage(&self, destination: &Destination) -> Option<LocalStorage> {
+        self.storage
+            .as_ref()
+            .map(|storage| storage.destination(&destination.instrumentation_key))
+    }
 }
 
 fn append_v2_track(uri: impl ToString) -> http::Uri {
@@ -573,6 +1270,14 @@ fn append_path(
     curr.try_into()
 }
 
+fn replace_host(uri: http::Uri, new_host: http::Uri) -> http::Uri {
+    let mut parts = uri.into_parts();
+    let new_parts = new_host.into_parts();
+    parts.scheme = new_parts.scheme;
+    parts.authority = new_parts.authority;
+    http::Uri::from_parts(parts).expect("valid uri + valid uri = valid uri")
+}
+
 /// Errors that occurred during span export.
 #[derive(thiserror::Error, Debug)]
 #[non_exhaustive]
@@ -609,6 +1314,32 @@ pub enum Error {
     #[error("upload failed with {0}")]
     Upload(String),
 
+    /// Application Insights rejected the request, because the token was invalid (HTTP 401) or the
+    /// identity is not allowed to send telemetry to the resource (HTTP 403). The request is not
+    /// retried during the export. Its telemetry is written to the offline storage, if configured,
+    /// and sent again by later exports, and dropped otherwise.
+    #[error("upload was not authorized ({0}); check the token credential")]
+    UploadUnauthorized(u16),
+
+    /// Application Insights throttled requests (HTTP 429, 439 or 503 with a `Retry-After` header,
+    /// or 439 without one, in which case uploads pause for an hour), e.g. because the daily cap is
+    /// reached. Telemetry is not sent until the given duration has passed. In the meantime it's
+    /// written to the offline storage, if configured, and dropped otherwise.
+    #[error("upload was throttled; uploads paused for {}s", .0.as_secs())]
+    UploadThrottled(Duration),
+
+    /// Could not get a Microsoft Entra ID token from the configured token credential.
+    #[error("getting token failed with {0}")]
+    TokenCredential(Box<dyn StdError + Send + Sync + 'static>),
+
+    /// Failed to read or write telemetry data in the offline storage.
+    #[error("accessing offline storage failed with {0}")]
+    OfflineStorage(std::io::Error),
+
+    /// Failed to write telemetry data to the configured [`EnvelopeSink`].
+    #[error("writing to sink failed with {0}")]
+    Sink(std::io::Error),
+
     /// Failed to process span for live metrics.
     #[cfg(feature = "live-metrics")]
     #[cfg_attr(docsrs, doc(cfg(feature = "live-metrics")))]
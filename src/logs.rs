@@ -2,11 +2,16 @@ use crate::{
     convert::{
         attrs_map_to_properties, attrs_to_map, attrs_to_properties, time_to_string, AttrValue,
     },
-    models::{Data, Envelope, ExceptionData, ExceptionDetails, MessageData, SeverityLevel},
+    destination::{Route, RoutedEnvelopes},
+    exception::{exceptions_from_attrs, problem_id},
+    models::{
+        context_tag_keys::attrs::CONNECTION_STRING, Data, Envelope, ExceptionData, MessageData,
+        SeverityLevel,
+    },
     tags::get_tags_for_log,
     Exporter,
 };
-use opentelemetry::{logs::Severity, InstrumentationScope};
+use opentelemetry::{logs::Severity, InstrumentationScope, Key};
 use opentelemetry_http::HttpClient;
 use opentelemetry_sdk::{
     error::OTelSdkResult,
@@ -14,7 +19,7 @@ use opentelemetry_sdk::{
     Resource,
 };
 use opentelemetry_semantic_conventions as semcov;
-use std::{sync::Arc, time::SystemTime};
+use std::time::{Duration, SystemTime};
 
 fn is_exception(record: &SdkLogRecord) -> bool {
     record.attributes_iter().any(|(k, _)| {
@@ -24,6 +29,31 @@ fn is_exception(record: &SdkLogRecord) -> bool {
 }
 
 impl<C> Exporter<C> {
+    /// Returns the route of the log record.
+    fn route_for_log(&self, record: &SdkLogRecord) -> Route {
+        let Some(connection_string) = self.connection_string_for_log(record) else {
+            return Route::Default;
+        };
+        self.routes.get(&connection_string)
+    }
+
+    fn connection_string_for_log(&self, record: &SdkLogRecord) -> Option<String> {
+        Some(match self.log_router {
+            Some(ref router) => router(record)?,
+            None => match record
+                .attributes_iter()
+                .find(|(k, _)| k.as_str() == CONNECTION_STRING)
+            {
+                Some((_, value)) => value.as_str().into_owned(),
+                None => self
+                    .resource
+                    .get(&Key::from_static_str(CONNECTION_STRING))?
+                    .as_str()
+                    .into_owned(),
+            },
+        })
+    }
+
     fn create_envelope_for_log(
         &self,
         (record, instrumentation_scope): (&SdkLogRecord, &InstrumentationScope),
@@ -33,17 +63,21 @@ impl<C> Exporter<C> {
         } else {
             None
         };
-        let (data, name) = if is_exception(record) {
+        let max_value_len = self.overflow_policy.max_value_len();
+        let (mut data, name) = if is_exception(record) {
             (
-                Data::Exception(RecordAndResource(record, event_resource).into()),
+                Data::Exception(RecordAndResource(record, event_resource, max_value_len).into()),
                 "Microsoft.ApplicationInsights.Exception",
             )
         } else {
             (
-                Data::Message(RecordAndResource(record, event_resource).into()),
+                Data::Message(RecordAndResource(record, event_resource, max_value_len).into()),
                 "Microsoft.ApplicationInsights.Message",
             )
         };
+        if let Some(ref measurement_attributes) = self.measurement_attributes {
+            measurement_attributes.apply(&mut data, record.attributes_iter());
+        }
 
         Envelope {
             name,
@@ -60,6 +94,7 @@ impl<C> Exporter<C> {
                 record,
                 instrumentation_scope,
                 &self.resource,
+                &self.context_tags,
             )),
             data: Some(data),
         }
@@ -75,25 +110,25 @@ where
         &self,
         batch: LogBatch<'_>,
     ) -> impl std::future::Future<Output = OTelSdkResult> + Send {
-        let client = Arc::clone(&self.client);
-        let endpoint = Arc::clone(&self.track_endpoint);
-        let envelopes: Vec<_> = batch
-            .iter()
-            .map(|log| self.create_envelope_for_log(log))
-            .collect();
+        let mut envelopes = RoutedEnvelopes::default();
+        let mut dropped = 0;
+        for log in batch.iter() {
+            let route = self.route_for_log(log.0);
+            dropped += envelopes.push(route, [self.create_envelope_for_log(log)]);
+        }
+        self.stats.logs.record_dropped(dropped);
 
         async move {
-            crate::uploader::send(
-                client.as_ref(),
-                endpoint.as_ref(),
-                envelopes,
-                self.retry_notify.clone(),
-            )
-            .await
-            .map_err(Into::into)
+            self.upload(|stats| &stats.logs, envelopes)
+                .await
+                .map_err(Into::into)
         }
     }
 
+    fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
+        self.shutdown_storage().map_err(Into::into)
+    }
+
     fn set_resource(&mut self, resource: &Resource) {
         self.resource = resource.clone();
     }
@@ -126,35 +161,28 @@ impl From<Severity> for SeverityLevel {
     }
 }
 
-struct RecordAndResource<'a>(&'a SdkLogRecord, Option<&'a Resource>);
+/// A log record, the resource and the maximum length of custom property values.
+struct RecordAndResource<'a>(&'a SdkLogRecord, Option<&'a Resource>, usize);
 
 impl From<RecordAndResource<'_>> for ExceptionData {
-    fn from(RecordAndResource(record, resource): RecordAndResource) -> ExceptionData {
+    fn from(
+        RecordAndResource(record, resource, max_value_len): RecordAndResource,
+    ) -> ExceptionData {
         let mut attrs = attrs_to_map(record.attributes_iter());
-        let exception = ExceptionDetails {
-            type_name: attrs
-                .remove(semcov::trace::EXCEPTION_TYPE)
-                .map(Into::into)
-                .unwrap_or_else(|| "".into()),
-            message: attrs
-                .remove(semcov::trace::EXCEPTION_MESSAGE)
-                .map(Into::into)
-                .unwrap_or_else(|| "".into()),
-            stack: attrs
-                .remove(semcov::trace::EXCEPTION_STACKTRACE)
-                .map(Into::into),
-        };
+        let exceptions = exceptions_from_attrs(&mut attrs, "", "");
         ExceptionData {
             ver: 2,
-            exceptions: vec![exception],
+            problem_id: problem_id(&mut attrs, &exceptions),
+            exceptions,
             severity_level: record.severity_number().map(Into::into),
-            properties: attrs_map_to_properties(attrs, resource),
+            properties: attrs_map_to_properties(attrs, resource, max_value_len),
+            measurements: None,
         }
     }
 }
 
 impl From<RecordAndResource<'_>> for MessageData {
-    fn from(RecordAndResource(record, resource): RecordAndResource) -> MessageData {
+    fn from(RecordAndResource(record, resource, max_value_len): RecordAndResource) -> MessageData {
         MessageData {
             ver: 2,
             severity_level: record.severity_number().map(Into::into),
@@ -167,6 +195,7 @@ impl From<RecordAndResource<'_>> for MessageData {
             properties: attrs_to_properties(
                 record.attributes_iter(),
                 resource,
+                max_value_len,
                 #[cfg(feature = "trace")]
                 &[],
             ),
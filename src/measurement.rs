@@ -0,0 +1,161 @@
+use crate::{
+    convert::{AttrKeyValue, AttrValue},
+    models::{Data, Measurements},
+};
+
+/// Attributes, which are sent as custom measurements instead of custom properties.
+///
+/// Custom measurements (`customMeasurements`) can be charted and aggregated in the portal, while
+/// custom properties (`customDimensions`) are always strings. Measurements are sent for requests,
+/// dependencies, custom events, exceptions, availability test results and page views.
+///
+/// Configure it with [`Exporter::with_measurement_attributes`](crate::Exporter::with_measurement_attributes).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(docsrs, doc(cfg(any(feature = "trace", feature = "logs"))))]
+pub enum MeasurementAttributes {
+    /// Send all attributes with integer or float values as measurements.
+    Numeric,
+    /// Send attributes whose key starts with the prefix, e.g. `ai.measurement.`, as measurements.
+    /// The prefix is removed from the key. Values must be numbers or strings that parse as
+    /// numbers.
+    Prefix(String),
+}
+
+impl MeasurementAttributes {
+    /// Send attributes whose key starts with the prefix as measurements.
+    pub fn prefix(prefix: impl Into<String>) -> Self {
+        Self::Prefix(prefix.into())
+    }
+
+    fn measurement<'a>(&self, key: &'a str, value: &dyn AttrValue) -> Option<(&'a str, f64)> {
+        match self {
+            Self::Numeric => Some((key, value.as_f64()?)),
+            Self::Prefix(prefix) => {
+                let name = key.strip_prefix(prefix.as_str())?;
+                let value = value
+                    .as_f64()
+                    .or_else(|| value.as_str().trim().parse().ok())?;
+                Some((name, value))
+            }
+        }
+    }
+
+    /// Moves the matching attributes from the custom properties to the custom measurements of the
+    /// telemetry item. Does nothing for telemetry types without measurements.
+    pub(crate) fn apply<'a, A, T: 'a>(&self, data: &mut Data, attributes: A)
+    where
+        A: Iterator<Item = &'a T>,
+        &'a T: Into<AttrKeyValue<'a>>,
+    {
+        let mut measurements = Measurements::new();
+        let mut keys = Vec::new();
+        for AttrKeyValue(key, value) in attributes.map(Into::into) {
+            if let Some((name, value)) = self.measurement(key, value) {
+                if value.is_finite() {
+                    measurements.insert(name.into(), value);
+                    keys.push(key);
+                }
+            }
+        }
+        if measurements.is_empty() {
+            return;
+        }
+
+        let Some(data_measurements) = data.measurements_mut() else {
+            return;
+        };
+        data_measurements
+            .get_or_insert_with(Default::default)
+            .extend(measurements);
+        if let Some(properties) = data.properties_mut() {
+            for key in keys {
+                properties.remove(&key.into());
+            }
+            if properties.is_empty() {
+                *data.properties_mut() = None;
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "trace"))]
+mod tests {
+    use super::*;
+    use crate::{
+        convert::attrs_to_properties,
+        models::{EventData, MAX_PROPERTY_VALUE_LEN},
+    };
+    use opentelemetry::KeyValue;
+
+    fn event(attrs: &[KeyValue]) -> Data {
+        Data::Event(EventData {
+            ver: 2,
+            name: "event".into(),
+            properties: attrs_to_properties(attrs.iter(), None, MAX_PROPERTY_VALUE_LEN, &[]),
+            measurements: None,
+        })
+    }
+
+    fn properties_and_measurements(data: &mut Data) -> (Vec<String>, Vec<(String, f64)>) {
+        let properties = data
+            .properties()
+            .into_iter()
+            .flatten()
+            .map(|(key, _)| key.as_ref().to_string())
+            .collect();
+        let measurements = data
+            .measurements_mut()
+            .unwrap()
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key.as_ref().to_string(), *value))
+            .collect();
+        (properties, measurements)
+    }
+
+    #[test]
+    fn numeric_attributes() {
+        let attrs = [
+            KeyValue::new("count", 3),
+            KeyValue::new("ratio", 0.5),
+            KeyValue::new("name", "42"),
+            KeyValue::new("nan", f64::NAN),
+        ];
+        let mut data = event(&attrs);
+        MeasurementAttributes::Numeric.apply(&mut data, attrs.iter());
+        assert_eq!(
+            properties_and_measurements(&mut data),
+            (
+                vec!["name".into(), "nan".into()],
+                vec![("count".into(), 3.0), ("ratio".into(), 0.5)]
+            )
+        );
+    }
+
+    #[test]
+    fn prefixed_attributes() {
+        let attrs = [
+            KeyValue::new("ai.measurement.count", 3),
+            KeyValue::new("ai.measurement.total", "42.5"),
+            KeyValue::new("ai.measurement.name", "checkout"),
+            KeyValue::new("other", 1),
+        ];
+        let mut data = event(&attrs);
+        MeasurementAttributes::prefix("ai.measurement.").apply(&mut data, attrs.iter());
+        assert_eq!(
+            properties_and_measurements(&mut data),
+            (
+                vec!["ai.measurement.name".into(), "other".into()],
+                vec![("count".into(), 3.0), ("total".into(), 42.5)]
+            )
+        );
+    }
+
+    #[test]
+    fn only_measurements() {
+        let attrs = [KeyValue::new("count", 3)];
+        let mut data = event(&attrs);
+        MeasurementAttributes::Numeric.apply(&mut data, attrs.iter());
+        assert!(data.properties().is_none());
+    }
+}
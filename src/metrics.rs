@@ -1,6 +1,6 @@
 use crate::{
     convert::time_to_string,
-    models::{Data, DataPoint, DataPointType, Envelope, MetricData, Properties},
+    models::{Data, DataPoint, DataPointType, Envelope, LimitedLenString, MetricData, Properties},
     tags::get_tags_for_metric,
     Exporter,
 };
@@ -17,7 +17,6 @@ use opentelemetry_sdk::{
 };
 use std::{
     convert::TryInto,
-    sync::Arc,
     time::{Duration, SystemTime},
 };
 
@@ -31,16 +30,18 @@ where
         &self,
         metrics: &ResourceMetrics,
     ) -> impl std::future::Future<Output = OTelSdkResult> + Send {
-        let client = Arc::clone(&self.client);
-        let endpoint = Arc::clone(&self.track_endpoint);
-
         let mut envelopes = Vec::new();
+        let max_value_len = self.overflow_policy.max_value_len();
         for scope_metrics in metrics.scope_metrics() {
             for metric in scope_metrics.metrics() {
                 let data_points = map_metric(metric);
                 for data in data_points {
-                    let tags =
-                        get_tags_for_metric(metrics.resource(), scope_metrics.scope(), &data.attrs);
+                    let tags = get_tags_for_metric(
+                        metrics.resource(),
+                        scope_metrics.scope(),
+                        &data.attrs,
+                        &self.context_tags,
+                    );
                     let properties: Properties = metrics
                         .resource()
                         .iter()
@@ -51,7 +52,12 @@ where
                                 .map(|kv| (&kv.key, &kv.value)),
                         )
                         .chain(data.attrs.iter().map(|kv| (&kv.key, &kv.value)))
-                        .map(|(k, v)| (k.as_str().into(), v.into()))
+                        .map(|(k, v)| {
+                            (
+                                k.as_str().into(),
+                                LimitedLenString::with_max_len(&v.as_str(), max_value_len),
+                            )
+                        })
                         .collect();
                     envelopes.push(Envelope {
                         name: "Microsoft.ApplicationInsights.Metric",
@@ -70,14 +76,9 @@ where
         }
 
         async move {
-            crate::uploader::send(
-                client.as_ref(),
-                endpoint.as_ref(),
-                envelopes,
-                self.retry_notify.clone(),
-            )
-            .await
-            .map_err(Into::into)
+            self.upload(|stats| &stats.metrics, envelopes.into())
+                .await
+                .map_err(Into::into)
         }
     }
 
@@ -86,7 +87,7 @@ where
     }
 
     fn shutdown_with_timeout(&self, _timeout: Duration) -> OTelSdkResult {
-        Ok(())
+        self.shutdown_storage().map_err(Into::into)
     }
 
     fn temporality(&self) -> Temporality {
@@ -0,0 +1,46 @@
+use crate::models::{LimitedLenString, Measurements, Properties};
+use serde::Serialize;
+
+/// Instances of AvailabilityData represent the result of executing an availability test.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AvailabilityData {
+    /// Schema version
+    pub(crate) ver: i32,
+
+    /// Identifier of a test run. Use it to correlate steps of test run and telemetry generated by
+    /// the service.
+    pub(crate) id: LimitedLenString<{ AvailabilityData::MAX_ID_LEN }>,
+
+    /// Name of the test that these availability results represent.
+    pub(crate) name: LimitedLenString<{ AvailabilityData::MAX_NAME_LEN }>,
+
+    /// Duration in format: DD.HH:MM:SS.MMMMMM. Must be less than 1000 days.
+    pub(crate) duration: String,
+
+    /// Success flag.
+    pub(crate) success: bool,
+
+    /// Name of the location where the test was run from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) run_location: Option<LimitedLenString<{ AvailabilityData::MAX_RUN_LOCATION_LEN }>>,
+
+    /// Diagnostic message for the result.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) message: Option<LimitedLenString<{ AvailabilityData::MAX_MESSAGE_LEN }>>,
+
+    /// Collection of custom properties.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) properties: Option<Properties>,
+
+    /// Collection of custom measurements.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) measurements: Option<Measurements>,
+}
+
+impl AvailabilityData {
+    pub(crate) const MAX_ID_LEN: usize = 64;
+    pub(crate) const MAX_NAME_LEN: usize = 1024;
+    pub(crate) const MAX_RUN_LOCATION_LEN: usize = 1024;
+    pub(crate) const MAX_MESSAGE_LEN: usize = 8192;
+}
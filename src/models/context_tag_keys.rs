@@ -1,3 +1,4 @@
+use crate::models::floor_char_boundary;
 use once_cell::sync::Lazy;
 use serde::Serialize;
 use std::collections::BTreeMap;
@@ -12,9 +13,14 @@ impl ContextTagKey {
     const fn new(key: &'static str, max_len: usize) -> Self {
         Self { key, max_len }
     }
+
+    #[cfg(feature = "cli")]
+    pub(crate) fn max_len(&self) -> usize {
+        self.max_len
+    }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub(crate) struct Tags(BTreeMap<&'static str, String>);
 
 impl Tags {
@@ -23,7 +29,7 @@ impl Tags {
     }
 
     pub(crate) fn insert(&mut self, key: ContextTagKey, mut value: String) -> Option<String> {
-        value.truncate(key.max_len);
+        value.truncate(floor_char_boundary(&value, key.max_len));
         self.0.insert(key.key, value)
     }
 
@@ -32,12 +38,25 @@ impl Tags {
         self.0.remove(key.key)
     }
 
+    pub(crate) fn extend(&mut self, other: &Tags) {
+        self.0
+            .extend(other.0.iter().map(|(key, value)| (*key, value.clone())));
+    }
+
+    pub(crate) fn get_by_name(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    pub(crate) fn remove_by_name(&mut self, key: &str) -> Option<String> {
+        self.0.remove(key)
+    }
+
     #[cfg(test)]
     pub(crate) fn get(&self, key: &ContextTagKey) -> Option<&String> {
         self.0.get(key.key)
     }
 
-    #[cfg(feature = "metrics")]
+    #[cfg(any(feature = "trace", feature = "metrics"))]
     pub(crate) fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
@@ -79,6 +98,54 @@ macro_rules! context_tag_keys {
             ///
             /// If not specified, the custom event name defaults to "&lt;no name&gt;".
             pub const CUSTOM_EVENT_NAME: &str = "ai.customEvent.name";
+
+            /// Problem id of an exception, which is used to group related exceptions. Defaults to
+            /// the exception type and the first function of the call stack outside of the standard
+            /// library.
+            pub const EXCEPTION_PROBLEM_ID: &str = "ai.exception.problemId";
+
+            /// Name of a page view recorded with special name "ai.pageView". Defaults to the name of
+            /// the span.
+            pub const PAGE_VIEW_NAME: &str = "ai.pageView.name";
+
+            /// URL of a page view recorded with special name "ai.pageView", including the query
+            /// string. Defaults to the `url.full` attribute of the span.
+            pub const PAGE_VIEW_URL: &str = "ai.pageView.url";
+
+            /// Duration in milliseconds of a page view recorded with special name "ai.pageView".
+            /// Defaults to the duration of the span.
+            pub const PAGE_VIEW_DURATION: &str = "ai.pageView.duration";
+
+            /// URL of the referring page of a page view recorded with special name "ai.pageView".
+            pub const PAGE_VIEW_REFERRER_URI: &str = "ai.pageView.referrerUri";
+
+            /// Name of the location an availability test was run from. Spans and span events with
+            /// this attribute are exported as availability test results instead of requests,
+            /// dependencies or traces.
+            pub const AVAILABILITY_RUN_LOCATION: &str = "ai.availability.run_location";
+
+            /// Whether an availability test succeeded (boolean). Defaults to `false` for spans with
+            /// an `Error` status and to `true` otherwise.
+            pub const AVAILABILITY_SUCCESS: &str = "ai.availability.success";
+
+            /// Diagnostic message of an availability test result. Defaults to the description of
+            /// an `Error` status of a span.
+            pub const AVAILABILITY_MESSAGE: &str = "ai.availability.message";
+
+            /// Id of an availability test result. Defaults to the span id for spans and to a new
+            /// random id for span events.
+            pub const AVAILABILITY_ID: &str = "ai.availability.id";
+
+            /// Duration in milliseconds of an availability test. Defaults to the duration of the
+            /// span for spans and to zero for span events.
+            pub const AVAILABILITY_DURATION: &str = "ai.availability.duration";
+
+            /// Connection string of the Application Insights resource, which should receive this
+            /// span or log record instead of the resource of the exporter's connection string.
+            /// Can be set on individual spans and log records or on the resource.
+            ///
+            /// The attribute is not sent to Application Insights.
+            pub const CONNECTION_STRING: &str = "microsoft.connection_string";
         }
 
         $($(#[doc = $doc])+
@@ -1,15 +1,21 @@
-#[cfg(feature = "metrics")]
-use crate::models::MetricData;
+#[cfg(any(feature = "trace", feature = "logs"))]
+use crate::models::Measurements;
 #[cfg(feature = "trace")]
-use crate::models::{EventData, RemoteDependencyData, RequestData};
+use crate::models::{
+    AvailabilityData, EventData, PageViewData, PageViewPerfData, RemoteDependencyData, RequestData,
+};
 #[cfg(any(feature = "trace", feature = "logs"))]
 use crate::models::{ExceptionData, MessageData};
+use crate::models::{MetricData, Properties};
 use serde::Serialize;
 
 /// Data struct to contain both B and C sections.
 #[derive(Debug, Serialize)]
 #[serde(tag = "baseType", content = "baseData")]
 pub(crate) enum Data {
+    #[cfg(feature = "trace")]
+    #[serde(rename = "AvailabilityData")]
+    Availability(AvailabilityData),
     #[cfg(feature = "trace")]
     #[serde(rename = "EventData")]
     Event(EventData),
@@ -19,13 +25,110 @@ pub(crate) enum Data {
     #[cfg(any(feature = "trace", feature = "logs"))]
     #[serde(rename = "MessageData")]
     Message(MessageData),
-    #[cfg(feature = "metrics")]
+    #[cfg_attr(
+        not(any(feature = "metrics", feature = "trace", feature = "statsbeat")),
+        allow(dead_code)
+    )]
     #[serde(rename = "MetricData")]
     Metric(MetricData),
     #[cfg(feature = "trace")]
+    #[serde(rename = "PageViewData")]
+    PageView(PageViewData),
+    #[cfg(feature = "trace")]
+    #[serde(rename = "PageviewPerformanceData")]
+    PageViewPerf(PageViewPerfData),
+    #[cfg(feature = "trace")]
     #[serde(rename = "RemoteDependencyData")]
     RemoteDependency(RemoteDependencyData),
     #[cfg(feature = "trace")]
     #[serde(rename = "RequestData")]
     Request(RequestData),
 }
+
+impl Data {
+    pub(crate) fn properties(&self) -> Option<&Properties> {
+        match self {
+            #[cfg(feature = "trace")]
+            Data::Availability(data) => data.properties.as_ref(),
+            #[cfg(feature = "trace")]
+            Data::Event(data) => data.properties.as_ref(),
+            #[cfg(any(feature = "trace", feature = "logs"))]
+            Data::Exception(data) => data.properties.as_ref(),
+            #[cfg(any(feature = "trace", feature = "logs"))]
+            Data::Message(data) => data.properties.as_ref(),
+            Data::Metric(data) => data.properties.as_ref(),
+            #[cfg(feature = "trace")]
+            Data::PageView(data) => data.properties.as_ref(),
+            #[cfg(feature = "trace")]
+            Data::PageViewPerf(data) => data.properties.as_ref(),
+            #[cfg(feature = "trace")]
+            Data::RemoteDependency(data) => data.properties.as_ref(),
+            #[cfg(feature = "trace")]
+            Data::Request(data) => data.properties.as_ref(),
+        }
+    }
+
+    pub(crate) fn properties_mut(&mut self) -> &mut Option<Properties> {
+        match self {
+            #[cfg(feature = "trace")]
+            Data::Availability(data) => &mut data.properties,
+            #[cfg(feature = "trace")]
+            Data::Event(data) => &mut data.properties,
+            #[cfg(any(feature = "trace", feature = "logs"))]
+            Data::Exception(data) => &mut data.properties,
+            #[cfg(any(feature = "trace", feature = "logs"))]
+            Data::Message(data) => &mut data.properties,
+            Data::Metric(data) => &mut data.properties,
+            #[cfg(feature = "trace")]
+            Data::PageView(data) => &mut data.properties,
+            #[cfg(feature = "trace")]
+            Data::PageViewPerf(data) => &mut data.properties,
+            #[cfg(feature = "trace")]
+            Data::RemoteDependency(data) => &mut data.properties,
+            #[cfg(feature = "trace")]
+            Data::Request(data) => &mut data.properties,
+        }
+    }
+
+    /// Custom measurements of telemetry types, which support them.
+    #[cfg(any(feature = "trace", feature = "logs"))]
+    pub(crate) fn measurements(&self) -> Option<&Measurements> {
+        match self {
+            #[cfg(feature = "trace")]
+            Data::Availability(data) => data.measurements.as_ref(),
+            #[cfg(feature = "trace")]
+            Data::Event(data) => data.measurements.as_ref(),
+            Data::Exception(data) => data.measurements.as_ref(),
+            #[cfg(feature = "trace")]
+            Data::PageView(data) => data.measurements.as_ref(),
+            #[cfg(feature = "trace")]
+            Data::PageViewPerf(data) => data.measurements.as_ref(),
+            #[cfg(feature = "trace")]
+            Data::RemoteDependency(data) => data.measurements.as_ref(),
+            #[cfg(feature = "trace")]
+            Data::Request(data) => data.measurements.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Custom measurements of telemetry types, which support them, to modify them.
+    #[cfg(any(feature = "trace", feature = "logs"))]
+    pub(crate) fn measurements_mut(&mut self) -> Option<&mut Option<Measurements>> {
+        match self {
+            #[cfg(feature = "trace")]
+            Data::Availability(data) => Some(&mut data.measurements),
+            #[cfg(feature = "trace")]
+            Data::Event(data) => Some(&mut data.measurements),
+            Data::Exception(data) => Some(&mut data.measurements),
+            #[cfg(feature = "trace")]
+            Data::PageView(data) => Some(&mut data.measurements),
+            #[cfg(feature = "trace")]
+            Data::PageViewPerf(data) => Some(&mut data.measurements),
+            #[cfg(feature = "trace")]
+            Data::RemoteDependency(data) => Some(&mut data.measurements),
+            #[cfg(feature = "trace")]
+            Data::Request(data) => Some(&mut data.measurements),
+            _ => None,
+        }
+    }
+}
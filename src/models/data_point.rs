@@ -7,10 +7,10 @@ use serde::Serialize;
 pub(crate) struct DataPoint {
     /// Namespace of the metric.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub(crate) ns: Option<LimitedLenString<256>>,
+    pub(crate) ns: Option<LimitedLenString<{ DataPoint::MAX_NS_LEN }>>,
 
     /// Name of the metric.
-    pub(crate) name: LimitedLenString<1024>,
+    pub(crate) name: LimitedLenString<{ DataPoint::MAX_NAME_LEN }>,
 
     /// Metric type. Single measurement or the aggregated value.
     #[serde(flatten, skip_serializing_if = "Option::is_none")]
@@ -20,11 +20,21 @@ pub(crate) struct DataPoint {
     pub(crate) value: f64,
 }
 
+impl DataPoint {
+    pub(crate) const MAX_NS_LEN: usize = 256;
+    pub(crate) const MAX_NAME_LEN: usize = 1024;
+}
+
 /// Type of the metric data measurement.
 #[derive(Debug, Serialize)]
 #[serde(tag = "kind")]
 pub(crate) enum DataPointType {
+    #[cfg_attr(
+        not(any(feature = "metrics", feature = "trace", feature = "statsbeat")),
+        allow(dead_code)
+    )]
     Measurement,
+    #[cfg_attr(not(any(feature = "metrics", feature = "statsbeat")), allow(dead_code))]
     Aggregation {
         /// Metric weight of the aggregated metric. Should not be set for a measurement.
         #[serde(skip_serializing_if = "Option::is_none")]
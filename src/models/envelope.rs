@@ -19,7 +19,7 @@ pub(crate) struct Envelope {
     /// decimal seconds digits provided are variable (and unspecified). Consumers should handle
     /// this, i.e. managed code consumers should not use format 'O' for parsing as it specifies a
     /// fixed length. Example: 2009-06-15T13:45:30.0000000Z.
-    pub(crate) time: LimitedLenString<64>,
+    pub(crate) time: LimitedLenString<{ Envelope::MAX_TIME_LEN }>,
 
     /// Sampling rate used in application. This telemetry item represents 1 / sampleRate actual
     /// telemetry items.
@@ -30,7 +30,7 @@ pub(crate) struct Envelope {
     /// there are cases when it is not a guid. No code should rely on iKey being a GUID.
     /// Instrumentation key is case insensitive.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub(crate) i_key: Option<LimitedLenString<40>>,
+    pub(crate) i_key: Option<LimitedLenString<{ Envelope::MAX_I_KEY_LEN }>>,
 
     /// Key/value collection of context properties. See ContextTagKeys for information on available
     /// properties.
@@ -41,3 +41,8 @@ pub(crate) struct Envelope {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) data: Option<Data>,
 }
+
+impl Envelope {
+    pub(crate) const MAX_TIME_LEN: usize = 64;
+    pub(crate) const MAX_I_KEY_LEN: usize = 40;
+}
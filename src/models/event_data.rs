@@ -1,4 +1,4 @@
-use crate::models::{LimitedLenString, Properties};
+use crate::models::{LimitedLenString, Measurements, Properties};
 use serde::Serialize;
 
 /// Instances of Event represent structured event records that can be grouped and searched by their
@@ -10,9 +10,17 @@ pub(crate) struct EventData {
     pub(crate) ver: i32,
 
     /// Event name. Keep it low cardinality to allow proper grouping and useful metrics.
-    pub(crate) name: LimitedLenString<512>,
+    pub(crate) name: LimitedLenString<{ EventData::MAX_NAME_LEN }>,
 
     /// Collection of custom properties.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) properties: Option<Properties>,
+
+    /// Collection of custom measurements.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) measurements: Option<Measurements>,
+}
+
+impl EventData {
+    pub(crate) const MAX_NAME_LEN: usize = 512;
 }
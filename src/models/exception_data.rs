@@ -1,4 +1,4 @@
-use crate::models::{ExceptionDetails, Properties, SeverityLevel};
+use crate::models::{ExceptionDetails, LimitedLenString, Measurements, Properties, SeverityLevel};
 use serde::Serialize;
 
 /// An instance of Exception represents a handled or unhandled exception that occurred during
@@ -17,7 +17,20 @@ pub(crate) struct ExceptionData {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) severity_level: Option<SeverityLevel>,
 
+    /// Identifier of where the exception was thrown in code. Used for exceptions grouping.
+    /// Typically a combination of exception type and a function from the call stack.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) problem_id: Option<LimitedLenString<{ ExceptionData::MAX_PROBLEM_ID_LEN }>>,
+
     /// Collection of custom properties.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) properties: Option<Properties>,
+
+    /// Collection of custom measurements.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) measurements: Option<Measurements>,
+}
+
+impl ExceptionData {
+    pub(crate) const MAX_PROBLEM_ID_LEN: usize = 1024;
 }
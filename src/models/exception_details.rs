@@ -1,17 +1,63 @@
-use crate::models::LimitedLenString;
+use crate::models::{parse_stack, LimitedLenString, StackFrame};
 use serde::Serialize;
 
 /// Exception details of the exception in a chain.
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct ExceptionDetails {
+    /// In case exception is nested (outer exception contains inner one), the id and outerId
+    /// properties are used to represent the nesting.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) id: Option<i32>,
+
+    /// The value of outerId is a reference to an element in ExceptionDetails that represents the
+    /// outer exception.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) outer_id: Option<i32>,
+
     /// Exception type name.
-    pub(crate) type_name: LimitedLenString<1024>,
+    pub(crate) type_name: LimitedLenString<{ ExceptionDetails::MAX_TYPE_NAME_LEN }>,
 
     /// Exception message.
-    pub(crate) message: LimitedLenString<32768>,
+    pub(crate) message: LimitedLenString<{ ExceptionDetails::MAX_MESSAGE_LEN }>,
+
+    /// Indicates if full exception stack is provided in the exception. The stack may be trimmed,
+    /// such as in the case of a StackOverflow exception.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) has_full_stack: Option<bool>,
 
     /// Text describing the stack. Either stack or parsedStack should have a value.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub(crate) stack: Option<LimitedLenString<32768>>,
+    pub(crate) stack: Option<LimitedLenString<{ ExceptionDetails::MAX_STACK_LEN }>>,
+
+    /// List of stack frames. Either stack or parsedStack should have a value.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) parsed_stack: Option<Vec<StackFrame>>,
+}
+
+impl ExceptionDetails {
+    pub(crate) const MAX_TYPE_NAME_LEN: usize = 1024;
+    pub(crate) const MAX_MESSAGE_LEN: usize = 32768;
+    pub(crate) const MAX_STACK_LEN: usize = 32768;
+
+    /// Creates exception details and parses the stack if it is a Rust backtrace.
+    pub(crate) fn new(
+        type_name: LimitedLenString<{ ExceptionDetails::MAX_TYPE_NAME_LEN }>,
+        message: LimitedLenString<{ ExceptionDetails::MAX_MESSAGE_LEN }>,
+        stack: Option<&str>,
+    ) -> Self {
+        let (parsed_stack, has_full_stack) = match stack.and_then(parse_stack) {
+            Some((frames, has_full_stack)) => (Some(frames), Some(has_full_stack)),
+            None => (None, None),
+        };
+        Self {
+            id: None,
+            outer_id: None,
+            type_name,
+            message,
+            has_full_stack,
+            stack: stack.map(Into::into),
+            parsed_stack,
+        }
+    }
 }
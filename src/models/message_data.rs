@@ -11,7 +11,7 @@ pub(crate) struct MessageData {
     pub(crate) ver: i32,
 
     /// Trace message
-    pub(crate) message: LimitedLenString<32768>,
+    pub(crate) message: LimitedLenString<{ MessageData::MAX_MESSAGE_LEN }>,
 
     /// Trace severity level.
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -21,3 +21,7 @@ pub(crate) struct MessageData {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) properties: Option<Properties>,
 }
+
+impl MessageData {
+    pub(crate) const MAX_MESSAGE_LEN: usize = 32768;
+}
@@ -1,6 +1,7 @@
+#[cfg(feature = "trace")]
+mod availability_data;
 pub(crate) mod context_tag_keys;
 mod data;
-#[cfg(feature = "metrics")]
 mod data_point;
 mod envelope;
 #[cfg(feature = "trace")]
@@ -11,10 +12,13 @@ mod exception_data;
 mod exception_details;
 #[cfg(any(feature = "trace", feature = "logs"))]
 mod message_data;
-#[cfg(feature = "metrics")]
 mod metric_data;
 #[cfg(feature = "trace")]
 mod ms_link;
+#[cfg(feature = "trace")]
+mod page_view_data;
+#[cfg(feature = "trace")]
+mod page_view_perf_data;
 #[cfg(feature = "live-metrics")]
 mod quick_pulse;
 #[cfg(feature = "trace")]
@@ -24,9 +28,12 @@ mod request_data;
 mod sanitize;
 #[cfg(any(feature = "trace", feature = "logs"))]
 mod severity_level;
+#[cfg(any(feature = "trace", feature = "logs"))]
+mod stack_frame;
 
+#[cfg(feature = "trace")]
+pub(crate) use availability_data::*;
 pub(crate) use data::*;
-#[cfg(feature = "metrics")]
 pub(crate) use data_point::*;
 pub(crate) use envelope::*;
 #[cfg(feature = "trace")]
@@ -37,10 +44,13 @@ pub(crate) use exception_data::*;
 pub(crate) use exception_details::*;
 #[cfg(any(feature = "trace", feature = "logs"))]
 pub(crate) use message_data::*;
-#[cfg(feature = "metrics")]
 pub(crate) use metric_data::*;
 #[cfg(feature = "trace")]
 pub(crate) use ms_link::*;
+#[cfg(feature = "trace")]
+pub(crate) use page_view_data::*;
+#[cfg(feature = "trace")]
+pub(crate) use page_view_perf_data::*;
 #[cfg(feature = "live-metrics")]
 pub(crate) use quick_pulse::*;
 #[cfg(feature = "trace")]
@@ -50,6 +60,8 @@ pub(crate) use request_data::*;
 pub(crate) use sanitize::*;
 #[cfg(any(feature = "trace", feature = "logs"))]
 pub(crate) use severity_level::*;
+#[cfg(any(feature = "trace", feature = "logs"))]
+pub(crate) use stack_frame::*;
 
 #[cfg(test)]
 mod tests {
@@ -130,4 +142,18 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn sanitization_at_char_boundary() {
+        let mut tags = Tags::new();
+        tags.insert(OPERATION_ID, "ж".repeat(100));
+        let message: LimitedLenString<32768> = "ж".repeat(20000).into();
+        let properties: Properties = [("ж".repeat(100).into(), "ж".repeat(50000).into())].into();
+
+        assert_eq!("ж".repeat(64), *tags.get(&OPERATION_ID).unwrap());
+        assert_eq!("ж".repeat(16384), message.as_ref());
+        let (key, value) = properties.iter().next().unwrap();
+        assert_eq!("ж".repeat(75), key.as_ref());
+        assert_eq!("ж".repeat(4096), value.as_ref());
+    }
 }
@@ -0,0 +1,47 @@
+use crate::models::{LimitedLenString, Measurements, Properties};
+use serde::Serialize;
+
+/// An instance of PageView represents a generic action on a page like a button click.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PageViewData {
+    /// Schema version
+    pub(crate) ver: i32,
+
+    /// Event name. Keep it low cardinality to allow proper grouping and useful metrics.
+    pub(crate) name: LimitedLenString<{ PageViewData::MAX_NAME_LEN }>,
+
+    /// Identifier of a page view instance. Used for correlation between page view and other
+    /// telemetry items.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) id: Option<LimitedLenString<{ PageViewData::MAX_ID_LEN }>>,
+
+    /// Request URL with all query string parameters.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) url: Option<LimitedLenString<{ PageViewData::MAX_URL_LEN }>>,
+
+    /// Request duration in format: DD.HH:MM:SS.MMMMMM. For a page view (PageViewData), this is
+    /// the duration. For a page view with performance information (PageViewPerfData), this is the
+    /// page load time. Must be less than 1000 days.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) duration: Option<String>,
+
+    /// Fully qualified page URI or URL of the referring page; if unknown, leave blank.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) referrer_uri: Option<LimitedLenString<{ PageViewData::MAX_REFERRER_URI_LEN }>>,
+
+    /// Collection of custom properties.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) properties: Option<Properties>,
+
+    /// Collection of custom measurements.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) measurements: Option<Measurements>,
+}
+
+impl PageViewData {
+    pub(crate) const MAX_NAME_LEN: usize = 1024;
+    pub(crate) const MAX_ID_LEN: usize = 128;
+    pub(crate) const MAX_URL_LEN: usize = 2048;
+    pub(crate) const MAX_REFERRER_URI_LEN: usize = 2048;
+}
@@ -1,4 +1,4 @@
-use crate::models::{LimitedLenString, Properties};
+use crate::models::{LimitedLenString, Measurements, Properties};
 use serde::Serialize;
 
 /// An instance of Remote Dependency represents an interaction of the monitored component with a
@@ -11,16 +11,16 @@ pub(crate) struct RemoteDependencyData {
 
     /// Name of the command initiated with this dependency call. Low cardinality value. Examples
     /// are stored procedure name and URL path template.
-    pub(crate) name: LimitedLenString<1024>,
+    pub(crate) name: LimitedLenString<{ RemoteDependencyData::MAX_NAME_LEN }>,
 
     /// Identifier of a dependency call instance. Used for correlation with the request telemetry
     /// item corresponding to this dependency call.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub(crate) id: Option<LimitedLenString<128>>,
+    pub(crate) id: Option<LimitedLenString<{ RemoteDependencyData::MAX_ID_LEN }>>,
 
     /// Result code of a dependency call. Examples are SQL error code and HTTP status code.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub(crate) result_code: Option<LimitedLenString<1024>>,
+    pub(crate) result_code: Option<LimitedLenString<{ RemoteDependencyData::MAX_RESULT_CODE_LEN }>>,
 
     /// Request duration in format: DD.HH:MM:SS.MMMMMM. Must be less than 1000 days.
     pub(crate) duration: String,
@@ -32,19 +32,32 @@ pub(crate) struct RemoteDependencyData {
     /// Command initiated by this dependency call. Examples are SQL statement and HTTP URL's with
     /// all query parameters.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub(crate) data: Option<LimitedLenString<8192>>,
+    pub(crate) data: Option<LimitedLenString<{ RemoteDependencyData::MAX_DATA_LEN }>>,
 
     /// Target site of a dependency call. Examples are server name, host address.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub(crate) target: Option<LimitedLenString<1024>>,
+    pub(crate) target: Option<LimitedLenString<{ RemoteDependencyData::MAX_TARGET_LEN }>>,
 
     /// Dependency type name. Very low cardinality value for logical grouping of dependencies and
     /// interpretation of other fields like commandName and resultCode. Examples are SQL, Azure
     /// table, and HTTP.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub(crate) type_: Option<LimitedLenString<1024>>,
+    pub(crate) type_: Option<LimitedLenString<{ RemoteDependencyData::MAX_TYPE_LEN }>>,
 
     /// Collection of custom properties.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) properties: Option<Properties>,
+
+    /// Collection of custom measurements.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) measurements: Option<Measurements>,
+}
+
+impl RemoteDependencyData {
+    pub(crate) const MAX_NAME_LEN: usize = 1024;
+    pub(crate) const MAX_ID_LEN: usize = 128;
+    pub(crate) const MAX_RESULT_CODE_LEN: usize = 1024;
+    pub(crate) const MAX_DATA_LEN: usize = 8192;
+    pub(crate) const MAX_TARGET_LEN: usize = 1024;
+    pub(crate) const MAX_TYPE_LEN: usize = 1024;
 }
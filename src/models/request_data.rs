@@ -1,4 +1,4 @@
-use crate::models::{LimitedLenString, Properties};
+use crate::models::{LimitedLenString, Measurements, Properties};
 use serde::Serialize;
 
 /// An instance of Request represents completion of an external request to the application to do
@@ -11,33 +11,45 @@ pub(crate) struct RequestData {
 
     /// Identifier of a request call instance. Used for correlation between request and other
     /// telemetry items.
-    pub(crate) id: LimitedLenString<128>,
+    pub(crate) id: LimitedLenString<{ RequestData::MAX_ID_LEN }>,
 
     /// Source of the request. Examples are the instrumentation key of the caller or the ip address
     /// of the caller.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub(crate) source: Option<LimitedLenString<1024>>,
+    pub(crate) source: Option<LimitedLenString<{ RequestData::MAX_SOURCE_LEN }>>,
 
     /// Name of the request. Represents code path taken to process request. Low cardinality value
     /// to allow better grouping of requests. For HTTP requests it represents the HTTP method and
     /// URL path template like 'GET /values/{id}'.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub(crate) name: Option<LimitedLenString<1024>>,
+    pub(crate) name: Option<LimitedLenString<{ RequestData::MAX_NAME_LEN }>>,
 
     /// Request duration in format: DD.HH:MM:SS.MMMMMM. Must be less than 1000 days.
     pub(crate) duration: String,
 
     /// Result of a request execution. HTTP status code for HTTP requests.
-    pub(crate) response_code: LimitedLenString<1024>,
+    pub(crate) response_code: LimitedLenString<{ RequestData::MAX_RESPONSE_CODE_LEN }>,
 
     /// Indication of successfull or unsuccessfull call.
     pub(crate) success: bool,
 
     /// Request URL with all query string parameters.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub(crate) url: Option<LimitedLenString<2048>>,
+    pub(crate) url: Option<LimitedLenString<{ RequestData::MAX_URL_LEN }>>,
 
     /// Collection of custom properties.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) properties: Option<Properties>,
+
+    /// Collection of custom measurements.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) measurements: Option<Measurements>,
+}
+
+impl RequestData {
+    pub(crate) const MAX_ID_LEN: usize = 128;
+    pub(crate) const MAX_SOURCE_LEN: usize = 1024;
+    pub(crate) const MAX_NAME_LEN: usize = 1024;
+    pub(crate) const MAX_RESPONSE_CODE_LEN: usize = 1024;
+    pub(crate) const MAX_URL_LEN: usize = 2048;
 }
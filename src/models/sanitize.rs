@@ -1,24 +1,67 @@
 use crate::convert::AttrValue;
-use serde::Serialize;
+use serde::{Serialize, Serializer};
 use std::{borrow::Cow, collections::BTreeMap};
 
-#[derive(Debug, Eq, PartialEq, PartialOrd, Ord, Serialize)]
-pub(crate) struct LimitedLenString<const N: usize>(String);
+/// Maximum length of custom property and measurement keys in bytes.
+pub(crate) const MAX_PROPERTY_KEY_LEN: usize = 150;
+/// Maximum length of custom property values in bytes.
+pub(crate) const MAX_PROPERTY_VALUE_LEN: usize = 8192;
+/// Maximum number of properties a long custom property value can be split into.
+pub(crate) const MAX_PROPERTY_PARTS: usize = 10;
 
-impl<const N: usize> From<&str> for LimitedLenString<N> {
+/// String with a maximum length of `N` bytes, which is sent truncated to a char boundary.
+///
+/// Up to `M` bytes of the original string are kept, so that values can be split instead of
+/// truncated (see [`OverflowPolicy`](crate::OverflowPolicy)).
+#[derive(Debug, Clone, Eq, PartialEq, PartialOrd, Ord)]
+pub(crate) struct LimitedLenString<const N: usize, const M: usize = N>(String);
+
+impl<const N: usize, const M: usize> LimitedLenString<N, M> {
+    /// Whether the string is longer than the maximum length.
+    pub(crate) fn is_truncated(&self) -> bool {
+        self.0.len() > N
+    }
+
+    /// The string including the part beyond the maximum length.
+    pub(crate) fn as_untruncated(&self) -> &str {
+        &self.0
+    }
+
+    /// Keeps at most `max_len` bytes of the string (and at most `M`). At least the first char
+    /// beyond `N` is kept, so [`Self::is_truncated`] still tells whether the string is too long.
+    pub(crate) fn with_max_len(s: &str, max_len: usize) -> Self {
+        Self(String::from(truncate(s, Self::kept_len(max_len))))
+    }
+
+    /// Drops the part of the string beyond `max_len` bytes like [`Self::with_max_len`] and frees
+    /// its memory.
+    #[cfg(feature = "trace")]
+    pub(crate) fn shrink_to(&mut self, max_len: usize) {
+        self.0
+            .truncate(floor_char_boundary(&self.0, Self::kept_len(max_len)));
+        self.0.shrink_to_fit();
+    }
+
+    fn kept_len(max_len: usize) -> usize {
+        // A char is at most 4 bytes long.
+        max_len.max(N + 4).min(M)
+    }
+}
+
+impl<const N: usize, const M: usize> From<&str> for LimitedLenString<N, M> {
     fn from(s: &str) -> Self {
-        Self(String::from(&s[0..std::cmp::min(s.len(), N)]))
+        Self(String::from(truncate(s, M)))
     }
 }
 
-impl<const N: usize> From<String> for LimitedLenString<N> {
+impl<const N: usize, const M: usize> From<String> for LimitedLenString<N, M> {
     fn from(mut s: String) -> Self {
-        s.truncate(N);
+        s.truncate(floor_char_boundary(&s, M));
         Self(s)
     }
 }
 
-impl<'a, const N: usize> From<Cow<'a, str>> for LimitedLenString<N> {
+impl<'a, const N: usize, const M: usize> From<Cow<'a, str>> for LimitedLenString<N, M> {
     fn from(s: std::borrow::Cow<'a, str>) -> Self {
         match s {
             Cow::Borrowed(b) => b.into(),
@@ -27,29 +70,101 @@ impl<'a, const N: usize> From<Cow<'a, str>> for LimitedLenString<N> {
     }
 }
 
-impl<const N: usize> From<&opentelemetry::Key> for LimitedLenString<N> {
+impl<const N: usize, const M: usize> From<&opentelemetry::Key> for LimitedLenString<N, M> {
     fn from(k: &opentelemetry::Key) -> Self {
         k.as_str().into()
     }
 }
 
-impl<const N: usize> From<&opentelemetry::Value> for LimitedLenString<N> {
+impl<const N: usize, const M: usize> From<&opentelemetry::Value> for LimitedLenString<N, M> {
     fn from(v: &opentelemetry::Value) -> Self {
         v.as_str().into()
     }
 }
 
-impl<const N: usize> From<&dyn AttrValue> for LimitedLenString<N> {
+impl<const N: usize, const M: usize> From<&dyn AttrValue> for LimitedLenString<N, M> {
     fn from(v: &dyn AttrValue) -> Self {
         v.as_str().into()
     }
 }
 
-impl<const N: usize> AsRef<str> for LimitedLenString<N> {
+impl<const N: usize, const M: usize> AsRef<str> for LimitedLenString<N, M> {
     #[inline]
     fn as_ref(&self) -> &str {
-        self.0.as_ref()
+        truncate(&self.0, N)
     }
 }
 
-pub(crate) type Properties = BTreeMap<LimitedLenString<150>, LimitedLenString<8192>>;
+impl<const N: usize, const M: usize> Serialize for LimitedLenString<N, M> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_ref())
+    }
+}
+
+/// Returns the longest prefix of the string with at most `max_len` bytes, which ends at a char
+/// boundary.
+pub(crate) fn truncate(s: &str, max_len: usize) -> &str {
+    &s[..floor_char_boundary(s, max_len)]
+}
+
+/// Largest index of a char boundary in the string, which is at most `max_len`.
+pub(crate) fn floor_char_boundary(s: &str, max_len: usize) -> usize {
+    if max_len >= s.len() {
+        return s.len();
+    }
+    (0..=max_len)
+        .rev()
+        .find(|&i| s.is_char_boundary(i))
+        .unwrap_or(0)
+}
+
+pub(crate) type Properties = BTreeMap<
+    LimitedLenString<MAX_PROPERTY_KEY_LEN>,
+    LimitedLenString<MAX_PROPERTY_VALUE_LEN, { MAX_PROPERTY_VALUE_LEN * MAX_PROPERTY_PARTS }>,
+>;
+
+#[cfg(any(feature = "trace", feature = "logs"))]
+pub(crate) type Measurements = BTreeMap<LimitedLenString<MAX_PROPERTY_KEY_LEN>, f64>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_at_char_boundary() {
+        let s: LimitedLenString<5> = "aaaé".into();
+        assert_eq!(s.as_ref(), "aaaé");
+        let s: LimitedLenString<4> = "aaaé".into();
+        assert_eq!(s.as_ref(), "aaa");
+        let s: LimitedLenString<4> = String::from("aaaé").into();
+        assert_eq!(s.as_ref(), "aaa");
+        let s: LimitedLenString<1> = "🦀".into();
+        assert_eq!(s.as_ref(), "");
+    }
+
+    #[test]
+    fn keep_up_to_given_len() {
+        let s = LimitedLenString::<2, 16>::with_max_len(&"a".repeat(20), 8);
+        assert!(s.is_truncated());
+        assert_eq!(s.as_untruncated(), "a".repeat(8));
+        assert!(!LimitedLenString::<2, 16>::with_max_len("aa", 0).is_truncated());
+    }
+
+    #[cfg(feature = "trace")]
+    #[test]
+    fn shrink_keeps_first_char_beyond_max_len() {
+        let mut s = LimitedLenString::<2, 16>::from("aééééé");
+        s.shrink_to(0);
+        assert!(s.is_truncated());
+        assert_eq!(s.as_untruncated(), "aéé");
+    }
+
+    #[test]
+    fn keep_up_to_max_kept_len() {
+        let s: LimitedLenString<2, 4> = "aaaaaa".into();
+        assert!(s.is_truncated());
+        assert_eq!(s.as_ref(), "aa");
+        assert_eq!(s.as_untruncated(), "aaaa");
+        assert_eq!(serde_json::to_string(&s).unwrap(), "\"aa\"");
+    }
+}
@@ -8,6 +8,5 @@ pub(crate) enum SeverityLevel {
     Information = 1,
     Warning = 2,
     Error = 3,
-    #[cfg(feature = "logs")]
     Critical = 4,
 }
@@ -0,0 +1,280 @@
+//! Parsed stack frames of exceptions.
+//!
+//! Stack traces in the format of [`std::backtrace::Backtrace`] are parsed into frames, e.g.
+//!
+//! ```plain
+//!    0: std::backtrace::Backtrace::force_capture
+//!              at /rustc/90b35a6239c3d8bdabc530a6a0816f7ff89a0aaf/library/std/src/backtrace.rs:310:18
+//!    1: app::checkout::pay
+//!              at ./src/checkout.rs:42:9
+//! ```
+//!
+//! Inlined functions are printed without a frame number and get a frame of their own.
+
+use crate::models::LimitedLenString;
+use serde::Serialize;
+
+/// Maximum combined length of the strings of all parsed frames. Larger stacks are trimmed from the
+/// middle, like in the
+/// [.NET SDK](https://github.com/microsoft/ApplicationInsights-dotnet/blob/2.22.0/BASE/src/Microsoft.ApplicationInsights/Extensibility/Implementation/External/ExceptionConverter.cs).
+const MAX_PARSED_STACK_LEN: usize = 32768;
+
+/// Stack frame information.
+#[derive(Debug, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StackFrame {
+    /// Level in the call stack. For the long stacks SDK may not report every function in a call
+    /// stack.
+    pub(crate) level: i32,
+
+    /// Method name.
+    pub(crate) method: LimitedLenString<{ StackFrame::MAX_METHOD_LEN }>,
+
+    /// Name of the assembly (crate) the frame belongs to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) assembly: Option<LimitedLenString<{ StackFrame::MAX_ASSEMBLY_LEN }>>,
+
+    /// File name if available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) file_name: Option<LimitedLenString<{ StackFrame::MAX_FILE_NAME_LEN }>>,
+
+    /// Line number if available.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) line: Option<i32>,
+}
+
+impl StackFrame {
+    pub(crate) const MAX_METHOD_LEN: usize = 1024;
+    pub(crate) const MAX_ASSEMBLY_LEN: usize = 1024;
+    pub(crate) const MAX_FILE_NAME_LEN: usize = 1024;
+
+    fn len(&self) -> usize {
+        self.method.as_ref().len()
+            + self.assembly.as_ref().map_or(0, |x| x.as_ref().len())
+            + self.file_name.as_ref().map_or(0, |x| x.as_ref().len())
+    }
+}
+
+/// Parses a Rust backtrace into stack frames. Returns the frames and whether all frames were kept,
+/// or `None` if the stack is not in the format of a Rust backtrace.
+pub(crate) fn parse_stack(stack: &str) -> Option<(Vec<StackFrame>, bool)> {
+    let mut frames: Vec<StackFrame> = Vec::new();
+    for line in stack.lines() {
+        if !line.starts_with(' ') {
+            continue;
+        }
+        let line = line.trim();
+        if let Some(location) = line.strip_prefix("at ") {
+            if let Some(frame) = frames.last_mut() {
+                if frame.file_name.is_none() {
+                    let (file_name, line) = parse_location(location);
+                    frame.file_name = Some(file_name.into());
+                    frame.line = line;
+                }
+            }
+            continue;
+        }
+        let method = match line.split_once(": ") {
+            Some((level, method)) if level.bytes().all(|b| b.is_ascii_digit()) => method,
+            // Inlined function of the previous frame.
+            _ if !frames.is_empty() => line,
+            _ => continue,
+        };
+        let method = strip_hash(method);
+        frames.push(StackFrame {
+            level: frames.len() as i32,
+            method: method.into(),
+            assembly: crate_name(method).map(Into::into),
+            file_name: None,
+            line: None,
+        });
+    }
+
+    if frames.is_empty() {
+        return None;
+    }
+    Some(trim(frames))
+}
+
+/// Keeps frames from the top and the bottom of the stack until the maximum length is reached.
+fn trim(frames: Vec<StackFrame>) -> (Vec<StackFrame>, bool) {
+    let total_len: usize = frames.iter().map(StackFrame::len).sum();
+    if total_len <= MAX_PARSED_STACK_LEN {
+        return (frames, true);
+    }
+
+    let mut len = 0;
+    let mut top = Vec::new();
+    let mut bottom = Vec::new();
+    let mut remaining = frames.into_iter();
+    while len < MAX_PARSED_STACK_LEN {
+        let Some(frame) = remaining.next() else {
+            break;
+        };
+        len += frame.len();
+        top.push(frame);
+        if len >= MAX_PARSED_STACK_LEN {
+            break;
+        }
+        let Some(frame) = remaining.next_back() else {
+            break;
+        };
+        len += frame.len();
+        bottom.push(frame);
+    }
+    top.extend(bottom.into_iter().rev());
+    (top, false)
+}
+
+fn parse_location(location: &str) -> (&str, Option<i32>) {
+    let mut parts = location.rsplitn(3, ':');
+    let last = parts.next().unwrap_or_default();
+    match (parts.next(), parts.next()) {
+        // file:line:column
+        (Some(line), Some(file)) if is_number(last) && is_number(line) => (file, line.parse().ok()),
+        // file:line
+        (Some(_), _) if is_number(last) => {
+            let (file, line) = location.rsplit_once(':').expect("has colon");
+            (file, line.parse().ok())
+        }
+        _ => (location, None),
+    }
+}
+
+fn is_number(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Removes the symbol hash, e.g. `::h0123456789abcdef`, from a method name.
+fn strip_hash(method: &str) -> &str {
+    match method.rsplit_once("::h") {
+        Some((method, hash)) if hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            method
+        }
+        _ => method,
+    }
+}
+
+/// Crate name of a method, i.e. the first path segment, e.g. `alloc` for
+/// `<alloc::boxed::Box<F> as core::ops::function::FnOnce<A>>::call_once`.
+fn crate_name(method: &str) -> Option<&str> {
+    let (first, _) = method.trim_start_matches('<').split_once("::")?;
+    if !first.is_empty()
+        && first
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+    {
+        Some(first)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BACKTRACE: &str = "   0: std::backtrace::Backtrace::force_capture
+             at /rustc/90b35a6239c3d8bdabc530a6a0816f7ff89a0aaf/library/std/src/backtrace.rs:310:18
+   1: app::checkout::pay::h0123456789abcdef
+             at ./src/checkout.rs:42:9
+      app::checkout::validate
+             at ./src/checkout.rs:10:5
+   2: <alloc::boxed::Box<F,A> as core::ops::function::FnOnce<Args>>::call_once
+   3: main
+";
+
+    #[test]
+    fn parse_backtrace() {
+        let (frames, has_full_stack) = parse_stack(BACKTRACE).unwrap();
+        assert!(has_full_stack);
+        assert_eq!(
+            frames,
+            vec![
+                StackFrame {
+                    level: 0,
+                    method: "std::backtrace::Backtrace::force_capture".into(),
+                    assembly: Some("std".into()),
+                    file_name: Some("/rustc/90b35a6239c3d8bdabc530a6a0816f7ff89a0aaf/library/std/src/backtrace.rs".into()),
+                    line: Some(310),
+                },
+                StackFrame {
+                    level: 1,
+                    method: "app::checkout::pay".into(),
+                    assembly: Some("app".into()),
+                    file_name: Some("./src/checkout.rs".into()),
+                    line: Some(42),
+                },
+                StackFrame {
+                    level: 2,
+                    method: "app::checkout::validate".into(),
+                    assembly: Some("app".into()),
+                    file_name: Some("./src/checkout.rs".into()),
+                    line: Some(10),
+                },
+                StackFrame {
+                    level: 3,
+                    method: "<alloc::boxed::Box<F,A> as core::ops::function::FnOnce<Args>>::call_once".into(),
+                    assembly: Some("alloc".into()),
+                    file_name: None,
+                    line: None,
+                },
+                StackFrame {
+                    level: 4,
+                    method: "main".into(),
+                    assembly: None,
+                    file_name: None,
+                    line: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_other_stacks() {
+        assert_eq!(parse_stack("at checkout"), None);
+        assert_eq!(
+            parse_stack("Error: failed\n\nStack backtrace:\n   0: app::main\n").map(|x| x.0),
+            Some(vec![StackFrame {
+                level: 0,
+                method: "app::main".into(),
+                assembly: Some("app".into()),
+                file_name: None,
+                line: None,
+            }])
+        );
+    }
+
+    #[test]
+    fn trim_from_the_middle() {
+        let stack: String = (0..1000)
+            .map(|i| {
+                format!("  {i}: app::module::function_{i}\n             at ./src/module.rs:{i}:1\n")
+            })
+            .collect();
+        let (frames, has_full_stack) = parse_stack(&stack).unwrap();
+        assert!(!has_full_stack);
+        assert!(frames.len() < 1000);
+        assert!(frames.iter().map(StackFrame::len).sum::<usize>() <= MAX_PARSED_STACK_LEN + 100);
+        assert_eq!(frames.first().unwrap().level, 0);
+        assert_eq!(frames.last().unwrap().level, 999);
+        assert!(frames.windows(2).all(|w| w[0].level < w[1].level));
+    }
+
+    #[test]
+    fn locations() {
+        assert_eq!(
+            parse_location("./src/main.rs:4:5"),
+            ("./src/main.rs", Some(4))
+        );
+        assert_eq!(
+            parse_location("./src/main.rs:4"),
+            ("./src/main.rs", Some(4))
+        );
+        assert_eq!(
+            parse_location("C:\\src\\main.rs:4:5"),
+            ("C:\\src\\main.rs", Some(4))
+        );
+        assert_eq!(parse_location("./src/main.rs"), ("./src/main.rs", None));
+    }
+}
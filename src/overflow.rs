@@ -0,0 +1,218 @@
+use crate::models::{
+    floor_char_boundary, truncate, Envelope, Properties, MAX_PROPERTY_KEY_LEN, MAX_PROPERTY_PARTS,
+    MAX_PROPERTY_VALUE_LEN,
+};
+
+/// How custom property values longer than the maximum length of 8192 bytes are sent.
+///
+/// Configure it with [`Exporter::with_overflow_policy`](crate::Exporter::with_overflow_policy).
+/// Telemetry items with such values are counted in [`SignalStats::truncated`] regardless of the
+/// policy.
+///
+/// [`SignalStats::truncated`]: crate::SignalStats::truncated
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OverflowPolicy {
+    /// Cut values at the maximum length.
+    #[default]
+    Truncate,
+    /// Cut values so that they end with the marker, e.g. `"…"`, to make truncated values
+    /// recognizable.
+    Marker(String),
+    /// Split values across the properties `key`, `key_1`, `key_2`, …, with at most 10 properties
+    /// per value. Longer values are truncated. Keys of existing properties are skipped, so e.g. a
+    /// property `key_1` set by the application is never overwritten.
+    ///
+    /// Values of up to 80 KiB are kept in memory until the policy is applied, right before
+    /// telemetry items are sent or written to a sink. With the other policies, values are cut
+    /// when telemetry items are created.
+    Split,
+}
+
+impl OverflowPolicy {
+    /// Number of bytes of custom property values the policy needs. Longer values are cut when
+    /// telemetry items are created, so they don't hold more memory than necessary until the
+    /// policy is applied.
+    pub(crate) fn max_value_len(&self) -> usize {
+        match self {
+            OverflowPolicy::Truncate | OverflowPolicy::Marker(_) => MAX_PROPERTY_VALUE_LEN,
+            OverflowPolicy::Split => MAX_PROPERTY_VALUE_LEN * MAX_PROPERTY_PARTS,
+        }
+    }
+
+    /// Applies the policy to the custom properties of the envelope. Returns whether any property
+    /// value was too long.
+    pub(crate) fn apply(&self, envelope: &mut Envelope) -> bool {
+        let Some(properties) = envelope
+            .data
+            .as_mut()
+            .and_then(|data| data.properties_mut().as_mut())
+        else {
+            return false;
+        };
+        let overflowed: Vec<_> = properties
+            .iter()
+            .filter(|(_, value)| value.is_truncated())
+            .map(|(key, value)| (key.clone(), value.as_untruncated().to_string()))
+            .collect();
+        if overflowed.is_empty() {
+            return false;
+        }
+
+        match self {
+            OverflowPolicy::Truncate => {
+                for (key, value) in overflowed {
+                    properties.insert(key, truncate(&value, MAX_PROPERTY_VALUE_LEN).into());
+                }
+            }
+            OverflowPolicy::Marker(marker) => {
+                for (key, value) in overflowed {
+                    let len = MAX_PROPERTY_VALUE_LEN.saturating_sub(marker.len());
+                    let value = format!("{}{}", truncate(&value, len), marker);
+                    properties.insert(key, value.into());
+                }
+            }
+            OverflowPolicy::Split => {
+                for (key, value) in overflowed {
+                    split(properties, key.as_ref(), &value);
+                }
+            }
+        }
+        true
+    }
+}
+
+fn split(properties: &mut Properties, key: &str, mut value: &str) {
+    let mut suffix = 0;
+    for part in 0..MAX_PROPERTY_PARTS {
+        if value.is_empty() {
+            break;
+        }
+        let (head, tail) = value.split_at(floor_char_boundary(value, MAX_PROPERTY_VALUE_LEN));
+        if head.is_empty() {
+            break;
+        }
+        let part_key = if part == 0 {
+            key.into()
+        } else {
+            loop {
+                suffix += 1;
+                let suffix = format!("_{suffix}");
+                let base = truncate(key, MAX_PROPERTY_KEY_LEN - suffix.len());
+                let part_key = format!("{base}{suffix}").into();
+                if !properties.contains_key(&part_key) {
+                    break part_key;
+                }
+            }
+        };
+        properties.insert(part_key, head.into());
+        value = tail;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Data, MessageData};
+
+    fn envelope(value: String) -> Envelope {
+        let mut properties = Properties::new();
+        properties.insert("short".into(), "value".into());
+        properties.insert("long".into(), value.into());
+        Envelope {
+            name: "Microsoft.ApplicationInsights.Message",
+            time: "2025-01-01T00:00:00Z".into(),
+            sample_rate: None,
+            i_key: None,
+            tags: None,
+            data: Some(Data::Message(MessageData {
+                ver: 2,
+                message: "message".into(),
+                severity_level: None,
+                properties: Some(properties),
+            })),
+        }
+    }
+
+    fn properties(envelope: &Envelope) -> Vec<(&str, &str)> {
+        envelope
+            .data
+            .as_ref()
+            .unwrap()
+            .properties()
+            .unwrap()
+            .iter()
+            .map(|(key, value)| (key.as_ref(), value.as_ref()))
+            .collect()
+    }
+
+    #[test]
+    fn short_values() {
+        let mut item = envelope("value".into());
+        assert!(!OverflowPolicy::Split.apply(&mut item));
+        assert_eq!(
+            properties(&item),
+            vec![("long", "value"), ("short", "value")]
+        );
+    }
+
+    #[test]
+    fn truncate_values() {
+        let mut item = envelope(format!("{}é", "a".repeat(8191)));
+        assert!(OverflowPolicy::Truncate.apply(&mut item));
+        assert_eq!(properties(&item)[0].1, "a".repeat(8191));
+        let properties = item.data.as_ref().unwrap().properties().unwrap();
+        assert!(properties
+            .values()
+            .all(|value| value.as_untruncated().len() <= MAX_PROPERTY_VALUE_LEN));
+    }
+
+    #[test]
+    fn marker_values() {
+        let mut item = envelope("a".repeat(9000));
+        assert!(OverflowPolicy::Marker("…".into()).apply(&mut item));
+        let value = properties(&item)[0].1;
+        assert_eq!(value.len(), 8192);
+        assert!(value.ends_with("a…"));
+    }
+
+    #[test]
+    fn split_values() {
+        let mut item = envelope(format!("{}{}", "a".repeat(8192), "b".repeat(100)));
+        assert!(OverflowPolicy::Split.apply(&mut item));
+        assert_eq!(
+            properties(&item),
+            vec![
+                ("long", "a".repeat(8192).as_str()),
+                ("long_1", "b".repeat(100).as_str()),
+                ("short", "value")
+            ]
+        );
+
+        let mut item = envelope("a".repeat(8192 * 20));
+        assert!(OverflowPolicy::Split.apply(&mut item));
+        let properties = properties(&item);
+        assert_eq!(properties.len(), 11);
+        assert_eq!(properties[9].0, "long_9");
+    }
+
+    #[test]
+    fn split_keeps_existing_properties() {
+        let mut item = envelope("a".repeat(8192 * 3));
+        if let Some(Data::Message(data)) = item.data.as_mut() {
+            let properties = data.properties.as_mut().unwrap();
+            properties.insert("long_1".into(), "user".into());
+        }
+        assert!(OverflowPolicy::Split.apply(&mut item));
+        assert_eq!(
+            properties(&item),
+            vec![
+                ("long", "a".repeat(8192).as_str()),
+                ("long_1", "user"),
+                ("long_2", "a".repeat(8192).as_str()),
+                ("long_3", "a".repeat(8192).as_str()),
+                ("short", "value")
+            ]
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Request bodies sent to the ingestion endpoint.
+//!
+//! Telemetry items of an export are serialized to JSON one after another into a single buffer,
+//! which holds the uncompressed JSON of all items. Request bodies are then compressed from slices
+//! of this buffer, which allows splitting large batches and retrying only some items. Peak memory
+//! is therefore about the same as serializing the whole JSON array before compressing it. Both
+//! buffers are returned to a [`BufferPool`] after an export and reused by the next one, and a
+//! request body is reused for retries as long as the same items are sent.
+
+use crate::Error;
+use flate2::{read::GzDecoder, write::GzEncoder};
+use std::{
+    borrow::Cow,
+    io::{self, Read, Write},
+    sync::Mutex,
+};
+
+/// Maximum number of buffers kept in a pool. An export needs two buffers (serialized items and
+/// request body), so this allows a few concurrent exports without allocating.
+const MAX_POOLED_BUFFERS: usize = 8;
+
+/// Buffers larger than this are not kept in a pool, so a single unusually large export doesn't
+/// keep its memory allocated forever.
+const MAX_POOLED_CAPACITY: usize = 16 * 1024 * 1024;
+
+/// The first two bytes of every gzip stream. JSON never starts with these.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Compression of request bodies.
+///
+/// Application Insights accepts both compressed and uncompressed request bodies. Compression
+/// usually reduces the size of request bodies by about 90%, at the cost of some CPU time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Send request bodies uncompressed. Useful for local stand-ins of the ingestion endpoint.
+    None,
+    /// Gzip with the fastest compression level.
+    Fast,
+    /// Gzip with the default compression level.
+    #[default]
+    Default,
+    /// Gzip with the best compression level.
+    Best,
+}
+
+impl Compression {
+    fn level(self) -> Option<flate2::Compression> {
+        match self {
+            Self::None => None,
+            Self::Fast => Some(flate2::Compression::fast()),
+            Self::Default => Some(flate2::Compression::default()),
+            Self::Best => Some(flate2::Compression::best()),
+        }
+    }
+}
+
+/// Buffers, which are reused across exports.
+#[derive(Debug, Default)]
+pub(crate) struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    /// Takes an empty buffer from the pool or allocates a new one.
+    pub(crate) fn take(&self) -> Vec<u8> {
+        self.buffers.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    /// Returns a buffer to the pool.
+    pub(crate) fn give(&self, mut buffer: Vec<u8>) {
+        if buffer.capacity() == 0 || buffer.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+        buffer.clear();
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < MAX_POOLED_BUFFERS {
+            buffers.push(buffer);
+        }
+    }
+}
+
+/// Writes a request body, compressing it if configured.
+pub(crate) enum PayloadWriter {
+    Plain(Vec<u8>),
+    Gzip(GzEncoder<Vec<u8>>),
+}
+
+impl PayloadWriter {
+    pub(crate) fn new(compression: Compression, buffer: Vec<u8>) -> Self {
+        match compression.level() {
+            Some(level) => Self::Gzip(GzEncoder::new(buffer, level)),
+            None => Self::Plain(buffer),
+        }
+    }
+
+    pub(crate) fn finish(self) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Plain(buffer) => Ok(buffer),
+            Self::Gzip(encoder) => encoder.finish().map_err(Error::UploadCompressRequest),
+        }
+    }
+}
+
+impl Write for PayloadWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(buffer) => buffer.write(buf),
+            Self::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Plain(buffer) => buffer.write_all(buf),
+            Self::Gzip(encoder) => encoder.write_all(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(buffer) => buffer.flush(),
+            Self::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Builds a request body from JSON that is already serialized, e.g. for partial retries and live
+/// metrics.
+pub(crate) fn serialize_request_body(
+    data: &[u8],
+    compression: Compression,
+) -> Result<Vec<u8>, Error> {
+    let mut writer = PayloadWriter::new(compression, Vec::new());
+    writer
+        .write_all(data)
+        .map_err(Error::UploadCompressRequest)?;
+    writer.finish()
+}
+
+/// Returns whether a request body is gzip compressed.
+pub(crate) fn is_compressed(payload: &[u8]) -> bool {
+    payload.starts_with(&GZIP_MAGIC)
+}
+
+/// Returns the JSON of a request body, decompressing it if necessary.
+pub(crate) fn decode(payload: &[u8]) -> io::Result<Cow<'_, [u8]>> {
+    if !is_compressed(payload) {
+        return Ok(Cow::Borrowed(payload));
+    }
+
+    let mut decoded = Vec::new();
+    GzDecoder::new(payload).read_to_end(&mut decoded)?;
+    Ok(Cow::Owned(decoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_case::test_case;
+
+    #[test_case(Compression::None, false ; "none")]
+    #[test_case(Compression::Fast, true ; "fast")]
+    #[test_case(Compression::Default, true ; "default")]
+    #[test_case(Compression::Best, true ; "best")]
+    fn roundtrip(compression: Compression, compressed: bool) {
+        let data = br#"[{"name":"Test"}]"#;
+        let payload = serialize_request_body(data, compression).unwrap();
+        assert_eq!(is_compressed(&payload), compressed);
+        assert_eq!(decode(&payload).unwrap().as_ref(), data);
+    }
+
+    #[test]
+    fn reuse_buffers() {
+        let pool = BufferPool::default();
+        let mut buffer = pool.take();
+        buffer.extend_from_slice(b"data");
+        let capacity = buffer.capacity();
+        pool.give(buffer);
+
+        let buffer = pool.take();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.capacity(), capacity);
+        assert_eq!(pool.take().capacity(), 0);
+    }
+
+    #[test]
+    fn drop_large_buffers() {
+        let pool = BufferPool::default();
+        pool.give(Vec::with_capacity(MAX_POOLED_CAPACITY + 1));
+        assert_eq!(pool.take().capacity(), 0);
+    }
+}
@@ -0,0 +1,605 @@
+#[cfg(any(feature = "trace", feature = "logs"))]
+use crate::models::{self, Measurements};
+use crate::models::{
+    context_tag_keys::{Tags, TAG_KEY_LOOKUP},
+    Data, Envelope, Properties,
+};
+use std::sync::Arc;
+
+pub(crate) type Processors = Vec<Arc<dyn TelemetryProcessor>>;
+
+/// Processes telemetry items after they were converted to the Application Insights data model and
+/// before they are sent, similar to telemetry processors and initializers in the Application
+/// Insights SDKs.
+///
+/// Processors run for traces, logs and metrics in the order they were added with
+/// [`Exporter::with_telemetry_processor`](crate::Exporter::with_telemetry_processor). They can
+/// modify items, e.g. to fix up names or add tags, or drop them.
+///
+/// ```
+/// use opentelemetry_application_insights::{Decision, TelemetryItem, TelemetryKind};
+///
+/// // Drop in-process dependencies.
+/// let processor = |item: &mut TelemetryItem<'_>| {
+///     if item.kind() == TelemetryKind::Dependency && item.dependency_type() == Some("InProc") {
+///         Decision::Drop
+///     } else {
+///         Decision::Keep
+///     }
+/// };
+/// ```
+pub trait TelemetryProcessor: Send + Sync + 'static {
+    /// Process a telemetry item. Return [`Decision::Drop`] to not send it. Later processors don't
+    /// see dropped items. Dropped items are counted in
+    /// [`SignalStats::dropped`](crate::SignalStats::dropped).
+    fn process(&self, item: &mut TelemetryItem<'_>) -> Decision;
+}
+
+impl<F> TelemetryProcessor for F
+where
+    F: Fn(&mut TelemetryItem<'_>) -> Decision + Send + Sync + 'static,
+{
+    fn process(&self, item: &mut TelemetryItem<'_>) -> Decision {
+        self(item)
+    }
+}
+
+/// Whether a [`TelemetryProcessor`] keeps or drops a telemetry item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// Send the item.
+    Keep,
+    /// Don't send the item.
+    Drop,
+}
+
+/// Application Insights telemetry type of a [`TelemetryItem`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TelemetryKind {
+    /// Request telemetry, e.g. from server spans.
+    Request,
+    /// Dependency telemetry, e.g. from client and internal spans.
+    Dependency,
+    /// Custom event telemetry.
+    Event,
+    /// Exception telemetry.
+    Exception,
+    /// Trace (message) telemetry, e.g. from span events and logs.
+    Trace,
+    /// Metric telemetry.
+    Metric,
+    /// Availability test result telemetry.
+    Availability,
+    /// Page view telemetry.
+    PageView,
+    /// Page view performance telemetry.
+    PageViewPerformance,
+}
+
+/// Severity level of exceptions and trace messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeverityLevel {
+    /// Verbose
+    Verbose,
+    /// Information
+    Information,
+    /// Warning
+    Warning,
+    /// Error
+    Error,
+    /// Critical
+    Critical,
+}
+
+#[cfg(any(feature = "trace", feature = "logs"))]
+impl From<SeverityLevel> for models::SeverityLevel {
+    fn from(severity_level: SeverityLevel) -> Self {
+        match severity_level {
+            SeverityLevel::Verbose => models::SeverityLevel::Verbose,
+            SeverityLevel::Information => models::SeverityLevel::Information,
+            SeverityLevel::Warning => models::SeverityLevel::Warning,
+            SeverityLevel::Error => models::SeverityLevel::Error,
+            SeverityLevel::Critical => models::SeverityLevel::Critical,
+        }
+    }
+}
+
+#[cfg(any(feature = "trace", feature = "logs"))]
+impl From<&models::SeverityLevel> for SeverityLevel {
+    fn from(severity_level: &models::SeverityLevel) -> Self {
+        match severity_level {
+            models::SeverityLevel::Verbose => SeverityLevel::Verbose,
+            models::SeverityLevel::Information => SeverityLevel::Information,
+            models::SeverityLevel::Warning => SeverityLevel::Warning,
+            models::SeverityLevel::Error => SeverityLevel::Error,
+            models::SeverityLevel::Critical => SeverityLevel::Critical,
+        }
+    }
+}
+
+/// Exception of an exception telemetry item, see [`TelemetryItem::exceptions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ExceptionInfo<'a> {
+    /// Type name of the exception.
+    pub type_name: &'a str,
+    /// Message of the exception.
+    pub message: &'a str,
+    /// Call stack of the exception.
+    pub stack: Option<&'a str>,
+}
+
+/// Mutable view of a telemetry item passed to a [`TelemetryProcessor`].
+///
+/// Values are truncated to the maximum lengths of the Application Insights schema.
+#[derive(Debug)]
+pub struct TelemetryItem<'a> {
+    envelope: &'a mut Envelope,
+}
+
+impl<'a> TelemetryItem<'a> {
+    pub(crate) fn new(envelope: &'a mut Envelope) -> Self {
+        Self { envelope }
+    }
+
+    /// Type name of the envelope, e.g. `Microsoft.ApplicationInsights.RemoteDependency`.
+    pub fn envelope_name(&self) -> &str {
+        self.envelope.name
+    }
+
+    /// Telemetry type of the item.
+    pub fn kind(&self) -> TelemetryKind {
+        match self.envelope.data {
+            #[cfg(feature = "trace")]
+            Some(Data::Availability(_)) => TelemetryKind::Availability,
+            #[cfg(feature = "trace")]
+            Some(Data::Event(_)) => TelemetryKind::Event,
+            #[cfg(any(feature = "trace", feature = "logs"))]
+            Some(Data::Exception(_)) => TelemetryKind::Exception,
+            #[cfg(any(feature = "trace", feature = "logs"))]
+            Some(Data::Message(_)) => TelemetryKind::Trace,
+            Some(Data::Metric(_)) => TelemetryKind::Metric,
+            #[cfg(feature = "trace")]
+            Some(Data::PageView(_)) => TelemetryKind::PageView,
+            #[cfg(feature = "trace")]
+            Some(Data::PageViewPerf(_)) => TelemetryKind::PageViewPerformance,
+            #[cfg(feature = "trace")]
+            Some(Data::RemoteDependency(_)) => TelemetryKind::Dependency,
+            #[cfg(feature = "trace")]
+            Some(Data::Request(_)) => TelemetryKind::Request,
+            None => unreachable!("telemetry items are always created with data"),
+        }
+    }
+
+    /// Name of a request, dependency, event, availability test result, page view or page view
+    /// performance item. `None` for other telemetry types.
+    pub fn name(&self) -> Option<&str> {
+        match self.envelope.data {
+            #[cfg(feature = "trace")]
+            Some(Data::Availability(ref data)) => Some(data.name.as_ref()),
+            #[cfg(feature = "trace")]
+            Some(Data::Event(ref data)) => Some(data.name.as_ref()),
+            #[cfg(feature = "trace")]
+            Some(Data::PageView(ref data)) => Some(data.name.as_ref()),
+            #[cfg(feature = "trace")]
+            Some(Data::PageViewPerf(ref data)) => Some(data.name.as_ref()),
+            #[cfg(feature = "trace")]
+            Some(Data::RemoteDependency(ref data)) => Some(data.name.as_ref()),
+            #[cfg(feature = "trace")]
+            Some(Data::Request(ref data)) => data.name.as_ref().map(AsRef::as_ref),
+            _ => None,
+        }
+    }
+
+    /// Set the name of a request, dependency, event, availability test result, page view or page
+    /// view performance item. Does nothing for other telemetry types.
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        #[cfg(feature = "trace")]
+        match self.envelope.data {
+            Some(Data::Availability(ref mut data)) => data.name = name.into().into(),
+            Some(Data::Event(ref mut data)) => data.name = name.into().into(),
+            Some(Data::PageView(ref mut data)) => data.name = name.into().into(),
+            Some(Data::PageViewPerf(ref mut data)) => data.name = name.into().into(),
+            Some(Data::RemoteDependency(ref mut data)) => data.name = name.into().into(),
+            Some(Data::Request(ref mut data)) => data.name = Some(name.into().into()),
+            _ => {}
+        }
+        #[cfg(not(feature = "trace"))]
+        let _ = name;
+    }
+
+    /// Type of a dependency, e.g. `HTTP` or `InProc`. `None` for other telemetry types.
+    pub fn dependency_type(&self) -> Option<&str> {
+        match self.envelope.data {
+            #[cfg(feature = "trace")]
+            Some(Data::RemoteDependency(ref data)) => data.type_.as_ref().map(AsRef::as_ref),
+            _ => None,
+        }
+    }
+
+    /// Target of a dependency, e.g. the host of an HTTP request or the database server. `None` for
+    /// other telemetry types.
+    pub fn target(&self) -> Option<&str> {
+        match self.envelope.data {
+            #[cfg(feature = "trace")]
+            Some(Data::RemoteDependency(ref data)) => data.target.as_ref().map(AsRef::as_ref),
+            _ => None,
+        }
+    }
+
+    /// Command of a dependency, e.g. the URL of an HTTP request or a database statement. `None`
+    /// for other telemetry types.
+    pub fn data(&self) -> Option<&str> {
+        match self.envelope.data {
+            #[cfg(feature = "trace")]
+            Some(Data::RemoteDependency(ref data)) => data.data.as_ref().map(AsRef::as_ref),
+            _ => None,
+        }
+    }
+
+    /// URL of a request, page view or page view performance item. `None` for other telemetry
+    /// types.
+    pub fn url(&self) -> Option<&str> {
+        match self.envelope.data {
+            #[cfg(feature = "trace")]
+            Some(Data::PageView(ref data)) => data.url.as_ref().map(AsRef::as_ref),
+            #[cfg(feature = "trace")]
+            Some(Data::PageViewPerf(ref data)) => data.url.as_ref().map(AsRef::as_ref),
+            #[cfg(feature = "trace")]
+            Some(Data::Request(ref data)) => data.url.as_ref().map(AsRef::as_ref),
+            _ => None,
+        }
+    }
+
+    /// Response code of a request or result code of a dependency, e.g. an HTTP status code.
+    /// `None` for other telemetry types.
+    pub fn result_code(&self) -> Option<&str> {
+        match self.envelope.data {
+            #[cfg(feature = "trace")]
+            Some(Data::RemoteDependency(ref data)) => data.result_code.as_ref().map(AsRef::as_ref),
+            #[cfg(feature = "trace")]
+            Some(Data::Request(ref data)) => Some(data.response_code.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// Whether a request, dependency or availability test succeeded. `None` for other telemetry
+    /// types.
+    pub fn success(&self) -> Option<bool> {
+        match self.envelope.data {
+            #[cfg(feature = "trace")]
+            Some(Data::Availability(ref data)) => Some(data.success),
+            #[cfg(feature = "trace")]
+            Some(Data::RemoteDependency(ref data)) => data.success,
+            #[cfg(feature = "trace")]
+            Some(Data::Request(ref data)) => Some(data.success),
+            _ => None,
+        }
+    }
+
+    /// Message of a trace or availability test result. `None` for other telemetry types. Use
+    /// [`TelemetryItem::exceptions`] for the messages of exceptions.
+    pub fn message(&self) -> Option<&str> {
+        match self.envelope.data {
+            #[cfg(feature = "trace")]
+            Some(Data::Availability(ref data)) => data.message.as_ref().map(AsRef::as_ref),
+            #[cfg(any(feature = "trace", feature = "logs"))]
+            Some(Data::Message(ref data)) => Some(data.message.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// Severity level of a trace or exception. `None` for other telemetry types.
+    pub fn severity_level(&self) -> Option<SeverityLevel> {
+        match self.envelope.data {
+            #[cfg(any(feature = "trace", feature = "logs"))]
+            Some(Data::Exception(ref data)) => data.severity_level.as_ref().map(Into::into),
+            #[cfg(any(feature = "trace", feature = "logs"))]
+            Some(Data::Message(ref data)) => data.severity_level.as_ref().map(Into::into),
+            _ => None,
+        }
+    }
+
+    /// Exceptions of an exception item, starting with the outermost exception and followed by
+    /// its causes. Empty for other telemetry types.
+    pub fn exceptions(&self) -> impl Iterator<Item = ExceptionInfo<'_>> {
+        #[cfg(any(feature = "trace", feature = "logs"))]
+        let exceptions = match self.envelope.data {
+            Some(Data::Exception(ref data)) => data.exceptions.as_slice(),
+            _ => &[],
+        };
+        #[cfg(any(feature = "trace", feature = "logs"))]
+        return exceptions.iter().map(|exception| ExceptionInfo {
+            type_name: exception.type_name.as_ref(),
+            message: exception.message.as_ref(),
+            stack: exception.stack.as_ref().map(AsRef::as_ref),
+        });
+        #[cfg(not(any(feature = "trace", feature = "logs")))]
+        std::iter::empty()
+    }
+
+    /// Get a custom measurement.
+    pub fn measurement(&self, key: &str) -> Option<f64> {
+        #[cfg(any(feature = "trace", feature = "logs"))]
+        return self.measurements()?.get(&key.into()).copied();
+        #[cfg(not(any(feature = "trace", feature = "logs")))]
+        {
+            let _ = key;
+            None
+        }
+    }
+
+    /// Iterate over all custom measurements.
+    pub fn measurements_iter(&self) -> impl Iterator<Item = (&str, f64)> {
+        #[cfg(any(feature = "trace", feature = "logs"))]
+        let measurements = self.measurements();
+        #[cfg(not(any(feature = "trace", feature = "logs")))]
+        let measurements: Option<&std::collections::BTreeMap<String, f64>> = None;
+        measurements
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| (key.as_ref(), *value))
+    }
+
+    /// Set a custom measurement. Does nothing for telemetry types without measurements, i.e.
+    /// traces and metrics.
+    pub fn set_measurement(&mut self, key: impl Into<String>, value: f64) {
+        #[cfg(any(feature = "trace", feature = "logs"))]
+        if let Some(measurements) = self.measurements_mut() {
+            measurements
+                .get_or_insert_with(Default::default)
+                .insert(key.into().into(), value);
+        }
+        #[cfg(not(any(feature = "trace", feature = "logs")))]
+        let _ = (key, value);
+    }
+
+    /// Remove a custom measurement and return its value.
+    pub fn remove_measurement(&mut self, key: &str) -> Option<f64> {
+        #[cfg(any(feature = "trace", feature = "logs"))]
+        return self.measurements_mut()?.as_mut()?.remove(&key.into());
+        #[cfg(not(any(feature = "trace", feature = "logs")))]
+        {
+            let _ = key;
+            None
+        }
+    }
+
+    /// Get a context tag, e.g. `ai.cloud.role`.
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.envelope.tags.as_ref()?.get_by_name(key)
+    }
+
+    /// Set a context tag, e.g. `ai.cloud.role`. Keys, which are not one of the [`attrs`]
+    /// constants for context tags, are ignored.
+    ///
+    /// [`attrs`]: crate::attrs
+    pub fn set_tag(&mut self, key: &str, value: impl Into<String>) {
+        if let Some(key) = TAG_KEY_LOOKUP.get(key) {
+            self.envelope
+                .tags
+                .get_or_insert_with(Tags::new)
+                .insert(key.clone(), value.into());
+        }
+    }
+
+    /// Remove a context tag and return its value.
+    pub fn remove_tag(&mut self, key: &str) -> Option<String> {
+        self.envelope.tags.as_mut()?.remove_by_name(key)
+    }
+
+    /// Get a custom property.
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties()?.get(&key.into()).map(AsRef::as_ref)
+    }
+
+    /// Iterate over all custom properties.
+    pub fn properties_iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.properties()
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| (key.as_ref(), value.as_ref()))
+    }
+
+    /// Set a custom property. Does nothing for telemetry items without data.
+    pub fn set_property(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        if let Some(properties) = self.properties_mut() {
+            properties
+                .get_or_insert_with(Default::default)
+                .insert(key.into().into(), value.into().into());
+        }
+    }
+
+    /// Remove a custom property and return its value.
+    pub fn remove_property(&mut self, key: &str) -> Option<String> {
+        let properties = self.properties_mut()?.as_mut()?;
+        let value = properties.remove(&key.into())?;
+        Some(value.as_ref().into())
+    }
+
+    fn properties(&self) -> Option<&Properties> {
+        self.envelope.data.as_ref()?.properties()
+    }
+
+    fn properties_mut(&mut self) -> Option<&mut Option<Properties>> {
+        Some(self.envelope.data.as_mut()?.properties_mut())
+    }
+
+    #[cfg(any(feature = "trace", feature = "logs"))]
+    fn measurements(&self) -> Option<&Measurements> {
+        self.envelope.data.as_ref()?.measurements()
+    }
+
+    #[cfg(any(feature = "trace", feature = "logs"))]
+    fn measurements_mut(&mut self) -> Option<&mut Option<Measurements>> {
+        self.envelope.data.as_mut()?.measurements_mut()
+    }
+}
+
+/// Runs all processors on the envelope and returns whether it should be sent.
+pub(crate) fn process(processors: &Processors, envelope: &mut Envelope) -> bool {
+    let mut item = TelemetryItem::new(envelope);
+    processors
+        .iter()
+        .all(|processor| processor.process(&mut item) == Decision::Keep)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        context_tag_keys::CLOUD_ROLE, ExceptionData, ExceptionDetails, MessageData,
+        RemoteDependencyData,
+    };
+
+    fn dependency(type_: &str) -> Envelope {
+        Envelope {
+            name: "Microsoft.ApplicationInsights.RemoteDependency",
+            time: "2025-01-01T00:00:00Z".into(),
+            sample_rate: None,
+            i_key: None,
+            tags: None,
+            data: Some(Data::RemoteDependency(RemoteDependencyData {
+                ver: 2,
+                name: "dependency".into(),
+                id: None,
+                result_code: Some("404".into()),
+                duration: "00.00:00:00.000000".into(),
+                success: Some(false),
+                data: Some("https://example.com/users/1".into()),
+                target: Some("example.com".into()),
+                type_: Some(type_.into()),
+                properties: None,
+                measurements: None,
+            })),
+        }
+    }
+
+    fn message() -> Envelope {
+        let mut tags = Tags::new();
+        tags.insert(CLOUD_ROLE, "role".into());
+        Envelope {
+            name: "Microsoft.ApplicationInsights.Message",
+            time: "2025-01-01T00:00:00Z".into(),
+            sample_rate: None,
+            i_key: None,
+            tags: Some(tags),
+            data: Some(Data::Message(MessageData {
+                ver: 2,
+                message: "message".into(),
+                severity_level: Some(models::SeverityLevel::Warning),
+                properties: None,
+            })),
+        }
+    }
+
+    #[test]
+    fn drop_items() {
+        let processors: Processors = vec![Arc::new(|item: &mut TelemetryItem<'_>| {
+            if item.dependency_type() == Some("InProc") {
+                Decision::Drop
+            } else {
+                Decision::Keep
+            }
+        })];
+
+        assert!(!process(&processors, &mut dependency("InProc")));
+        assert!(process(&processors, &mut dependency("HTTP")));
+        assert!(process(&processors, &mut message()));
+    }
+
+    #[test]
+    fn modify_items() {
+        let processors: Processors = vec![
+            Arc::new(|item: &mut TelemetryItem<'_>| {
+                item.set_name("renamed");
+                item.set_tag("ai.cloud.roleInstance", "instance");
+                item.set_tag("unknown", "ignored");
+                item.set_property("key", "value");
+                Decision::Keep
+            }),
+            Arc::new(|item: &mut TelemetryItem<'_>| {
+                let role = item.remove_tag("ai.cloud.role");
+                item.set_property("role", role.unwrap_or_default());
+                Decision::Keep
+            }),
+        ];
+
+        let mut envelope = dependency("HTTP");
+        assert!(process(&processors, &mut envelope));
+        let item = TelemetryItem::new(&mut envelope);
+        assert_eq!(item.kind(), TelemetryKind::Dependency);
+        assert_eq!(item.name(), Some("renamed"));
+        assert_eq!(item.tag("ai.cloud.roleInstance"), Some("instance"));
+        assert_eq!(item.tag("unknown"), None);
+        assert_eq!(
+            item.properties_iter().collect::<Vec<_>>(),
+            vec![("key", "value"), ("role", "")]
+        );
+
+        let mut envelope = message();
+        assert!(process(&processors, &mut envelope));
+        let mut item = TelemetryItem::new(&mut envelope);
+        assert_eq!(item.kind(), TelemetryKind::Trace);
+        assert_eq!(item.name(), None);
+        assert_eq!(item.tag("ai.cloud.role"), None);
+        assert_eq!(item.property("role"), Some("role"));
+        assert_eq!(item.remove_property("key"), Some("value".into()));
+        assert_eq!(item.property("key"), None);
+    }
+
+    #[test]
+    fn read_typed_fields() {
+        let mut envelope = dependency("HTTP");
+        let mut item = TelemetryItem::new(&mut envelope);
+        assert_eq!(item.target(), Some("example.com"));
+        assert_eq!(item.data(), Some("https://example.com/users/1"));
+        assert_eq!(item.result_code(), Some("404"));
+        assert_eq!(item.success(), Some(false));
+        assert_eq!(item.url(), None);
+        assert_eq!(item.message(), None);
+        item.set_measurement("size", 42.0);
+        assert_eq!(item.measurement("size"), Some(42.0));
+        assert_eq!(
+            item.measurements_iter().collect::<Vec<_>>(),
+            vec![("size", 42.0)]
+        );
+        assert_eq!(item.remove_measurement("size"), Some(42.0));
+        assert_eq!(item.measurement("size"), None);
+
+        let mut envelope = message();
+        let mut item = TelemetryItem::new(&mut envelope);
+        assert_eq!(item.message(), Some("message"));
+        assert_eq!(item.severity_level(), Some(SeverityLevel::Warning));
+        assert_eq!(item.target(), None);
+        item.set_measurement("size", 42.0);
+        assert_eq!(item.measurement("size"), None);
+
+        let mut envelope = Envelope {
+            name: "Microsoft.ApplicationInsights.Exception",
+            time: "2025-01-01T00:00:00Z".into(),
+            sample_rate: None,
+            i_key: None,
+            tags: None,
+            data: Some(Data::Exception(ExceptionData {
+                ver: 2,
+                exceptions: vec![
+                    ExceptionDetails::new("ConfigError".into(), "cannot read config".into(), None),
+                    ExceptionDetails::new("io::Error".into(), "no such file".into(), None),
+                ],
+                severity_level: Some(models::SeverityLevel::Error),
+                problem_id: None,
+                properties: None,
+                measurements: None,
+            })),
+        };
+        let item = TelemetryItem::new(&mut envelope);
+        assert_eq!(item.severity_level(), Some(SeverityLevel::Error));
+        let exceptions: Vec<_> = item.exceptions().collect();
+        assert_eq!(exceptions.len(), 2);
+        assert_eq!(exceptions[0].type_name, "ConfigError");
+        assert_eq!(exceptions[1].message, "no such file");
+        assert_eq!(exceptions[1].stack, None);
+    }
+}
@@ -1,5 +1,7 @@
 use crate::{
+    credential::{missing_credential_error, TokenCache},
     models::{context_tag_keys, QuickPulseEnvelope, QuickPulseMetric},
+    replace_host,
     tags::get_tags_for_resource,
     trace::{get_duration, is_remote_dependency_success, is_request_success, EVENT_NAME_EXCEPTION},
     uploader_quick_pulse::{self, PostOrPing},
@@ -96,11 +98,19 @@ impl<R: RuntimeChannel> LiveMetricsSpanProcessor<R> {
             resource_data: (&exporter.resource).into(),
         }));
         let shared = shared_outer.clone();
+        let requires_credential = exporter.aad_scope.is_some();
+        if requires_credential && exporter.credential.is_none() {
+            let err = missing_credential_error();
+            let err: &dyn std::error::Error = &err;
+            opentelemetry::otel_warn!(name: "ApplicationInsights.LiveMetrics.MissingTokenCredential", error = err);
+        }
         runtime.spawn(Box::pin(async move {
             let mut sender = Sender::new(
                 exporter.client,
                 exporter.live_post_endpoint,
                 exporter.live_ping_endpoint,
+                exporter.credential,
+                requires_credential,
             );
 
             let message_receiver = message_receiver.fuse();
@@ -118,9 +128,11 @@ impl<R: RuntimeChannel> LiveMetricsSpanProcessor<R> {
                         let (resource_data, metrics) = {
                             let mut shared = shared.lock().unwrap();
                             let resource_data = shared.resource_data.clone();
-                            let metrics = curr_is_collecting
-                                .then(|| shared.metrics_collector.collect_and_reset())
-                                .unwrap_or_default();
+                            let metrics = if curr_is_collecting {
+                                shared.metrics_collector.collect_and_reset()
+                            } else {
+                                Vec::new()
+                            };
                             (resource_data, metrics)
                         };
                         let (next_is_collecting, next_timeout) = sender
@@ -222,17 +234,27 @@ struct Sender<C: HttpClient + 'static> {
     client: Arc<C>,
     live_post_endpoint: http::Uri,
     live_ping_endpoint: http::Uri,
+    credential: Option<Arc<TokenCache>>,
+    requires_credential: bool,
     last_success_time: SystemTime,
     polling_interval_hint: Option<Duration>,
     stream_id: String,
 }
 
 impl<C: HttpClient + 'static> Sender<C> {
-    fn new(client: Arc<C>, live_post_endpoint: http::Uri, live_ping_endpoint: http::Uri) -> Self {
+    fn new(
+        client: Arc<C>,
+        live_post_endpoint: http::Uri,
+        live_ping_endpoint: http::Uri,
+        credential: Option<Arc<TokenCache>>,
+        requires_credential: bool,
+    ) -> Self {
         Self {
             client,
             live_post_endpoint,
             live_ping_endpoint,
+            credential,
+            requires_credential,
             last_success_time: SystemTime::now(),
             polling_interval_hint: None,
             stream_id: format!("{:032x}", RandomIdGenerator::default().new_trace_id()),
@@ -261,21 +283,28 @@ impl<C: HttpClient + 'static> Sender<C> {
             role_name: resource_data.role_name,
         };
 
-        let res = uploader_quick_pulse::send(
-            self.client.as_ref(),
-            if is_collecting {
-                &self.live_post_endpoint
-            } else {
-                &self.live_ping_endpoint
-            },
-            if is_collecting {
-                PostOrPing::Post
-            } else {
-                PostOrPing::Ping
-            },
-            envelope,
-        )
-        .await;
+        // Never send unauthenticated requests to resources that require Microsoft Entra ID
+        // authentication.
+        let res = if self.requires_credential && self.credential.is_none() {
+            Err(missing_credential_error())
+        } else {
+            uploader_quick_pulse::send(
+                self.client.as_ref(),
+                if is_collecting {
+                    &self.live_post_endpoint
+                } else {
+                    &self.live_ping_endpoint
+                },
+                if is_collecting {
+                    PostOrPing::Post
+                } else {
+                    PostOrPing::Ping
+                },
+                envelope,
+                self.credential.as_deref(),
+            )
+            .await
+        };
         let (last_send_succeeded, mut next_is_collecting) = if let Ok(res) = res {
             self.last_success_time = now;
             if let Some(redirected_host) = res.redirected_host {
@@ -477,11 +506,3 @@ impl MetricsCollector {
         });
     }
 }
-
-fn replace_host(uri: http::Uri, new_host: http::Uri) -> http::Uri {
-    let mut parts = uri.into_parts();
-    let new_parts = new_host.into_parts();
-    parts.scheme = new_parts.scheme;
-    parts.authority = new_parts.authority;
-    http::Uri::from_parts(parts).expect("valid uri + valid uri = valid uri")
-}
@@ -0,0 +1,155 @@
+use crate::uploader::{
+    STATUS_APPLICATION_INACTIVE, STATUS_INTERNAL_SERVER_ERROR, STATUS_PARTIAL_CONTENT,
+    STATUS_REQUEST_TIMEOUT, STATUS_SERVICE_UNAVAILABLE, STATUS_TOO_MANY_REQUESTS,
+};
+use backon::{BackoffBuilder, ExponentialBackoff, ExponentialBuilder};
+use std::time::Duration;
+
+const DEFAULT_MIN_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(5);
+// No total delay should be needed, because the batch span processor already enforces a
+// `max_export_timeout`. However, as of `opentelemetry_sdk` v0.30.0:
+// - the option is only respected for ::span_processor_with_async_runtime::BatchSpanProcessor
+// - the option doesn't exist for metric or log exports or the SimpleSpanProcessor
+// Therefore, default to a total delay, which is slightly larger than the default
+// `max_export_timeout`.
+const DEFAULT_TOTAL_DELAY: Duration = Duration::from_secs(35);
+const DEFAULT_RETRYABLE_STATUS_CODES: [u16; 6] = [
+    STATUS_PARTIAL_CONTENT,
+    STATUS_REQUEST_TIMEOUT,
+    STATUS_TOO_MANY_REQUESTS,
+    STATUS_APPLICATION_INACTIVE,
+    STATUS_INTERNAL_SERVER_ERROR,
+    STATUS_SERVICE_UNAVAILABLE,
+];
+
+/// Configures how requests to upload telemetry are retried.
+///
+/// Requests are retried with an exponential backoff until they succeed, fail with a status code
+/// that is not retryable, or the maximum number of attempts or the total delay is reached.
+///
+/// ```
+/// use opentelemetry_application_insights::RetryPolicy;
+/// use std::time::Duration;
+///
+/// // Fail fast.
+/// let policy = RetryPolicy::no_retries();
+///
+/// // Keep retrying for longer.
+/// let policy = RetryPolicy::new()
+///     .with_max_delay(Duration::from_secs(30))
+///     .with_total_delay(Duration::from_secs(5 * 60));
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    min_delay: Duration,
+    max_delay: Duration,
+    total_delay: Duration,
+    max_attempts: Option<usize>,
+    jitter: bool,
+    retryable_status_codes: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryPolicy {
+    /// Create the default retry policy.
+    pub fn new() -> Self {
+        Self {
+            min_delay: DEFAULT_MIN_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            total_delay: DEFAULT_TOTAL_DELAY,
+            max_attempts: None,
+            jitter: true,
+            retryable_status_codes: DEFAULT_RETRYABLE_STATUS_CODES.to_vec(),
+        }
+    }
+
+    /// Create a retry policy, which sends every request only once.
+    pub fn no_retries() -> Self {
+        Self::new().with_max_attempts(1)
+    }
+
+    /// Set the delay before the first retry. Later retries wait exponentially longer.
+    ///
+    /// Default: 500 milliseconds.
+    pub fn with_min_delay(mut self, min_delay: Duration) -> Self {
+        self.min_delay = min_delay;
+        self
+    }
+
+    /// Set the maximum delay between two attempts.
+    ///
+    /// Default: 5 seconds.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Set the maximum sum of all delays for one export. No retry is attempted if it would exceed
+    /// this, including when Application Insights asks to wait longer using a `Retry-After` header.
+    /// Large exports, which are split into multiple requests, share this delay.
+    ///
+    /// Default: 35 seconds.
+    pub fn with_total_delay(mut self, total_delay: Duration) -> Self {
+        self.total_delay = total_delay;
+        self
+    }
+
+    /// Set the maximum number of attempts for one request, including the first one. A value of 0
+    /// or 1 disables retries.
+    ///
+    /// Default: unlimited (only bound by the total delay).
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = Some(max_attempts);
+        self
+    }
+
+    /// Set whether delays are randomized, which avoids many clients retrying at the same time.
+    ///
+    /// Default: true.
+    pub fn with_jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the HTTP status codes, which are retried. These apply to the status code of the response
+    /// as well as to the status codes of individual telemetry items in a partial success response.
+    /// Remove 206 to reject all failed items of 206 (partial success) responses instead of
+    /// retrying them.
+    ///
+    /// Default: 206, 408, 429, 439, 500 and 503.
+    pub fn with_retryable_status_codes(
+        mut self,
+        status_codes: impl IntoIterator<Item = u16>,
+    ) -> Self {
+        self.retryable_status_codes = status_codes.into_iter().collect();
+        self
+    }
+
+    pub(crate) fn is_retryable(&self, status_code: u16) -> bool {
+        self.retryable_status_codes.contains(&status_code)
+    }
+
+    pub(crate) fn total_delay(&self) -> Duration {
+        self.total_delay
+    }
+
+    pub(crate) fn backoff(&self) -> ExponentialBackoff {
+        let mut builder = ExponentialBuilder::new()
+            .with_min_delay(self.min_delay)
+            .with_max_delay(self.max_delay);
+        if self.jitter {
+            builder = builder.with_jitter();
+        }
+        builder = match self.max_attempts {
+            Some(max_attempts) => builder.with_max_times(max_attempts.saturating_sub(1)),
+            None => builder.without_max_times(),
+        };
+        builder.build()
+    }
+}
@@ -0,0 +1,276 @@
+//! Writes envelopes to stdout or files instead of sending them to Application Insights.
+//!
+//! Every envelope is written as one JSON document followed by a newline (newline-delimited JSON,
+//! unless pretty-printing is enabled). Envelopes are serialized exactly like in request bodies, so
+//! the output shows what would have been sent.
+
+use crate::models::Envelope;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Configuration for writing telemetry to stdout or files instead of sending it to Application
+/// Insights, e.g. for local development or to inspect telemetry in CI without a real resource.
+///
+/// ```no_run
+/// use opentelemetry_application_insights::EnvelopeSink;
+///
+/// let sink = EnvelopeSink::rotating_file("/tmp/telemetry.ndjson", 10 * 1024 * 1024, 5)
+///     .with_pretty(true);
+/// ```
+#[derive(Debug, Clone)]
+pub struct EnvelopeSink {
+    target: Target,
+    pretty: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Target {
+    Stdout,
+    File(PathBuf),
+    RotatingFile {
+        path: PathBuf,
+        max_size: u64,
+        max_files: usize,
+    },
+}
+
+impl EnvelopeSink {
+    /// Write envelopes to stdout.
+    pub fn stdout() -> Self {
+        Self {
+            target: Target::Stdout,
+            pretty: false,
+        }
+    }
+
+    /// Append envelopes to the given file. The file is created if it does not exist.
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Self {
+            target: Target::File(path.into()),
+            pretty: false,
+        }
+    }
+
+    /// Append envelopes to the given file and rotate it once it would grow larger than
+    /// `max_size` bytes.
+    ///
+    /// Rotated files get the suffix `.1` (newest) up to `.<max_files - 1>` (oldest). Older files
+    /// are deleted.
+    pub fn rotating_file(path: impl Into<PathBuf>, max_size: u64, max_files: usize) -> Self {
+        Self {
+            target: Target::RotatingFile {
+                path: path.into(),
+                max_size,
+                max_files: max_files.max(1),
+            },
+            pretty: false,
+        }
+    }
+
+    /// Set whether envelopes are pretty-printed. Pretty-printed envelopes span multiple lines, so
+    /// the output is no longer newline-delimited JSON.
+    ///
+    /// Default: false.
+    pub fn with_pretty(mut self, pretty: bool) -> Self {
+        self.pretty = pretty;
+        self
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct SinkWriter {
+    config: EnvelopeSink,
+    file: Mutex<Option<OpenFile>>,
+}
+
+#[derive(Debug)]
+struct OpenFile {
+    file: File,
+    size: u64,
+}
+
+impl SinkWriter {
+    pub(crate) fn new(config: EnvelopeSink) -> Self {
+        Self {
+            config,
+            file: Mutex::new(None),
+        }
+    }
+
+    /// Write a batch of envelopes. The batch is written at once, so batches of concurrent exports
+    /// don't interleave.
+    pub(crate) fn write(&self, envelopes: &[Envelope]) -> io::Result<()> {
+        if envelopes.is_empty() {
+            return Ok(());
+        }
+
+        let mut batch = Vec::new();
+        for envelope in envelopes {
+            if self.config.pretty {
+                serde_json::to_writer_pretty(&mut batch, envelope)?;
+            } else {
+                serde_json::to_writer(&mut batch, envelope)?;
+            }
+            batch.push(b'\n');
+        }
+
+        let (path, rotation) = match &self.config.target {
+            Target::Stdout => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(&batch)?;
+                return stdout.flush();
+            }
+            Target::File(path) => (path, None),
+            Target::RotatingFile {
+                path,
+                max_size,
+                max_files,
+            } => (path, Some((*max_size, *max_files))),
+        };
+
+        let mut file = self.file.lock().unwrap();
+        if file.is_none() {
+            *file = Some(OpenFile::open(path)?);
+        }
+        if let Some((max_size, max_files)) = rotation {
+            let size = file.as_ref().map_or(0, |open| open.size);
+            if size > 0 && size + batch.len() as u64 > max_size {
+                *file = None;
+                rotate(path, max_files)?;
+                *file = Some(OpenFile::open(path)?);
+            }
+        }
+        let open = file.as_mut().expect("file was opened above");
+        open.file.write_all(&batch)?;
+        open.size += batch.len() as u64;
+        Ok(())
+    }
+}
+
+impl OpenFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self { file, size })
+    }
+}
+
+/// Shifts `<path>.1` .. `<path>.<max_files - 2>` by one and moves `<path>` to `<path>.1`.
+fn rotate(path: &Path, max_files: usize) -> io::Result<()> {
+    let rotated = |index: usize| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(format!(".{index}"));
+        PathBuf::from(name)
+    };
+
+    if max_files <= 1 {
+        return fs::remove_file(path);
+    }
+    match fs::remove_file(rotated(max_files - 1)) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+    for index in (1..max_files - 1).rev() {
+        match fs::rename(rotated(index), rotated(index + 1)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+    fs::rename(path, rotated(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope(name: &str) -> Envelope {
+        Envelope {
+            name: "Microsoft.ApplicationInsights.Message",
+            time: "2025-01-01T00:00:00Z".into(),
+            sample_rate: None,
+            i_key: Some(name.into()),
+            tags: None,
+            data: None,
+        }
+    }
+
+    fn dir(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "opentelemetry-application-insights-sink-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&path);
+        path
+    }
+
+    #[test]
+    fn file() {
+        let dir = dir("file");
+        let path = dir.join("telemetry.ndjson");
+        let sink = SinkWriter::new(EnvelopeSink::file(&path));
+        sink.write(&[envelope("a"), envelope("b")]).unwrap();
+        sink.write(&[envelope("c")]).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = content.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            r#"{"name":"Microsoft.ApplicationInsights.Message","time":"2025-01-01T00:00:00Z","iKey":"a"}"#
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn pretty() {
+        let dir = dir("pretty");
+        let path = dir.join("telemetry.json");
+        let sink = SinkWriter::new(EnvelopeSink::file(&path).with_pretty(true));
+        sink.write(&[envelope("a")]).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("{\n  \"name\""));
+        assert!(content.ends_with("}\n"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotating_file() {
+        let dir = dir("rotating_file");
+        let path = dir.join("telemetry.ndjson");
+        let line_len = serde_json::to_vec(&envelope("a")).unwrap().len() as u64 + 1;
+        let sink = SinkWriter::new(EnvelopeSink::rotating_file(&path, line_len * 2, 3));
+        for name in ["a", "b", "c", "d", "e", "f", "g"] {
+            sink.write(&[envelope(name)]).unwrap();
+        }
+
+        let i_keys = |path: PathBuf| -> Vec<String> {
+            fs::read_to_string(path)
+                .unwrap()
+                .lines()
+                .map(|line| {
+                    serde_json::from_str::<serde_json::Value>(line).unwrap()["iKey"]
+                        .as_str()
+                        .unwrap()
+                        .to_string()
+                })
+                .collect()
+        };
+        assert_eq!(i_keys(path.clone()), vec!["g"]);
+        assert_eq!(i_keys(dir.join("telemetry.ndjson.1")), vec!["e", "f"]);
+        assert_eq!(i_keys(dir.join("telemetry.ndjson.2")), vec!["c", "d"]);
+        assert!(!dir.join("telemetry.ndjson.3").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,381 @@
+use opentelemetry::{
+    metrics::{AsyncInstrument, MeterProvider},
+    KeyValue,
+};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+const METER_NAME: &str = "opentelemetry-application-insights";
+
+/// Counters of telemetry items and bytes sent by an exporter.
+///
+/// Returned by [`Exporter::stats`](crate::Exporter::stats). Counters start at zero when the
+/// exporter is created and are shared by all of its clones.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ExporterStats {
+    /// Counters for spans and span events.
+    pub traces: SignalStats,
+    /// Counters for log records.
+    pub logs: SignalStats,
+    /// Counters for metrics.
+    pub metrics: SignalStats,
+    /// Counters for telemetry items sent from the offline storage. Stored batches are shared by
+    /// all signals, so items sent from the storage are not counted for their signal.
+    pub stored: SignalStats,
+}
+
+/// Counters of telemetry items and bytes sent for one signal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SignalStats {
+    /// Telemetry items sent to Application Insights, including retries.
+    pub sent: u64,
+    /// Telemetry items accepted by Application Insights.
+    pub accepted: u64,
+    /// Telemetry items rejected by Application Insights with a status code that is not retried,
+    /// by status code.
+    pub rejected: BTreeMap<u16, u64>,
+    /// Telemetry items sent again after a retryable error.
+    pub retried: u64,
+    /// Telemetry items, which were dropped because they could not be sent within the retry
+    /// policy, could not be routed to their connection string or were dropped by a
+    /// [`TelemetryProcessor`](crate::TelemetryProcessor).
+    pub dropped: u64,
+    /// Telemetry items, which could not be sent within the retry policy and were written to the
+    /// offline storage.
+    pub persisted: u64,
+    /// Size of request bodies before compression in bytes.
+    pub uncompressed_bytes: u64,
+    /// Size of request bodies as sent in bytes, i.e. after compression unless it's disabled with
+    /// [`Compression::None`](crate::Compression::None).
+    pub compressed_bytes: u64,
+    /// Telemetry items with custom property values longer than the maximum length, which were
+    /// handled according to the [`OverflowPolicy`](crate::OverflowPolicy).
+    pub truncated: u64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Stats {
+    pub(crate) traces: Counters,
+    pub(crate) logs: Counters,
+    pub(crate) metrics: Counters,
+    pub(crate) stored: Counters,
+}
+
+impl Stats {
+    pub(crate) fn snapshot(&self) -> ExporterStats {
+        ExporterStats {
+            traces: self.traces.snapshot(),
+            logs: self.logs.snapshot(),
+            metrics: self.metrics.snapshot(),
+            stored: self.stored.snapshot(),
+        }
+    }
+
+    fn signals(&self) -> [(&'static str, &Counters); 4] {
+        [
+            ("traces", &self.traces),
+            ("logs", &self.logs),
+            ("metrics", &self.metrics),
+            ("stored", &self.stored),
+        ]
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    sent: AtomicU64,
+    accepted: AtomicU64,
+    rejected: Mutex<BTreeMap<u16, u64>>,
+    retried: AtomicU64,
+    dropped: AtomicU64,
+    persisted: AtomicU64,
+    uncompressed_bytes: AtomicU64,
+    compressed_bytes: AtomicU64,
+    truncated: AtomicU64,
+}
+
+impl Counters {
+    pub(crate) fn record_request(
+        &self,
+        items: usize,
+        uncompressed_bytes: usize,
+        compressed_bytes: usize,
+    ) {
+        add(&self.sent, items);
+        add(&self.uncompressed_bytes, uncompressed_bytes);
+        add(&self.compressed_bytes, compressed_bytes);
+    }
+
+    pub(crate) fn record_accepted(&self, items: usize) {
+        add(&self.accepted, items);
+    }
+
+    pub(crate) fn record_rejected(&self, status_code: u16, items: usize) {
+        *self
+            .rejected
+            .lock()
+            .unwrap()
+            .entry(status_code)
+            .or_default() += items as u64;
+    }
+
+    pub(crate) fn record_retried(&self, items: usize) {
+        add(&self.retried, items);
+    }
+
+    pub(crate) fn record_dropped(&self, items: usize) {
+        add(&self.dropped, items);
+    }
+
+    pub(crate) fn record_persisted(&self, items: usize) {
+        add(&self.persisted, items);
+    }
+
+    pub(crate) fn record_truncated(&self, items: usize) {
+        add(&self.truncated, items);
+    }
+
+    pub(crate) fn snapshot(&self) -> SignalStats {
+        SignalStats {
+            sent: self.sent.load(Ordering::Relaxed),
+            accepted: self.accepted.load(Ordering::Relaxed),
+            rejected: self.rejected.lock().unwrap().clone(),
+            retried: self.retried.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            persisted: self.persisted.load(Ordering::Relaxed),
+            uncompressed_bytes: self.uncompressed_bytes.load(Ordering::Relaxed),
+            compressed_bytes: self.compressed_bytes.load(Ordering::Relaxed),
+            truncated: self.truncated.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn add(counter: &AtomicU64, value: usize) {
+    counter.fetch_add(value as u64, Ordering::Relaxed);
+}
+
+/// Calls the given function with the stats of the exporter's own connection string (without
+/// instrumentation key) and of every other destination (with its instrumentation key).
+pub(crate) type StatsSource = Arc<dyn Fn(&mut dyn FnMut(Option<&str>, &Stats)) + Send + Sync>;
+
+/// Registers observable counters, which report the exporter stats, on the given meter provider.
+pub(crate) fn register<P: MeterProvider + ?Sized>(source: StatsSource, meter_provider: &P) {
+    let meter = meter_provider.meter(METER_NAME);
+
+    let items_source = source.clone();
+    meter
+        .u64_observable_counter("applicationinsights.exporter.items")
+        .with_description("Telemetry items processed by the Application Insights exporter")
+        .with_unit("{item}")
+        .with_callback(move |observer| {
+            items_source(&mut |destination, stats| observe_items(stats, destination, observer))
+        })
+        .build();
+
+    meter
+        .u64_observable_counter("applicationinsights.exporter.request.body.size")
+        .with_description("Size of request bodies sent by the Application Insights exporter")
+        .with_unit("By")
+        .with_callback(move |observer| {
+            source(&mut |destination, stats| observe_bytes(stats, destination, observer))
+        })
+        .build();
+}
+
+fn observe_items(stats: &Stats, destination: Option<&str>, observer: &dyn AsyncInstrument<u64>) {
+    for (signal, counters) in stats.signals() {
+        let snapshot = counters.snapshot();
+        for (outcome, value) in [
+            ("sent", snapshot.sent),
+            ("accepted", snapshot.accepted),
+            ("retried", snapshot.retried),
+            ("dropped", snapshot.dropped),
+            ("persisted", snapshot.persisted),
+            ("truncated", snapshot.truncated),
+        ] {
+            observe(
+                observer,
+                value,
+                destination,
+                [
+                    KeyValue::new("signal", signal),
+                    KeyValue::new("outcome", outcome),
+                ],
+            );
+        }
+        for (status_code, value) in snapshot.rejected {
+            observe(
+                observer,
+                value,
+                destination,
+                [
+                    KeyValue::new("signal", signal),
+                    KeyValue::new("outcome", "rejected"),
+                    KeyValue::new("http.response.status_code", i64::from(status_code)),
+                ],
+            );
+        }
+    }
+}
+
+fn observe_bytes(stats: &Stats, destination: Option<&str>, observer: &dyn AsyncInstrument<u64>) {
+    for (signal, counters) in stats.signals() {
+        let snapshot = counters.snapshot();
+        for (body, value) in [
+            ("uncompressed", snapshot.uncompressed_bytes),
+            ("sent", snapshot.compressed_bytes),
+        ] {
+            observe(
+                observer,
+                value,
+                destination,
+                [KeyValue::new("signal", signal), KeyValue::new("body", body)],
+            );
+        }
+    }
+}
+
+/// Observes the value with the attributes and, for other destinations than the exporter's own
+/// connection string, a `destination` attribute with their instrumentation key.
+fn observe<const N: usize>(
+    observer: &dyn AsyncInstrument<u64>,
+    value: u64,
+    destination: Option<&str>,
+    attributes: [KeyValue; N],
+) {
+    match destination {
+        None => observer.observe(value, &attributes),
+        Some(instrumentation_key) => {
+            let mut attributes = attributes.to_vec();
+            attributes.push(KeyValue::new(
+                "destination",
+                instrumentation_key.to_string(),
+            ));
+            observer.observe(value, &attributes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry_sdk::metrics::{
+        data::{AggregatedMetrics, MetricData},
+        InMemoryMetricExporter, PeriodicReader, SdkMeterProvider,
+    };
+
+    #[test]
+    fn snapshot() {
+        let stats = Stats::default();
+        stats.logs.record_request(3, 100, 40);
+        stats.logs.record_accepted(2);
+        stats.logs.record_rejected(400, 1);
+        stats.logs.record_rejected(400, 2);
+        stats.logs.record_dropped(4);
+
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.traces, SignalStats::default());
+        assert_eq!(snapshot.metrics, SignalStats::default());
+        assert_eq!(snapshot.stored, SignalStats::default());
+        assert_eq!(
+            snapshot.logs,
+            SignalStats {
+                sent: 3,
+                accepted: 2,
+                rejected: BTreeMap::from([(400, 3)]),
+                dropped: 4,
+                uncompressed_bytes: 100,
+                compressed_bytes: 40,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn register_observable_counters() {
+        let stats = Arc::new(Stats::default());
+        stats.traces.record_request(5, 100, 40);
+        stats.traces.record_rejected(400, 1);
+        let other = Arc::new(Stats::default());
+        other.traces.record_request(2, 50, 20);
+
+        let exporter = InMemoryMetricExporter::default();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+        register(
+            Arc::new(move |observe: &mut dyn FnMut(Option<&str>, &Stats)| {
+                observe(None, &stats);
+                observe(Some("other"), &other);
+            }),
+            &meter_provider,
+        );
+        meter_provider.force_flush().unwrap();
+
+        let metrics = exporter.get_finished_metrics().unwrap();
+        let metrics: Vec<_> = metrics
+            .iter()
+            .flat_map(|resource_metrics| resource_metrics.scope_metrics())
+            .flat_map(|scope_metrics| scope_metrics.metrics())
+            .collect();
+        let items = metrics
+            .iter()
+            .find(|metric| metric.name() == "applicationinsights.exporter.items")
+            .unwrap();
+        let AggregatedMetrics::U64(MetricData::Sum(sum)) = items.data() else {
+            panic!("unexpected data");
+        };
+        let value = |signal: &str, outcome: &str, destination: Option<&str>| {
+            sum.data_points()
+                .find(|point| {
+                    let has = |key: &str, value: &str| {
+                        point
+                            .attributes()
+                            .any(|kv| kv.key.as_str() == key && kv.value.as_str() == value)
+                    };
+                    let destination = match destination {
+                        Some(destination) => has("destination", destination),
+                        None => point
+                            .attributes()
+                            .all(|kv| kv.key.as_str() != "destination"),
+                    };
+                    has("signal", signal) && has("outcome", outcome) && destination
+                })
+                .map(|point| point.value())
+        };
+        assert_eq!(value("traces", "sent", None), Some(5));
+        assert_eq!(value("traces", "rejected", None), Some(1));
+        assert_eq!(value("logs", "sent", None), Some(0));
+        assert_eq!(value("traces", "sent", Some("other")), Some(2));
+        assert_eq!(value("traces", "rejected", Some("other")), None);
+        let body_size = metrics
+            .iter()
+            .find(|metric| metric.name() == "applicationinsights.exporter.request.body.size")
+            .unwrap();
+        let AggregatedMetrics::U64(MetricData::Sum(sum)) = body_size.data() else {
+            panic!("unexpected data");
+        };
+        let sent = sum
+            .data_points()
+            .find(|point| {
+                point
+                    .attributes()
+                    .any(|kv| kv.key.as_str() == "signal" && kv.value.as_str() == "traces")
+                    && point
+                        .attributes()
+                        .any(|kv| kv.key.as_str() == "body" && kv.value.as_str() == "sent")
+                    && point
+                        .attributes()
+                        .all(|kv| kv.key.as_str() != "destination")
+            })
+            .map(|point| point.value());
+        assert_eq!(sent, Some(40));
+    }
+}
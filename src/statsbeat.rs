@@ -0,0 +1,562 @@
+//! Statsbeat: usage and network health metrics about the exporter itself.
+//!
+//! Follows the network statsbeat of the official Azure Monitor exporters:
+//!
+//! - [Statsbeat in Python exporter](https://github.com/Azure/azure-sdk-for-python/tree/azure-monitor-opentelemetry-exporter_1.0.0b21/sdk/monitor/azure-monitor-opentelemetry-exporter/azure/monitor/opentelemetry/exporter/statsbeat)
+//!
+//! The uploader records the outcome and duration of every request per ingestion endpoint host.
+//! A background task sends the counters as metrics to a separate Application Insights resource
+//! every statsbeat interval.
+
+use crate::{
+    connection_string::ConnectionString,
+    convert::time_to_string,
+    models::{Data, DataPoint, DataPointType, Envelope, MetricData, Properties},
+    payload::{BufferPool, Compression},
+    retry::RetryPolicy,
+    stats::Counters,
+    uploader::{self, Endpoint, Throttle, Upload},
+    HttpClient,
+};
+use backon::{FuturesTimerSleeper, Sleeper};
+use bytes::Bytes;
+use http::{Request, Uri};
+use opentelemetry_sdk::runtime::Runtime;
+use std::{
+    collections::BTreeMap,
+    error::Error as StdError,
+    future::{poll_fn, Future},
+    pin::pin,
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::{Duration, SystemTime},
+};
+
+const DISABLED_ENV_VAR: &str = "APPLICATIONINSIGHTS_STATSBEAT_DISABLED_ALL";
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(15 * 60);
+const ENVELOPE_NAME: &str = "Statsbeat";
+
+const IMDS_ENDPOINT: &str =
+    "http://169.254.169.254/metadata/instance/compute?api-version=2017-08-01&format=json";
+const IMDS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Configuration for sending statsbeat, i.e. metrics about the requests the exporter sends to
+/// Application Insights.
+///
+/// Statsbeat contains request success, failure, retry, throttle and exception counts and request
+/// durations per ingestion endpoint, as well as the resource provider the application runs on (App
+/// Service, Functions, AKS, VM or unknown). It's sent to the Application Insights resource of the
+/// given connection string, which is separate from the one receiving your telemetry.
+///
+/// Statsbeat is disabled if the `APPLICATIONINSIGHTS_STATSBEAT_DISABLED_ALL` environment variable
+/// is set to `true`.
+///
+/// This requires the **statsbeat** feature, which enables the experimental async runtime API of
+/// `opentelemetry_sdk`.
+///
+/// ```
+/// use opentelemetry_application_insights::Statsbeat;
+/// use std::time::Duration;
+///
+/// let statsbeat = Statsbeat::new("InstrumentationKey=...")
+///     .expect("valid connection string")
+///     .with_interval(Duration::from_secs(60 * 60));
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(docsrs, doc(cfg(feature = "statsbeat")))]
+pub struct Statsbeat {
+    track_endpoint: Uri,
+    instrumentation_key: String,
+    interval: Duration,
+}
+
+impl Statsbeat {
+    /// Create a new statsbeat configuration, which sends statsbeat to the Application Insights
+    /// resource of the given connection string.
+    pub fn new(
+        connection_string: impl AsRef<str>,
+    ) -> Result<Self, Box<dyn StdError + Send + Sync + 'static>> {
+        let connection_string: ConnectionString = connection_string.as_ref().parse()?;
+        Ok(Self {
+            track_endpoint: crate::append_v2_track(&connection_string.ingestion_endpoint),
+            instrumentation_key: connection_string.instrumentation_key,
+            interval: DEFAULT_INTERVAL,
+        })
+    }
+
+    /// Set how often statsbeat is sent.
+    ///
+    /// Default: 15 minutes.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+/// Returns whether statsbeat was disabled using the environment variable.
+pub(crate) fn is_disabled() -> bool {
+    std::env::var(DISABLED_ENV_VAR).is_ok_and(|value| value.eq_ignore_ascii_case("true"))
+}
+
+/// Outcome of a single request to an ingestion endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RequestOutcome {
+    Success,
+    Failure(u16),
+    Retry(u16),
+    Throttle(u16),
+    Exception(&'static str),
+}
+
+#[derive(Debug, Default)]
+struct NetworkCounters {
+    success: u64,
+    failure: BTreeMap<u16, u64>,
+    retry: BTreeMap<u16, u64>,
+    throttle: BTreeMap<u16, u64>,
+    exception: BTreeMap<&'static str, u64>,
+    duration_count: u64,
+    duration_sum: Duration,
+    duration_min: Duration,
+    duration_max: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResourceProvider {
+    AppService,
+    Functions,
+    Aks,
+    Vm,
+    Unknown,
+}
+
+impl ResourceProvider {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::AppService => "appsvc",
+            Self::Functions => "functions",
+            Self::Aks => "aks",
+            Self::Vm => "vm",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    fn from_env(var: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        if var("FUNCTIONS_WORKER_RUNTIME").is_some() {
+            Some(Self::Functions)
+        } else if var("WEBSITE_SITE_NAME").is_some() {
+            Some(Self::AppService)
+        } else if var("AKS_ARM_NAMESPACE_ID").is_some() {
+            Some(Self::Aks)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct StatsbeatState {
+    config: Statsbeat,
+    endpoint: Endpoint,
+    customer_instrumentation_key: String,
+    network: Mutex<BTreeMap<String, NetworkCounters>>,
+    resource_provider: Mutex<Option<ResourceProvider>>,
+    retry_policy: RetryPolicy,
+    throttle: Throttle,
+    counters: Counters,
+    buffers: BufferPool,
+}
+
+impl StatsbeatState {
+    pub(crate) fn new(config: Statsbeat, customer_instrumentation_key: String) -> Self {
+        Self {
+            endpoint: Endpoint::new(config.track_endpoint.clone()),
+            config,
+            customer_instrumentation_key,
+            network: Mutex::default(),
+            resource_provider: Mutex::default(),
+            retry_policy: RetryPolicy::no_retries(),
+            throttle: Throttle::default(),
+            counters: Counters::default(),
+            buffers: BufferPool::default(),
+        }
+    }
+
+    /// Record the outcome of a request to the given endpoint.
+    pub(crate) fn record(&self, endpoint: &Uri, outcome: RequestOutcome, duration: Duration) {
+        let host = endpoint.host().unwrap_or_default().to_string();
+        let mut network = self.network.lock().unwrap();
+        let counters = network.entry(host).or_default();
+        match outcome {
+            RequestOutcome::Success => counters.success += 1,
+            RequestOutcome::Failure(status_code) => {
+                *counters.failure.entry(status_code).or_default() += 1
+            }
+            RequestOutcome::Retry(status_code) => {
+                *counters.retry.entry(status_code).or_default() += 1
+            }
+            RequestOutcome::Throttle(status_code) => {
+                *counters.throttle.entry(status_code).or_default() += 1
+            }
+            RequestOutcome::Exception(exception_type) => {
+                *counters.exception.entry(exception_type).or_default() += 1
+            }
+        }
+        if counters.duration_count == 0 || duration < counters.duration_min {
+            counters.duration_min = duration;
+        }
+        if duration > counters.duration_max {
+            counters.duration_max = duration;
+        }
+        counters.duration_count += 1;
+        counters.duration_sum += duration;
+    }
+
+    /// Send the counters recorded since they were sent last.
+    async fn send(&self, client: &dyn HttpClient) {
+        let network = std::mem::take(&mut *self.network.lock().unwrap());
+        if network.is_empty() {
+            return;
+        }
+
+        let resource_provider = self.resource_provider(client).await;
+        let envelopes = self.envelopes(network, resource_provider, SystemTime::now());
+        let upload = Upload {
+            client,
+            endpoint: &self.endpoint,
+            retry_policy: &self.retry_policy,
+            retry_notify: None,
+            dead_letter: None,
+            storage: None,
+            credential: None,
+            throttle: &self.throttle,
+            counters: &self.counters,
+            statsbeat: None,
+            compression: Compression::default(),
+            buffers: &self.buffers,
+        };
+        if let Err(err) = uploader::send(&upload, envelopes).await {
+            opentelemetry::otel_debug!(name: "ApplicationInsights.Statsbeat.SendFailed", error = err.to_string().as_str());
+        }
+    }
+
+    /// Returns the resource provider, which is detected on first use. This may query the instance
+    /// metadata service, so it's only called by the background task.
+    async fn resource_provider(&self, client: &dyn HttpClient) -> ResourceProvider {
+        if let Some(resource_provider) = *self.resource_provider.lock().unwrap() {
+            return resource_provider;
+        }
+
+        let resource_provider = match ResourceProvider::from_env(|name| std::env::var(name).ok()) {
+            Some(resource_provider) => resource_provider,
+            None if is_azure_vm(client).await => ResourceProvider::Vm,
+            None => ResourceProvider::Unknown,
+        };
+        *self.resource_provider.lock().unwrap() = Some(resource_provider);
+        resource_provider
+    }
+
+    fn envelopes(
+        &self,
+        network: BTreeMap<String, NetworkCounters>,
+        resource_provider: ResourceProvider,
+        now: SystemTime,
+    ) -> Vec<Envelope> {
+        let mut common = Properties::new();
+        common.insert("rp".into(), resource_provider.as_str().into());
+        common.insert("attach".into(), "Manual".into());
+        common.insert(
+            "cikey".into(),
+            self.customer_instrumentation_key.as_str().into(),
+        );
+        common.insert("os".into(), std::env::consts::OS.into());
+        common.insert("language".into(), "rust".into());
+        common.insert("version".into(), env!("CARGO_PKG_VERSION").into());
+        common.insert("endpoint".into(), "breeze".into());
+
+        let time = time_to_string(now);
+        let mut envelopes = Vec::new();
+        let mut push = |host: &str, name: &str, kind, value, extra: Option<(&str, String)>| {
+            let mut properties = common.clone();
+            properties.insert("host".into(), host.into());
+            if let Some((key, value)) = extra {
+                properties.insert(key.into(), value.into());
+            }
+            envelopes.push(Envelope {
+                name: ENVELOPE_NAME,
+                time: time.as_str().into(),
+                sample_rate: None,
+                i_key: Some(self.config.instrumentation_key.as_str().into()),
+                tags: None,
+                data: Some(Data::Metric(MetricData {
+                    ver: 2,
+                    metrics: vec![DataPoint {
+                        ns: None,
+                        name: name.into(),
+                        kind: Some(kind),
+                        value,
+                    }],
+                    properties: Some(properties),
+                })),
+            });
+        };
+
+        for (host, counters) in network {
+            if counters.success > 0 {
+                push(
+                    &host,
+                    "Request_Success_Count",
+                    DataPointType::Measurement,
+                    counters.success as f64,
+                    None,
+                );
+            }
+            for (name, by_status_code) in [
+                ("Request_Failure_Count", &counters.failure),
+                ("Retry_Count", &counters.retry),
+                ("Throttle_Count", &counters.throttle),
+            ] {
+                for (status_code, count) in by_status_code {
+                    push(
+                        &host,
+                        name,
+                        DataPointType::Measurement,
+                        *count as f64,
+                        Some(("statusCode", status_code.to_string())),
+                    );
+                }
+            }
+            for (exception_type, count) in &counters.exception {
+                push(
+                    &host,
+                    "Exception_Count",
+                    DataPointType::Measurement,
+                    *count as f64,
+                    Some(("exceptionType", exception_type.to_string())),
+                );
+            }
+            if counters.duration_count > 0 {
+                push(
+                    &host,
+                    "Request_Duration",
+                    DataPointType::Aggregation {
+                        count: Some(counters.duration_count.try_into().unwrap_or(i32::MAX)),
+                        min: Some(as_millis(counters.duration_min)),
+                        max: Some(as_millis(counters.duration_max)),
+                        std_dev: None,
+                    },
+                    as_millis(counters.duration_sum),
+                    None,
+                );
+            }
+        }
+
+        envelopes
+    }
+}
+
+/// Spawns a task, which sends statsbeat every interval until all exporters using the state were
+/// dropped.
+pub(crate) fn spawn<C, R>(state: &Arc<StatsbeatState>, client: Arc<C>, runtime: R)
+where
+    C: HttpClient + 'static,
+    R: Runtime,
+{
+    let interval = state.config.interval;
+    let state = Arc::downgrade(state);
+    runtime.clone().spawn(async move {
+        // Detect the resource provider right away, so it's known by the time statsbeat is sent.
+        match state.upgrade() {
+            Some(state) => state.resource_provider(client.as_ref()).await,
+            None => return,
+        };
+        loop {
+            runtime.delay(interval).await;
+            let Some(state) = state.upgrade() else {
+                break;
+            };
+            state.send(client.as_ref()).await;
+        }
+    });
+}
+
+fn as_millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Checks whether the application runs on an Azure VM by querying the instance metadata service.
+async fn is_azure_vm(client: &dyn HttpClient) -> bool {
+    let request = Request::get(IMDS_ENDPOINT)
+        .header("Metadata", "true")
+        .body(Bytes::new())
+        .expect("request should be valid");
+    matches!(
+        with_timeout(client.send_bytes(request), IMDS_TIMEOUT).await,
+        Some(Ok(response)) if response.status().is_success()
+    )
+}
+
+async fn with_timeout<F: Future>(future: F, timeout: Duration) -> Option<F::Output> {
+    let mut future = pin!(future);
+    let mut sleep = pin!(FuturesTimerSleeper.sleep(timeout));
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        if sleep.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(None);
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::uploader::tests::TestClient;
+    use flate2::read::GzDecoder;
+    use test_case::test_case;
+
+    const CONNECTION_STRING: &str =
+        "InstrumentationKey=statsbeat;IngestionEndpoint=https://statsbeat.example.com";
+
+    fn state(interval: Duration) -> StatsbeatState {
+        StatsbeatState::new(
+            Statsbeat::new(CONNECTION_STRING)
+                .unwrap()
+                .with_interval(interval),
+            "customer".into(),
+        )
+    }
+
+    #[test_case(&[], None ; "unknown")]
+    #[test_case(&["WEBSITE_SITE_NAME"], Some(ResourceProvider::AppService) ; "app service")]
+    #[test_case(&["WEBSITE_SITE_NAME", "FUNCTIONS_WORKER_RUNTIME"], Some(ResourceProvider::Functions) ; "functions")]
+    #[test_case(&["AKS_ARM_NAMESPACE_ID"], Some(ResourceProvider::Aks) ; "aks")]
+    fn resource_provider_from_env(vars: &[&str], expected: Option<ResourceProvider>) {
+        let resource_provider =
+            ResourceProvider::from_env(|name| vars.contains(&name).then(|| "value".into()));
+        assert_eq!(resource_provider, expected);
+    }
+
+    #[test]
+    fn envelopes() {
+        let state = state(DEFAULT_INTERVAL);
+        let endpoint = Uri::from_static("https://ingest.example.com/v2/track");
+        state.record(
+            &endpoint,
+            RequestOutcome::Success,
+            Duration::from_millis(10),
+        );
+        state.record(
+            &endpoint,
+            RequestOutcome::Success,
+            Duration::from_millis(30),
+        );
+        state.record(
+            &endpoint,
+            RequestOutcome::Throttle(439),
+            Duration::from_millis(20),
+        );
+        state.record(
+            &endpoint,
+            RequestOutcome::Exception("ConnectionError"),
+            Duration::from_millis(20),
+        );
+
+        let network = std::mem::take(&mut *state.network.lock().unwrap());
+        let envelopes = state.envelopes(network, ResourceProvider::Vm, SystemTime::UNIX_EPOCH);
+        let envelopes: Vec<serde_json::Value> = envelopes
+            .iter()
+            .map(|envelope| serde_json::to_value(envelope).unwrap())
+            .collect();
+        let summary: Vec<_> = envelopes
+            .iter()
+            .map(|envelope| {
+                let data = &envelope["data"]["baseData"];
+                (
+                    data["metrics"][0]["name"].as_str().unwrap(),
+                    data["metrics"][0]["value"].as_f64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("Request_Success_Count", 2.0),
+                ("Throttle_Count", 1.0),
+                ("Exception_Count", 1.0),
+                ("Request_Duration", 80.0),
+            ]
+        );
+
+        let envelope = &envelopes[1];
+        assert_eq!(envelope["name"], "Statsbeat");
+        assert_eq!(envelope["iKey"], "statsbeat");
+        let properties = &envelope["data"]["baseData"]["properties"];
+        assert_eq!(properties["rp"], "vm");
+        assert_eq!(properties["cikey"], "customer");
+        assert_eq!(properties["host"], "ingest.example.com");
+        assert_eq!(properties["statusCode"], "439");
+        let duration = &envelopes[3]["data"]["baseData"]["metrics"][0];
+        assert_eq!(duration["count"], 4);
+        assert_eq!(duration["min"], 10.0);
+        assert_eq!(duration["max"], 30.0);
+    }
+
+    #[tokio::test]
+    async fn send() {
+        let state = state(DEFAULT_INTERVAL);
+        *state.resource_provider.lock().unwrap() = Some(ResourceProvider::Unknown);
+        let client = TestClient::default().with_200();
+
+        // Nothing recorded, nothing sent.
+        state.send(&client).await;
+        assert_eq!(client.requests.lock().unwrap().len(), 0, "request count");
+
+        state.record(
+            &Uri::from_static("https://ingest.example.com/v2/track"),
+            RequestOutcome::Success,
+            Duration::from_millis(10),
+        );
+        state.send(&client).await;
+        let requests = client.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1, "request count");
+        assert_eq!(requests[0].uri(), "https://statsbeat.example.com/v2/track");
+        let envelopes: Vec<serde_json::Value> =
+            serde_json::from_reader(GzDecoder::new(&requests[0].body()[..])).unwrap();
+        assert_eq!(envelopes.len(), 2);
+        assert!(state.network.lock().unwrap().is_empty());
+    }
+
+    // Time is paused, so the runtime only advances it once all tasks wait. This makes the order of
+    // the background task and the test deterministic.
+    #[tokio::test(start_paused = true)]
+    async fn background_task() {
+        let state = Arc::new(state(Duration::from_millis(10)));
+        let client = Arc::new(TestClient::default().with_connection_error().with_200());
+        spawn(&state, client.clone(), opentelemetry_sdk::runtime::Tokio);
+
+        // The resource provider is detected before anything is sent.
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        assert_eq!(client.requests.lock().unwrap().len(), 1, "request count");
+        assert_eq!(
+            *state.resource_provider.lock().unwrap(),
+            Some(ResourceProvider::Unknown)
+        );
+
+        state.record(
+            &Uri::from_static("https://ingest.example.com/v2/track"),
+            RequestOutcome::Success,
+            Duration::from_millis(10),
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(client.requests.lock().unwrap().len(), 2, "request count");
+
+        // The task stops once the exporter is dropped.
+        drop(state);
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(Arc::strong_count(&client), 1, "task has stopped");
+    }
+}
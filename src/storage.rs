@@ -0,0 +1,428 @@
+//! Local storage for telemetry that could not be uploaded.
+//!
+//! Follows the file layout of the official Azure Monitor exporters:
+//!
+//! - [LocalFileStorage in Python exporter](https://github.com/Azure/azure-sdk-for-python/blob/azure-monitor-opentelemetry-exporter_1.0.0b21/sdk/monitor/azure-monitor-opentelemetry-exporter/azure/monitor/opentelemetry/exporter/_storage.py)
+//!
+//! Each batch is stored as a JSON array (the same format as the request body, gzip compressed
+//! unless compression is disabled) in a file called `<timestamp>-<pid>-<counter>.blob`. Files are
+//! written to a `.tmp` file first and renamed afterwards, so readers never see partially written
+//! batches. To send a stored batch, a process leases it by renaming it to
+//! `<name>.blob@<lease expiry>.lock`. Renames are atomic, so two processes never lease the same
+//! file. Leases of crashed processes expire and the file can be leased again afterwards.
+//!
+//! Batches for additional Application Insights resources are stored in the same way in
+//! `destinations/<instrumentation key>` below the storage directory. The maximum size applies to
+//! the storage directory including these.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime},
+};
+
+const BLOB_EXTENSION: &str = ".blob";
+const LOCK_EXTENSION: &str = ".lock";
+const TMP_EXTENSION: &str = ".tmp";
+const LEASE_SEPARATOR: char = '@';
+
+const DESTINATIONS_DIR: &str = "destinations";
+
+const DEFAULT_MAX_SIZE: u64 = 50 * 1024 * 1024;
+const DEFAULT_RETENTION: Duration = Duration::from_secs(48 * 60 * 60);
+const DEFAULT_LEASE_DURATION: Duration = Duration::from_secs(60);
+
+static BLOB_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Configuration for persisting telemetry on disk, which failed to upload.
+///
+/// Batches that could not be uploaded because of a retryable error (e.g. network issues or
+/// throttling) are written to the given directory. They are sent again during later exports.
+///
+/// ```no_run
+/// use opentelemetry_application_insights::OfflineStorage;
+/// use std::time::Duration;
+///
+/// let storage = OfflineStorage::new("/var/lib/my-app/telemetry")
+///     .with_max_size(10 * 1024 * 1024)
+///     .with_retention(Duration::from_secs(24 * 60 * 60));
+/// ```
+#[derive(Debug, Clone)]
+pub struct OfflineStorage {
+    path: PathBuf,
+    max_size: u64,
+    retention: Duration,
+    lease_duration: Duration,
+}
+
+impl OfflineStorage {
+    /// Create a new offline storage configuration, which stores telemetry in the given directory.
+    /// The directory is created if it does not exist.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            max_size: DEFAULT_MAX_SIZE,
+            retention: DEFAULT_RETENTION,
+            lease_duration: DEFAULT_LEASE_DURATION,
+        }
+    }
+
+    /// Set the maximum size of the directory in bytes, including telemetry stored for additional
+    /// resources. New batches are dropped if the directory is full.
+    ///
+    /// Default: 50 MiB.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Set how long batches are kept. Older batches are deleted without sending them.
+    ///
+    /// Default: 48 hours.
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Set how long a process may send a stored batch before other processes are allowed to pick
+    /// it up.
+    ///
+    /// Default: 60 seconds.
+    pub fn with_lease_duration(mut self, lease_duration: Duration) -> Self {
+        self.lease_duration = lease_duration;
+        self
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct LocalStorage {
+    config: OfflineStorage,
+    /// Directory, whose size is limited by the maximum size. It's the storage directory itself,
+    /// or the root storage directory for destinations.
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub(crate) fn new(config: OfflineStorage) -> Self {
+        let root = config.path.clone();
+        Self { config, root }
+    }
+
+    /// Storage for another destination with the same configuration. It shares the maximum size
+    /// with this storage.
+    pub(crate) fn destination(&self, name: &str) -> Self {
+        let mut config = self.config.clone();
+        config.path = config.path.join(DESTINATIONS_DIR).join(name);
+        Self {
+            config,
+            root: self.root.clone(),
+        }
+    }
+
+    /// Store a serialized (and compressed) batch.
+    pub(crate) fn put(&self, payload: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.config.path)?;
+        if dir_size(&self.root)? + payload.len() as u64 > self.config.max_size {
+            return Err(io::Error::other("offline storage is full"));
+        }
+
+        let name = format!(
+            "{:020}-{}-{}",
+            to_millis(SystemTime::now()),
+            std::process::id(),
+            BLOB_COUNTER.fetch_add(1, Ordering::Relaxed),
+        );
+        let tmp_path = self.config.path.join(format!("{name}{TMP_EXTENSION}"));
+        fs::write(&tmp_path, payload)?;
+        fs::rename(
+            &tmp_path,
+            self.config.path.join(format!("{name}{BLOB_EXTENSION}")),
+        )
+    }
+
+    /// Lease the oldest stored batch that is not leased by someone else. Expired batches are
+    /// deleted along the way.
+    pub(crate) fn lease_next(&self) -> Option<LeasedBlob> {
+        let now = SystemTime::now();
+        let lease_expiry = to_millis(now + self.config.lease_duration);
+        for (path, file_name) in self.entries().ok()? {
+            let Some(blob_name) = available_blob_name(&file_name, now) else {
+                if file_name.ends_with(TMP_EXTENSION) && self.is_expired(&file_name, now) {
+                    let _ = fs::remove_file(&path);
+                }
+                continue;
+            };
+
+            if self.is_expired(blob_name, now) {
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+
+            let leased_path = self.config.path.join(format!(
+                "{blob_name}{LEASE_SEPARATOR}{lease_expiry}{LOCK_EXTENSION}"
+            ));
+            if fs::rename(&path, &leased_path).is_ok() {
+                return Some(LeasedBlob {
+                    path: leased_path,
+                    blob_path: self.config.path.join(blob_name),
+                });
+            }
+        }
+
+        None
+    }
+
+    /// Delete expired batches and abandoned temporary files. Files that cannot be deleted are
+    /// logged and skipped.
+    pub(crate) fn cleanup(&self) -> io::Result<()> {
+        let now = SystemTime::now();
+        for (path, file_name) in self.entries()? {
+            let blob_name = file_name
+                .split(LEASE_SEPARATOR)
+                .next()
+                .unwrap_or(&file_name);
+            if self.is_expired(blob_name, now) {
+                if let Err(err) = fs::remove_file(&path) {
+                    opentelemetry::otel_warn!(name: "ApplicationInsights.OfflineStorage.CleanupFailed", file_name = file_name.as_str(), error = err.to_string().as_str());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Files in the storage directory, sorted by name and therefore by creation time.
+    fn entries(&self) -> io::Result<Vec<(PathBuf, String)>> {
+        let dir = match fs::read_dir(&self.config.path) {
+            Ok(dir) => dir,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+        let mut entries: Vec<_> = dir
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name().into_string().ok()?;
+                Some((entry.path(), file_name))
+            })
+            .collect();
+        entries.sort_by(|a, b| a.1.cmp(&b.1));
+        Ok(entries)
+    }
+
+    fn is_expired(&self, file_name: &str, now: SystemTime) -> bool {
+        let created = file_name
+            .split('-')
+            .next()
+            .and_then(|millis| millis.parse::<u64>().ok())
+            .map(|millis| SystemTime::UNIX_EPOCH + Duration::from_millis(millis));
+        match created {
+            Some(created) => now
+                .duration_since(created)
+                .map(|age| age > self.config.retention)
+                .unwrap_or(false),
+            // Not a file written by us.
+            None => false,
+        }
+    }
+}
+
+/// Size of all files in a directory and its subdirectories.
+fn dir_size(path: &Path) -> io::Result<u64> {
+    let dir = match fs::read_dir(path) {
+        Ok(dir) => dir,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+    let mut size = 0;
+    for entry in dir.filter_map(|entry| entry.ok()) {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            size += dir_size(&entry.path()).unwrap_or(0);
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Returns the name of the blob (`<name>.blob`) if the file can be leased, i.e. it's either a blob
+/// or a lock file with an expired lease.
+fn available_blob_name(file_name: &str, now: SystemTime) -> Option<&str> {
+    if file_name.ends_with(BLOB_EXTENSION) {
+        return Some(file_name);
+    }
+
+    let (blob_name, lease) = file_name
+        .strip_suffix(LOCK_EXTENSION)?
+        .split_once(LEASE_SEPARATOR)?;
+    let lease_expiry = lease.parse::<u64>().ok()?;
+    (lease_expiry < to_millis(now)).then_some(blob_name)
+}
+
+fn to_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// A stored batch, which is leased by this process.
+#[derive(Debug)]
+pub(crate) struct LeasedBlob {
+    path: PathBuf,
+    blob_path: PathBuf,
+}
+
+impl LeasedBlob {
+    pub(crate) fn read(&self) -> io::Result<Vec<u8>> {
+        fs::read(&self.path)
+    }
+
+    /// Delete the batch, e.g. because it was sent successfully.
+    pub(crate) fn delete(self) -> io::Result<()> {
+        fs::remove_file(&self.path)
+    }
+
+    /// Give up the lease, so the batch can be sent again later.
+    pub(crate) fn release(self) -> io::Result<()> {
+        fs::rename(&self.path, &self.blob_path)
+    }
+
+    #[cfg(test)]
+    fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage(name: &str) -> (LocalStorage, PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "opentelemetry-application-insights-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&path);
+        (LocalStorage::new(OfflineStorage::new(&path)), path)
+    }
+
+    fn file_names(path: &Path) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn put_and_lease() {
+        let (storage, path) = storage("put_and_lease");
+        storage.put(b"first").unwrap();
+        storage.put(b"second").unwrap();
+
+        let blob = storage.lease_next().unwrap();
+        assert_eq!(blob.read().unwrap(), b"first");
+        assert!(blob.path().to_str().unwrap().ends_with(LOCK_EXTENSION));
+
+        // Leased blobs are not handed out twice.
+        let other = storage.lease_next().unwrap();
+        assert_eq!(other.read().unwrap(), b"second");
+        assert!(storage.lease_next().is_none());
+
+        blob.delete().unwrap();
+        other.release().unwrap();
+        assert_eq!(file_names(&path).len(), 1);
+        assert_eq!(storage.lease_next().unwrap().read().unwrap(), b"second");
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn expired_lease() {
+        let (storage, path) = storage("expired_lease");
+        let storage = LocalStorage::new(storage.config.with_lease_duration(Duration::ZERO));
+        storage.put(b"batch").unwrap();
+
+        let _abandoned = storage.lease_next().unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        let blob = storage.lease_next().unwrap();
+        assert_eq!(blob.read().unwrap(), b"batch");
+        assert_eq!(file_names(&path).len(), 1);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn max_size() {
+        let (storage, path) = storage("max_size");
+        let storage = LocalStorage::new(storage.config.with_max_size(10));
+        storage.put(b"12345").unwrap();
+        storage.put(b"12345").unwrap();
+        storage.put(b"1").unwrap_err();
+        assert_eq!(file_names(&path).len(), 2);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn destinations() {
+        let (storage, path) = storage("destinations");
+        let storage = LocalStorage::new(storage.config.with_max_size(10));
+        let destination = storage.destination("00000000-0000-0000-0000-000000000000");
+        destination.put(b"12345").unwrap();
+        storage.put(b"12345").unwrap();
+
+        // Destinations share the maximum size.
+        destination.put(b"1").unwrap_err();
+        storage.put(b"1").unwrap_err();
+
+        // Destinations are not mistaken for batches of the storage.
+        assert_eq!(storage.lease_next().unwrap().read().unwrap(), b"12345");
+        assert!(storage.lease_next().is_none());
+        assert_eq!(destination.lease_next().unwrap().read().unwrap(), b"12345");
+        let storage = LocalStorage::new(storage.config.with_retention(Duration::ZERO));
+        storage.cleanup().unwrap();
+        assert!(path.join(DESTINATIONS_DIR).is_dir());
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn retention() {
+        let (storage, path) = storage("retention");
+        let storage = LocalStorage::new(storage.config.with_retention(Duration::ZERO));
+        storage.put(b"batch").unwrap();
+        fs::write(path.join("00000000000000000000-1-1.tmp"), b"tmp").unwrap();
+        fs::write(path.join("unrelated.txt"), b"unrelated").unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+
+        storage.cleanup().unwrap();
+        assert_eq!(file_names(&path), vec!["unrelated.txt"]);
+
+        storage.put(b"batch").unwrap();
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(storage.lease_next().is_none());
+        assert_eq!(file_names(&path), vec!["unrelated.txt"]);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn cleanup_continues_after_errors() {
+        let (storage, path) = storage("cleanup_continues_after_errors");
+        let storage = LocalStorage::new(storage.config.with_retention(Duration::ZERO));
+        // Directories cannot be removed with `remove_file`.
+        fs::create_dir_all(path.join("00000000000000000000-1-0.blob")).unwrap();
+        fs::write(path.join("00000000000000000000-1-1.blob"), b"batch").unwrap();
+
+        storage.cleanup().unwrap();
+        assert_eq!(file_names(&path), vec!["00000000000000000000-1-0.blob"]);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+}
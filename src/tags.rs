@@ -3,7 +3,7 @@ use crate::{
     models::context_tag_keys::{self as tags, Tags, TAG_KEY_LOOKUP},
 };
 #[cfg(feature = "trace")]
-use opentelemetry::trace::{SpanId, SpanKind};
+use opentelemetry::trace::{Event, SpanId, SpanKind};
 #[cfg(feature = "metrics")]
 use opentelemetry::KeyValue;
 use opentelemetry::{InstrumentationScope, Key};
@@ -16,9 +16,10 @@ use opentelemetry_semantic_conventions as semcov;
 use std::collections::HashMap;
 
 #[cfg(feature = "trace")]
-pub(crate) fn get_tags_for_span(span: &SpanData, resource: &Resource) -> Tags {
+pub(crate) fn get_tags_for_span(span: &SpanData, resource: &Resource, context_tags: &Tags) -> Tags {
     let mut tags = Tags::new();
     build_tags_from_resource_attrs(&mut tags, resource, &span.instrumentation_scope);
+    tags.extend(context_tags);
 
     let attrs_map = build_tags_from_attrs(
         &mut tags,
@@ -65,9 +66,14 @@ pub(crate) fn get_tags_for_span(span: &SpanData, resource: &Resource) -> Tags {
 }
 
 #[cfg(feature = "trace")]
-pub(crate) fn get_tags_for_event(span: &SpanData, resource: &Resource) -> Tags {
+pub(crate) fn get_tags_for_event(
+    span: &SpanData,
+    resource: &Resource,
+    context_tags: &Tags,
+) -> Tags {
     let mut tags = Tags::new();
     build_tags_from_resource_attrs(&mut tags, resource, &span.instrumentation_scope);
+    tags.extend(context_tags);
 
     tags.insert(tags::OPERATION_ID, span.span_context.trace_id().to_string());
     tags.insert(
@@ -77,14 +83,34 @@ pub(crate) fn get_tags_for_event(span: &SpanData, resource: &Resource) -> Tags {
     tags
 }
 
+#[cfg(feature = "trace")]
+pub(crate) fn get_tags_for_page_view(
+    span: &SpanData,
+    event: &Event,
+    resource: &Resource,
+    context_tags: &Tags,
+) -> Tags {
+    let mut tags = get_tags_for_event(span, resource, context_tags);
+    build_tags_from_attrs(
+        &mut tags,
+        event
+            .attributes
+            .iter()
+            .map(|kv| (&kv.key, &kv.value as &dyn AttrValue)),
+    );
+    tags
+}
+
 #[cfg(feature = "metrics")]
 pub(crate) fn get_tags_for_metric(
     resource: &Resource,
     scope: &InstrumentationScope,
     attrs: &[KeyValue],
+    context_tags: &Tags,
 ) -> Tags {
     let mut tags = Tags::new();
     build_tags_from_resource_attrs(&mut tags, resource, scope);
+    tags.extend(context_tags);
     build_tags_from_attrs(
         &mut tags,
         attrs
@@ -99,9 +125,11 @@ pub(crate) fn get_tags_for_log(
     record: &SdkLogRecord,
     instrumentation_scope: &InstrumentationScope,
     resource: &Resource,
+    context_tags: &Tags,
 ) -> Tags {
     let mut tags = Tags::new();
     build_tags_from_resource_attrs(&mut tags, resource, instrumentation_scope);
+    tags.extend(context_tags);
 
     build_tags_from_attrs(
         &mut tags,
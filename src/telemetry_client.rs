@@ -0,0 +1,729 @@
+//! Typed telemetry items for data that doesn't fit OpenTelemetry spans, logs or metrics.
+
+use crate::{
+    convert::{duration_to_string, time_to_string},
+    exception::computed_problem_id,
+    models::{
+        context_tag_keys::{Tags, TAG_KEY_LOOKUP},
+        AvailabilityData, Data, DataPoint, DataPointType, Envelope, EventData, ExceptionData,
+        ExceptionDetails, Measurements, MessageData, MetricData, PageViewData, PageViewPerfData,
+        Properties,
+    },
+    Error, Exporter, HttpClient, SeverityLevel,
+};
+use opentelemetry_sdk::trace::{IdGenerator, RandomIdGenerator};
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    collections::BTreeMap,
+    fmt,
+    mem::take,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+const DEFAULT_MAX_BUFFERED: usize = 10_000;
+
+/// Sends custom Application Insights telemetry items, e.g. business events with measurements,
+/// availability test results or page views, similar to the `TelemetryClient` of the Application
+/// Insights SDKs.
+///
+/// Items are buffered until [`TelemetryClient::flush`] is called and then sent with the
+/// configuration of the exporter, including its retry policy, offline storage, additional
+/// connection strings, context tags, common properties and telemetry processors. Items, which are
+/// still buffered when the client is dropped, are not sent.
+///
+/// At most 10,000 items are buffered (see [`TelemetryClient::with_max_buffered`]). Items tracked
+/// while the buffer is full are dropped and counted as
+/// [dropped in the exporter stats](crate::SignalStats::dropped).
+///
+/// ```no_run
+/// use opentelemetry_application_insights::{
+///     EventTelemetry, Exporter, MetricTelemetry, TelemetryClient,
+/// };
+///
+/// # async fn run() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// let connection_string = std::env::var("APPLICATIONINSIGHTS_CONNECTION_STRING")?;
+/// let exporter = Exporter::new_from_connection_string(connection_string, reqwest::Client::new())?;
+/// let client = TelemetryClient::new(exporter);
+///
+/// client.track_event(
+///     EventTelemetry::new("checkout")
+///         .with_property("payment", "card")
+///         .with_measurement("total", 42.5),
+/// );
+/// client.track_metric(MetricTelemetry::new("queue length", 7.0).with_namespace("orders"));
+/// client.flush().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "trace")))]
+pub struct TelemetryClient<C> {
+    exporter: Exporter<C>,
+    buffer: Mutex<Vec<Envelope>>,
+    max_buffered: usize,
+}
+
+impl<C: fmt::Debug> fmt::Debug for TelemetryClient<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TelemetryClient")
+            .field("exporter", &self.exporter)
+            .field("buffered", &self.buffer.lock().unwrap().len())
+            .field("max_buffered", &self.max_buffered)
+            .finish()
+    }
+}
+
+impl<C> TelemetryClient<C> {
+    /// Create a new telemetry client, which sends items with the given exporter.
+    pub fn new(exporter: Exporter<C>) -> Self {
+        Self {
+            exporter,
+            buffer: Mutex::new(Vec::new()),
+            max_buffered: DEFAULT_MAX_BUFFERED,
+        }
+    }
+
+    /// Set the maximum number of items, which are buffered until the next
+    /// [`flush`](TelemetryClient::flush). Items tracked while the buffer is full are dropped.
+    ///
+    /// Default: 10,000.
+    pub fn with_max_buffered(mut self, max_buffered: usize) -> Self {
+        self.max_buffered = max_buffered;
+        self
+    }
+
+    /// Track a custom event.
+    ///
+    /// The item is dropped if the buffer is full.
+    pub fn track_event(&self, event: EventTelemetry) {
+        let Common {
+            time,
+            properties,
+            tags,
+        } = event.common;
+        let data = Data::Event(EventData {
+            ver: 2,
+            name: event.name.into(),
+            properties: none_if_empty(properties),
+            measurements: none_if_empty(event.measurements),
+        });
+        self.track("Microsoft.ApplicationInsights.Event", time, tags, data);
+    }
+
+    /// Track a single metric value.
+    ///
+    /// The item is dropped if the buffer is full.
+    pub fn track_metric(&self, metric: MetricTelemetry) {
+        let Common {
+            time,
+            properties,
+            tags,
+        } = metric.common;
+        let data = Data::Metric(MetricData {
+            ver: 2,
+            metrics: vec![DataPoint {
+                ns: metric.namespace.map(Into::into),
+                name: metric.name.into(),
+                kind: Some(DataPointType::Measurement),
+                value: metric.value,
+            }],
+            properties: none_if_empty(properties),
+        });
+        self.track("Microsoft.ApplicationInsights.Metric", time, tags, data);
+    }
+
+    /// Track an exception.
+    ///
+    /// The item is dropped if the buffer is full.
+    pub fn track_exception(&self, exception: ExceptionTelemetry) {
+        let Common {
+            time,
+            properties,
+            tags,
+        } = exception.common;
+        let details = ExceptionDetails::new(
+            exception.type_name.into(),
+            exception.message.into(),
+            exception.stack.as_deref(),
+        );
+        let data = Data::Exception(ExceptionData {
+            ver: 2,
+            problem_id: exception
+                .problem_id
+                .map(Into::into)
+                .or_else(|| computed_problem_id(&details)),
+            exceptions: vec![details],
+            severity_level: exception.severity_level.map(Into::into),
+            properties: none_if_empty(properties),
+            measurements: none_if_empty(exception.measurements),
+        });
+        self.track("Microsoft.ApplicationInsights.Exception", time, tags, data);
+    }
+
+    /// Track a trace (log) message.
+    ///
+    /// The item is dropped if the buffer is full.
+    pub fn track_trace(&self, trace: TraceTelemetry) {
+        let Common {
+            time,
+            properties,
+            tags,
+        } = trace.common;
+        let data = Data::Message(MessageData {
+            ver: 2,
+            message: trace.message.into(),
+            severity_level: trace.severity_level.map(Into::into),
+            properties: none_if_empty(properties),
+        });
+        self.track("Microsoft.ApplicationInsights.Message", time, tags, data);
+    }
+
+    /// Track the result of an availability test.
+    ///
+    /// The item is dropped if the buffer is full.
+    pub fn track_availability(&self, availability: AvailabilityTelemetry) {
+        let Common {
+            time,
+            properties,
+            tags,
+        } = availability.common;
+        let data = Data::Availability(AvailabilityData {
+            ver: 2,
+            id: availability
+                .id
+                .unwrap_or_else(|| RandomIdGenerator::default().new_span_id().to_string())
+                .into(),
+            name: availability.name.into(),
+            duration: duration_to_string(availability.duration),
+            success: availability.success,
+            run_location: availability.run_location.map(Into::into),
+            message: availability.message.map(Into::into),
+            properties: none_if_empty(properties),
+            measurements: none_if_empty(availability.measurements),
+        });
+        self.track(
+            "Microsoft.ApplicationInsights.Availability",
+            time,
+            tags,
+            data,
+        );
+    }
+
+    /// Track a page view.
+    ///
+    /// The item is dropped if the buffer is full.
+    pub fn track_page_view(&self, page_view: PageViewTelemetry) {
+        let Common {
+            time,
+            properties,
+            tags,
+        } = page_view.common;
+        let data = Data::PageView(PageViewData {
+            ver: 2,
+            name: page_view.name.into(),
+            id: page_view.id.map(Into::into),
+            url: page_view.url.map(Into::into),
+            duration: page_view.duration.map(duration_to_string),
+            referrer_uri: page_view.referrer_uri.map(Into::into),
+            properties: none_if_empty(properties),
+            measurements: none_if_empty(page_view.measurements),
+        });
+        self.track("Microsoft.ApplicationInsights.PageView", time, tags, data);
+    }
+
+    /// Track performance information of a page view, e.g. collected by the browser.
+    ///
+    /// The item is dropped if the buffer is full.
+    pub fn track_page_view_performance(&self, performance: PageViewPerformanceTelemetry) {
+        let Common {
+            time,
+            properties,
+            tags,
+        } = performance.common;
+        let data = Data::PageViewPerf(PageViewPerfData {
+            ver: 2,
+            name: performance.name.into(),
+            id: performance.id.map(Into::into),
+            url: performance.url.map(Into::into),
+            duration: performance.perf_total.map(duration_to_string),
+            referrer_uri: performance.referrer_uri.map(Into::into),
+            perf_total: performance.perf_total.map(duration_to_string),
+            network_connect: performance.network_connect.map(duration_to_string),
+            sent_request: performance.sent_request.map(duration_to_string),
+            received_response: performance.received_response.map(duration_to_string),
+            dom_processing: performance.dom_processing.map(duration_to_string),
+            properties: none_if_empty(properties),
+            measurements: none_if_empty(performance.measurements),
+        });
+        self.track(
+            "Microsoft.ApplicationInsights.PageviewPerformance",
+            time,
+            tags,
+            data,
+        );
+    }
+
+    fn track(&self, name: &'static str, time: Option<SystemTime>, item_tags: Tags, mut data: Data) {
+        let mut tags = self.exporter.context_tags.clone();
+        tags.extend(&item_tags);
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.len() >= self.max_buffered {
+            let counters = match data {
+                Data::Metric(_) => &self.exporter.stats.metrics,
+                _ => &self.exporter.stats.logs,
+            };
+            counters.record_dropped(1);
+            return;
+        }
+        // Buffered items only keep as much of long property values as the overflow policy needs.
+        if let Some(properties) = data.properties_mut() {
+            let max_value_len = self.exporter.overflow_policy.max_value_len();
+            for value in properties.values_mut() {
+                value.shrink_to(max_value_len);
+            }
+        }
+        let envelope = Envelope {
+            name,
+            time: time_to_string(time.unwrap_or_else(SystemTime::now)).into(),
+            sample_rate: None,
+            i_key: Some(self.exporter.instrumentation_key.clone().into()),
+            tags: Some(tags).filter(|x| !x.is_empty()),
+            data: Some(data),
+        };
+        buffer.push(envelope);
+    }
+}
+
+impl<C: HttpClient + 'static> TelemetryClient<C> {
+    /// Send all buffered items.
+    ///
+    /// Metrics are counted as `metrics` and all other items as `logs` in the
+    /// [exporter stats](Exporter::stats).
+    pub async fn flush(&self) -> Result<(), Error> {
+        let envelopes = take(&mut *self.buffer.lock().unwrap());
+        if envelopes.is_empty() {
+            return Ok(());
+        }
+
+        let (metrics, others): (Vec<_>, Vec<_>) = envelopes
+            .into_iter()
+            .partition(|envelope| matches!(envelope.data, Some(Data::Metric(_))));
+        let metrics_result = if metrics.is_empty() {
+            Ok(())
+        } else {
+            self.exporter
+                .upload(|stats| &stats.metrics, metrics.into())
+                .await
+        };
+        let others_result = if others.is_empty() {
+            Ok(())
+        } else {
+            self.exporter
+                .upload(|stats| &stats.logs, others.into())
+                .await
+        };
+        metrics_result.and(others_result)
+    }
+}
+
+fn none_if_empty<K, V>(map: BTreeMap<K, V>) -> Option<BTreeMap<K, V>> {
+    Some(map).filter(|map| !map.is_empty())
+}
+
+/// Fields shared by all telemetry items.
+#[derive(Debug, Clone)]
+struct Common {
+    time: Option<SystemTime>,
+    properties: Properties,
+    tags: Tags,
+}
+
+impl Common {
+    fn new() -> Self {
+        Self {
+            time: None,
+            properties: Properties::new(),
+            tags: Tags::new(),
+        }
+    }
+}
+
+macro_rules! common_builders {
+    ($($ty:ident),*) => {
+        $(impl $ty {
+            /// Set the time of the item.
+            ///
+            /// Default: the time the item is tracked.
+            pub fn with_time(mut self, time: SystemTime) -> Self {
+                self.common.time = Some(time);
+                self
+            }
+
+            /// Add a custom property.
+            pub fn with_property(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+                self.common
+                    .properties
+                    .insert(key.into().into(), value.into().into());
+                self
+            }
+
+            /// Set a context tag, e.g. [`attrs::OPERATION_ID`](crate::attrs::OPERATION_ID) to
+            /// correlate the item with a trace. Keys, which are not one of the
+            /// [`attrs`](crate::attrs) constants for context tags, are ignored.
+            ///
+            /// These tags override the context tags of the exporter.
+            pub fn with_tag(mut self, key: &str, value: impl Into<String>) -> Self {
+                if let Some(key) = TAG_KEY_LOOKUP.get(key) {
+                    self.common.tags.insert(key.clone(), value.into());
+                }
+                self
+            }
+        })*
+    };
+}
+
+macro_rules! measurement_builders {
+    ($($ty:ident),*) => {
+        $(impl $ty {
+            /// Add a custom measurement, which can be charted and aggregated in the portal.
+            pub fn with_measurement(mut self, key: impl Into<String>, value: f64) -> Self {
+                self.measurements.insert(key.into().into(), value);
+                self
+            }
+        })*
+    };
+}
+
+common_builders!(
+    EventTelemetry,
+    MetricTelemetry,
+    ExceptionTelemetry,
+    TraceTelemetry,
+    AvailabilityTelemetry,
+    PageViewTelemetry,
+    PageViewPerformanceTelemetry
+);
+measurement_builders!(
+    EventTelemetry,
+    ExceptionTelemetry,
+    AvailabilityTelemetry,
+    PageViewTelemetry,
+    PageViewPerformanceTelemetry
+);
+
+/// Custom event for [`TelemetryClient::track_event`].
+#[derive(Debug, Clone)]
+#[cfg_attr(docsrs, doc(cfg(feature = "trace")))]
+pub struct EventTelemetry {
+    name: String,
+    measurements: Measurements,
+    common: Common,
+}
+
+impl EventTelemetry {
+    /// Create a new event. Keep the name low cardinality to allow proper grouping.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            measurements: Measurements::new(),
+            common: Common::new(),
+        }
+    }
+}
+
+/// Metric value for [`TelemetryClient::track_metric`].
+#[derive(Debug, Clone)]
+#[cfg_attr(docsrs, doc(cfg(feature = "trace")))]
+pub struct MetricTelemetry {
+    name: String,
+    namespace: Option<String>,
+    value: f64,
+    common: Common,
+}
+
+impl MetricTelemetry {
+    /// Create a new metric value.
+    pub fn new(name: impl Into<String>, value: f64) -> Self {
+        Self {
+            name: name.into(),
+            namespace: None,
+            value,
+            common: Common::new(),
+        }
+    }
+
+    /// Set the namespace of the metric.
+    pub fn with_namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.namespace = Some(namespace.into());
+        self
+    }
+}
+
+/// Exception for [`TelemetryClient::track_exception`].
+#[derive(Debug, Clone)]
+#[cfg_attr(docsrs, doc(cfg(feature = "trace")))]
+pub struct ExceptionTelemetry {
+    type_name: String,
+    message: String,
+    stack: Option<String>,
+    severity_level: Option<SeverityLevel>,
+    problem_id: Option<String>,
+    measurements: Measurements,
+    common: Common,
+}
+
+impl ExceptionTelemetry {
+    /// Create a new exception.
+    pub fn new(type_name: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            type_name: type_name.into(),
+            message: message.into(),
+            stack: None,
+            severity_level: None,
+            problem_id: None,
+            measurements: Measurements::new(),
+            common: Common::new(),
+        }
+    }
+
+    /// Set the stack trace, e.g. a formatted [`std::backtrace::Backtrace`]. Stack traces in the
+    /// format of Rust backtraces are also sent as parsed stack frames.
+    pub fn with_stack(mut self, stack: impl Into<String>) -> Self {
+        self.stack = Some(stack.into());
+        self
+    }
+
+    /// Set the stack trace from a captured backtrace. Does nothing if the backtrace was not
+    /// captured.
+    pub fn with_backtrace(self, backtrace: &Backtrace) -> Self {
+        match backtrace.status() {
+            BacktraceStatus::Captured => self.with_stack(backtrace.to_string()),
+            _ => self,
+        }
+    }
+
+    /// Set the severity level.
+    pub fn with_severity_level(mut self, severity_level: SeverityLevel) -> Self {
+        self.severity_level = Some(severity_level);
+        self
+    }
+
+    /// Set the problem id, which is used to group related exceptions. Defaults to the exception
+    /// type and the first function of a Rust backtrace outside of the standard library.
+    pub fn with_problem_id(mut self, problem_id: impl Into<String>) -> Self {
+        self.problem_id = Some(problem_id.into());
+        self
+    }
+}
+
+/// Trace (log) message for [`TelemetryClient::track_trace`].
+#[derive(Debug, Clone)]
+#[cfg_attr(docsrs, doc(cfg(feature = "trace")))]
+pub struct TraceTelemetry {
+    message: String,
+    severity_level: Option<SeverityLevel>,
+    common: Common,
+}
+
+impl TraceTelemetry {
+    /// Create a new trace message.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            severity_level: None,
+            common: Common::new(),
+        }
+    }
+
+    /// Set the severity level.
+    pub fn with_severity_level(mut self, severity_level: SeverityLevel) -> Self {
+        self.severity_level = Some(severity_level);
+        self
+    }
+}
+
+/// Availability test result for [`TelemetryClient::track_availability`].
+#[derive(Debug, Clone)]
+#[cfg_attr(docsrs, doc(cfg(feature = "trace")))]
+pub struct AvailabilityTelemetry {
+    id: Option<String>,
+    name: String,
+    duration: Duration,
+    success: bool,
+    run_location: Option<String>,
+    message: Option<String>,
+    measurements: Measurements,
+    common: Common,
+}
+
+impl AvailabilityTelemetry {
+    /// Create a new availability test result.
+    pub fn new(name: impl Into<String>, duration: Duration, success: bool) -> Self {
+        Self {
+            id: None,
+            name: name.into(),
+            duration,
+            success,
+            run_location: None,
+            message: None,
+            measurements: Measurements::new(),
+            common: Common::new(),
+        }
+    }
+
+    /// Set the id of the test run.
+    ///
+    /// Default: a random id.
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Set the name of the location the test was run from.
+    pub fn with_run_location(mut self, run_location: impl Into<String>) -> Self {
+        self.run_location = Some(run_location.into());
+        self
+    }
+
+    /// Set a diagnostic message for the result.
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+}
+
+/// Page view for [`TelemetryClient::track_page_view`].
+#[derive(Debug, Clone)]
+#[cfg_attr(docsrs, doc(cfg(feature = "trace")))]
+pub struct PageViewTelemetry {
+    name: String,
+    id: Option<String>,
+    url: Option<String>,
+    duration: Option<Duration>,
+    referrer_uri: Option<String>,
+    measurements: Measurements,
+    common: Common,
+}
+
+impl PageViewTelemetry {
+    /// Create a new page view.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            id: None,
+            url: None,
+            duration: None,
+            referrer_uri: None,
+            measurements: Measurements::new(),
+            common: Common::new(),
+        }
+    }
+
+    /// Set the id of the page view, which can be used to correlate other items with it.
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Set the URL of the page, including the query string.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Set the duration of the page view.
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Set the URL of the referring page.
+    pub fn with_referrer_uri(mut self, referrer_uri: impl Into<String>) -> Self {
+        self.referrer_uri = Some(referrer_uri.into());
+        self
+    }
+}
+
+/// Page view performance information for [`TelemetryClient::track_page_view_performance`].
+#[derive(Debug, Clone)]
+#[cfg_attr(docsrs, doc(cfg(feature = "trace")))]
+pub struct PageViewPerformanceTelemetry {
+    name: String,
+    id: Option<String>,
+    url: Option<String>,
+    referrer_uri: Option<String>,
+    perf_total: Option<Duration>,
+    network_connect: Option<Duration>,
+    sent_request: Option<Duration>,
+    received_response: Option<Duration>,
+    dom_processing: Option<Duration>,
+    measurements: Measurements,
+    common: Common,
+}
+
+impl PageViewPerformanceTelemetry {
+    /// Create new page view performance information.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            id: None,
+            url: None,
+            referrer_uri: None,
+            perf_total: None,
+            network_connect: None,
+            sent_request: None,
+            received_response: None,
+            dom_processing: None,
+            measurements: Measurements::new(),
+            common: Common::new(),
+        }
+    }
+
+    /// Set the id of the page view this information belongs to.
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Set the URL of the page, including the query string.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Set the URL of the referring page.
+    pub fn with_referrer_uri(mut self, referrer_uri: impl Into<String>) -> Self {
+        self.referrer_uri = Some(referrer_uri.into());
+        self
+    }
+
+    /// Set the total time to load the page. This is also the duration of the item.
+    pub fn with_perf_total(mut self, perf_total: Duration) -> Self {
+        self.perf_total = Some(perf_total);
+        self
+    }
+
+    /// Set the time to establish the network connection.
+    pub fn with_network_connect(mut self, network_connect: Duration) -> Self {
+        self.network_connect = Some(network_connect);
+        self
+    }
+
+    /// Set the time to send the request.
+    pub fn with_sent_request(mut self, sent_request: Duration) -> Self {
+        self.sent_request = Some(sent_request);
+        self
+    }
+
+    /// Set the time to receive the response.
+    pub fn with_received_response(mut self, received_response: Duration) -> Self {
+        self.received_response = Some(received_response);
+        self
+    }
+
+    /// Set the time to process the DOM.
+    pub fn with_dom_processing(mut self, dom_processing: Duration) -> Self {
+        self.dom_processing = Some(dom_processing);
+        self
+    }
+}
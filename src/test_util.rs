@@ -0,0 +1,628 @@
+//! An in-process fake of the Application Insights ingestion endpoint (`/v2/track`) and the live
+//! metrics endpoints (`QuickPulseService.svc/ping` and `post`) for integration tests.
+//!
+//! The server listens on a random local port and handles each connection on its own thread, so it
+//! works with blocking and async HTTP clients alike. Requests are recorded with decompressed bodies
+//! and can be inspected as typed telemetry. Responses can be scripted to exercise retries,
+//! throttling, redirects and live metrics subscriptions. A fake token endpoint hands out bearer
+//! tokens for testing Microsoft Entra ID authentication. Note that the `HttpClient` implementations
+//! of reqwest turn error status codes into errors and follow redirects on their own, so the exporter
+//! never sees them. Use a client that returns responses as they are to test those cases.
+//!
+//! ```no_run
+//! use opentelemetry_application_insights::{
+//!     test_util::{FakeBreeze, FakeResponse},
+//!     Exporter,
+//! };
+//!
+//! let server = FakeBreeze::start();
+//! server.respond_to_track(FakeResponse::partial(&[(0, 500)]));
+//! let exporter = Exporter::new_from_connection_string(
+//!     server.connection_string(),
+//!     reqwest::blocking::Client::new(),
+//! )
+//! .expect("connection string is valid");
+//! // ... export telemetry ...
+//! for request in server.requests() {
+//!     println!("{:?} {}", request.name, request.response_code);
+//! }
+//! ```
+
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+/// Instrumentation key used in [`FakeBreeze::connection_string`].
+pub const INSTRUMENTATION_KEY: &str = "00000000-0000-0000-0000-000000000000";
+
+/// A fake Application Insights ingestion and live metrics server. The server stops when it's
+/// dropped.
+#[derive(Debug)]
+pub struct FakeBreeze {
+    addr: SocketAddr,
+    state: Arc<State>,
+    accept: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    shutdown: AtomicBool,
+    received: Mutex<Vec<ReceivedRequest>>,
+    track_responses: Mutex<VecDeque<FakeResponse>>,
+    quick_pulse_responses: Mutex<VecDeque<FakeResponse>>,
+    subscribed: AtomicBool,
+    issued_tokens: AtomicUsize,
+    /// Open connections by id, so they can be closed on drop. Removed when the client closes them.
+    connections: Mutex<HashMap<usize, TcpStream>>,
+    next_connection: AtomicUsize,
+}
+
+impl FakeBreeze {
+    /// Start a server on a random port of `127.0.0.1`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server cannot listen on a local port.
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("fake server can listen");
+        let addr = listener.local_addr().expect("fake server has an address");
+        let state = Arc::new(State::default());
+        let accept = {
+            let state = state.clone();
+            std::thread::spawn(move || accept(listener, state))
+        };
+        Self {
+            addr,
+            state,
+            accept: Some(accept),
+        }
+    }
+
+    /// Base URL of the server, e.g. `http://127.0.0.1:12345`.
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Connection string, which uses this server as ingestion and live endpoint.
+    pub fn connection_string(&self) -> String {
+        format!(
+            "InstrumentationKey={};IngestionEndpoint={endpoint};LiveEndpoint={endpoint}",
+            INSTRUMENTATION_KEY,
+            endpoint = self.endpoint(),
+        )
+    }
+
+    /// Connection string like [`FakeBreeze::connection_string`], which requires Microsoft Entra ID
+    /// authentication (`Authorization=AAD`).
+    pub fn aad_connection_string(&self) -> String {
+        format!("{};Authorization=AAD", self.connection_string())
+    }
+
+    /// URL of the fake token endpoint, e.g. `http://127.0.0.1:12345/token`.
+    ///
+    /// Every request to it gets a new token `fake-token-<N>`, which is valid for an hour, in a JSON
+    /// body like `{"access_token":"fake-token-0","expires_in":3600,"token_type":"Bearer"}`.
+    pub fn token_endpoint(&self) -> String {
+        format!("{}/token", self.endpoint())
+    }
+
+    /// Queue a response for the next request to `/v2/track`. Responses are used in the order they
+    /// were queued. Without queued responses, all items are accepted.
+    pub fn respond_to_track(&self, response: FakeResponse) {
+        self.state
+            .track_responses
+            .lock()
+            .unwrap()
+            .push_back(response);
+    }
+
+    /// Queue a response for the next live metrics request (`ping` or `post`). Responses are used
+    /// in the order they were queued. Without queued responses, the server responds with 200 and
+    /// the subscription state set with [`FakeBreeze::set_subscribed`].
+    pub fn respond_to_quick_pulse(&self, response: FakeResponse) {
+        self.state
+            .quick_pulse_responses
+            .lock()
+            .unwrap()
+            .push_back(response);
+    }
+
+    /// Set whether default live metrics responses tell the exporter to post live metrics
+    /// (`x-ms-qps-subscribed` header).
+    ///
+    /// Default: false.
+    pub fn set_subscribed(&self, subscribed: bool) {
+        self.state.subscribed.store(subscribed, Ordering::Relaxed);
+    }
+
+    /// All requests received so far.
+    pub fn received_requests(&self) -> Vec<ReceivedRequest> {
+        self.state.received.lock().unwrap().clone()
+    }
+
+    /// Remove all received requests.
+    pub fn clear(&self) {
+        self.state.received.lock().unwrap().clear();
+    }
+
+    /// All envelopes received in requests to `/v2/track`, in the order they were received.
+    /// Envelopes of retried requests are included multiple times.
+    pub fn envelopes(&self) -> Vec<TrackedEnvelope> {
+        self.received_requests()
+            .iter()
+            .filter(|request| request.kind == RequestKind::Track)
+            .flat_map(|request| request.envelopes().unwrap_or_default())
+            .collect()
+    }
+
+    /// Received request telemetry.
+    pub fn requests(&self) -> Vec<RequestTelemetry> {
+        self.telemetry("RequestData")
+    }
+
+    /// Received dependency telemetry.
+    pub fn dependencies(&self) -> Vec<DependencyTelemetry> {
+        self.telemetry("RemoteDependencyData")
+    }
+
+    /// Received trace (message) telemetry.
+    pub fn traces(&self) -> Vec<TraceTelemetry> {
+        self.telemetry("MessageData")
+    }
+
+    fn telemetry<T: for<'de> Deserialize<'de>>(&self, base_type: &str) -> Vec<T> {
+        self.envelopes()
+            .into_iter()
+            .filter_map(|envelope| envelope.data)
+            .filter(|data| data.base_type == base_type)
+            .filter_map(|data| serde_json::from_value(data.base_data).ok())
+            .collect()
+    }
+}
+
+impl Drop for FakeBreeze {
+    fn drop(&mut self) {
+        self.state.shutdown.store(true, Ordering::Relaxed);
+        // Wake up the accept loop.
+        let _ = TcpStream::connect(self.addr);
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+        for (_, connection) in self.state.connections.lock().unwrap().drain() {
+            let _ = connection.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// The endpoint a request was sent to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RequestKind {
+    /// Telemetry upload (`/v2/track`).
+    Track,
+    /// Live metrics ping (`QuickPulseService.svc/ping`).
+    QuickPulsePing,
+    /// Live metrics post (`QuickPulseService.svc/post`).
+    QuickPulsePost,
+    /// Token request (`/token`).
+    Token,
+    /// Any other path.
+    Other,
+}
+
+/// A request received by [`FakeBreeze`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ReceivedRequest {
+    /// The endpoint the request was sent to.
+    pub kind: RequestKind,
+    /// HTTP method.
+    pub method: String,
+    /// Path and query.
+    pub path: String,
+    /// Request headers with lowercase names.
+    pub headers: HashMap<String, String>,
+    /// Request body, decompressed if it was gzip compressed.
+    pub body: Vec<u8>,
+}
+
+impl ReceivedRequest {
+    /// Parse the body as JSON.
+    pub fn json(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::from_slice(&self.body)
+    }
+
+    /// Parse the body as a JSON array of envelopes, as sent to `/v2/track`.
+    pub fn envelopes(&self) -> serde_json::Result<Vec<TrackedEnvelope>> {
+        serde_json::from_slice(&self.body)
+    }
+}
+
+/// A telemetry item received by [`FakeBreeze`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct TrackedEnvelope {
+    /// Type name of the telemetry item, e.g. `Microsoft.ApplicationInsights.Request`.
+    pub name: String,
+    /// Time the telemetry item was created.
+    pub time: String,
+    /// Sample rate as a percentage.
+    #[serde(default)]
+    pub sample_rate: Option<f64>,
+    /// Instrumentation key.
+    #[serde(default)]
+    pub i_key: Option<String>,
+    /// Context tags, e.g. `ai.operation.id`.
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Telemetry data.
+    #[serde(default)]
+    pub data: Option<TrackedData>,
+}
+
+/// Data of a telemetry item.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct TrackedData {
+    /// Type of the data, e.g. `RequestData`.
+    pub base_type: String,
+    /// The data. Use [`FakeBreeze::requests`], [`FakeBreeze::dependencies`] or
+    /// [`FakeBreeze::traces`] to get typed data.
+    pub base_data: serde_json::Value,
+}
+
+/// Request telemetry received by [`FakeBreeze`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct RequestTelemetry {
+    /// Identifier of the request.
+    pub id: String,
+    /// Name of the request.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Duration in the format `DD.HH:MM:SS.MMMMMM`.
+    pub duration: String,
+    /// Response code.
+    pub response_code: String,
+    /// Whether the request was successful.
+    pub success: bool,
+    /// Request URL.
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Source of the request.
+    #[serde(default)]
+    pub source: Option<String>,
+    /// Custom properties.
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+}
+
+/// Dependency telemetry received by [`FakeBreeze`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct DependencyTelemetry {
+    /// Name of the dependency call.
+    pub name: String,
+    /// Identifier of the dependency call.
+    #[serde(default)]
+    pub id: Option<String>,
+    /// Result code.
+    #[serde(default)]
+    pub result_code: Option<String>,
+    /// Duration in the format `DD.HH:MM:SS.MMMMMM`.
+    pub duration: String,
+    /// Whether the call was successful.
+    #[serde(default)]
+    pub success: Option<bool>,
+    /// Command of the call, e.g. a URL or SQL statement.
+    #[serde(default)]
+    pub data: Option<String>,
+    /// Target of the call, e.g. a host name.
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Dependency type, e.g. `HTTP`.
+    #[serde(default, rename = "type")]
+    pub type_: Option<String>,
+    /// Custom properties.
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+}
+
+/// Trace (message) telemetry received by [`FakeBreeze`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct TraceTelemetry {
+    /// Trace message.
+    pub message: String,
+    /// Severity level from 0 (verbose) to 4 (critical).
+    #[serde(default)]
+    pub severity_level: Option<u8>,
+    /// Custom properties.
+    #[serde(default)]
+    pub properties: BTreeMap<String, String>,
+}
+
+/// A scripted response of [`FakeBreeze`].
+#[derive(Debug, Clone)]
+pub struct FakeResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Body,
+}
+
+#[derive(Debug, Clone)]
+enum Body {
+    Fixed(Vec<u8>),
+    /// A track response for the items in the request, rejecting the items at the given indexes.
+    Track(Vec<(usize, u16)>),
+}
+
+impl FakeResponse {
+    /// 200 response. For `/v2/track` requests all items are accepted.
+    pub fn ok() -> Self {
+        Self {
+            status: 200,
+            headers: Vec::new(),
+            body: Body::Track(Vec::new()),
+        }
+    }
+
+    /// Response with the given status code and an empty body.
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Body::Fixed(Vec::new()),
+        }
+    }
+
+    /// 206 response, which rejects the items at the given indexes with the given status codes
+    /// and accepts all others.
+    pub fn partial(errors: &[(usize, u16)]) -> Self {
+        Self {
+            status: 206,
+            headers: Vec::new(),
+            body: Body::Track(errors.to_vec()),
+        }
+    }
+
+    /// Response with the given status code (e.g. 429, 439 or 503) and a `Retry-After` header.
+    pub fn throttled(status: u16, retry_after_secs: u64) -> Self {
+        Self::status(status).with_header("retry-after", retry_after_secs.to_string())
+    }
+
+    /// Redirect (e.g. 307 or 308) to the given absolute URL.
+    pub fn redirect(status: u16, location: impl Into<String>) -> Self {
+        Self::status(status).with_header("location", location)
+    }
+
+    /// Live metrics response, which tells the exporter whether to post live metrics.
+    pub fn quick_pulse(subscribed: bool) -> Self {
+        Self::status(200).with_header("x-ms-qps-subscribed", subscribed.to_string())
+    }
+
+    /// Add a response header, e.g. `x-ms-qps-service-endpoint-redirect-v2`.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Replace the response body.
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Body::Fixed(body.into());
+        self
+    }
+
+    fn body(&self, request: &ReceivedRequest) -> Vec<u8> {
+        match &self.body {
+            Body::Fixed(body) => body.clone(),
+            Body::Track(errors) => {
+                let received = request.envelopes().map_or(0, |envelopes| envelopes.len());
+                let rejected = errors
+                    .iter()
+                    .filter(|(index, _)| *index < received)
+                    .map(|(index, _)| index)
+                    .collect::<BTreeSet<_>>()
+                    .len();
+                let errors: Vec<_> = errors
+                    .iter()
+                    .filter(|(index, _)| *index < received)
+                    .map(|(index, status_code)| {
+                        serde_json::json!({
+                            "index": index,
+                            "statusCode": status_code,
+                            "message": "rejected by fake server",
+                        })
+                    })
+                    .collect();
+                serde_json::to_vec(&serde_json::json!({
+                    "itemsReceived": received,
+                    "itemsAccepted": received - rejected,
+                    "errors": errors,
+                }))
+                .expect("track response can be serialized")
+            }
+        }
+    }
+}
+
+fn accept(listener: TcpListener, state: Arc<State>) {
+    for stream in listener.incoming() {
+        if state.shutdown.load(Ordering::Relaxed) {
+            break;
+        }
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let id = state.next_connection.fetch_add(1, Ordering::Relaxed);
+        if let Ok(clone) = stream.try_clone() {
+            state.connections.lock().unwrap().insert(id, clone);
+        }
+        let state = state.clone();
+        std::thread::spawn(move || {
+            let _ = serve(stream, &state);
+            state.connections.lock().unwrap().remove(&id);
+        });
+    }
+}
+
+/// Serves requests on a connection until the client closes it.
+fn serve(stream: TcpStream, state: &State) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    while let Some(request) = read_request(&mut reader)? {
+        let response = match request.kind {
+            RequestKind::Track => state.track_responses.lock().unwrap().pop_front(),
+            RequestKind::QuickPulsePing | RequestKind::QuickPulsePost => {
+                state.quick_pulse_responses.lock().unwrap().pop_front()
+            }
+            RequestKind::Token => Some(token_response(state)),
+            RequestKind::Other => Some(FakeResponse::status(404)),
+        }
+        .unwrap_or_else(|| match request.kind {
+            RequestKind::Track => FakeResponse::ok(),
+            _ => FakeResponse::quick_pulse(state.subscribed.load(Ordering::Relaxed)),
+        });
+        let body = response.body(&request);
+        state.received.lock().unwrap().push(request);
+        write_response(&mut writer, &response, &body)?;
+    }
+    Ok(())
+}
+
+fn token_response(state: &State) -> FakeResponse {
+    let token = state.issued_tokens.fetch_add(1, Ordering::Relaxed);
+    let body = serde_json::json!({
+        "access_token": format!("fake-token-{token}"),
+        "expires_in": 3600,
+        "token_type": "Bearer",
+    });
+    FakeResponse::status(200).with_body(body.to_string())
+}
+
+fn read_request(reader: &mut impl BufRead) -> io::Result<Option<ReceivedRequest>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let mut body = Vec::new();
+    if headers
+        .get("transfer-encoding")
+        .is_some_and(|value| value.eq_ignore_ascii_case("chunked"))
+    {
+        read_chunked(reader, &mut body)?;
+    } else if let Some(length) = headers.get("content-length") {
+        let length = length
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid content-length"))?;
+        body.resize(length, 0);
+        reader.read_exact(&mut body)?;
+    }
+    if headers
+        .get("content-encoding")
+        .is_some_and(|value| value.eq_ignore_ascii_case("gzip"))
+    {
+        let mut decoded = Vec::new();
+        GzDecoder::new(body.as_slice()).read_to_end(&mut decoded)?;
+        body = decoded;
+    }
+
+    let kind = if path.ends_with("/v2/track") {
+        RequestKind::Track
+    } else if path.contains("QuickPulseService.svc/ping") {
+        RequestKind::QuickPulsePing
+    } else if path.contains("QuickPulseService.svc/post") {
+        RequestKind::QuickPulsePost
+    } else if path.ends_with("/token") {
+        RequestKind::Token
+    } else {
+        RequestKind::Other
+    };
+    Ok(Some(ReceivedRequest {
+        kind,
+        method,
+        path,
+        headers,
+        body,
+    }))
+}
+
+fn read_chunked(reader: &mut impl BufRead, body: &mut Vec<u8>) -> io::Result<()> {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let size = line.trim_end().split(';').next().unwrap_or_default();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid chunk size"))?;
+        if size == 0 {
+            // Skip trailers.
+            loop {
+                line.clear();
+                reader.read_line(&mut line)?;
+                if line.trim_end().is_empty() {
+                    return Ok(());
+                }
+            }
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        line.clear();
+        reader.read_line(&mut line)?;
+    }
+}
+
+fn write_response(writer: &mut impl Write, response: &FakeResponse, body: &[u8]) -> io::Result<()> {
+    let reason = http::StatusCode::from_u16(response.status)
+        .ok()
+        .and_then(|status| status.canonical_reason())
+        .unwrap_or("Unknown");
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\ncontent-length: {}\r\n",
+        response.status,
+        reason,
+        body.len()
+    );
+    if !body.is_empty() {
+        head.push_str("content-type: application/json\r\n");
+    }
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    writer.write_all(head.as_bytes())?;
+    writer.write_all(body)?;
+    writer.flush()
+}
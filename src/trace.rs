@@ -3,25 +3,32 @@ use crate::{
         attrs_map_to_properties, attrs_to_map, attrs_to_properties, duration_to_string,
         status_to_result_code, time_to_string, value_to_severity_level,
     },
+    destination::{Route, RoutedEnvelopes},
+    exception::{exceptions_from_attrs, problem_id},
     models::{
-        context_tag_keys::attrs::CUSTOM_EVENT_NAME, Data, Envelope, EventData, ExceptionData,
-        ExceptionDetails, LimitedLenString, MessageData, RemoteDependencyData, RequestData,
+        context_tag_keys::attrs::{
+            AVAILABILITY_DURATION, AVAILABILITY_ID, AVAILABILITY_MESSAGE,
+            AVAILABILITY_RUN_LOCATION, AVAILABILITY_SUCCESS, CONNECTION_STRING, CUSTOM_EVENT_NAME,
+            PAGE_VIEW_DURATION, PAGE_VIEW_NAME, PAGE_VIEW_REFERRER_URI, PAGE_VIEW_URL,
+        },
+        AvailabilityData, Data, Envelope, EventData, ExceptionData, LimitedLenString, MessageData,
+        PageViewData, RemoteDependencyData, RequestData, SeverityLevel,
     },
-    tags::{get_tags_for_event, get_tags_for_span},
+    tags::{get_tags_for_event, get_tags_for_page_view, get_tags_for_span},
     Exporter,
 };
 use opentelemetry::{
     trace::{Event, SpanKind, Status},
-    Value,
+    Key, KeyValue, Value,
 };
 use opentelemetry_http::HttpClient;
 use opentelemetry_sdk::{
     error::OTelSdkResult,
-    trace::{SpanData, SpanExporter},
+    trace::{IdGenerator, RandomIdGenerator, SpanData, SpanExporter},
     Resource,
 };
 use opentelemetry_semantic_conventions as semcov;
-use std::{borrow::Cow, collections::HashMap, sync::Arc, time::Duration};
+use std::{borrow::Cow, collections::HashMap, time::Duration};
 
 /// Deprecated semantic convention key for HTTP host
 ///
@@ -63,15 +70,48 @@ const DEPRECATED_SERVER_SOCKET_PORT: &str = "server.socket.port";
 
 pub(crate) const EVENT_NAME_CUSTOM: &str = "ai.custom";
 pub(crate) const EVENT_NAME_EXCEPTION: &str = "exception";
+pub(crate) const EVENT_NAME_PAGE_VIEW: &str = "ai.pageView";
+
+/// Prefix of the attributes, which describe availability test results and are not converted to
+/// custom properties.
+const AVAILABILITY_PREFIX: &str = "ai.availability.";
 
 impl<C> Exporter<C> {
+    /// Returns the route of the span.
+    fn route_for_span(&self, span: &SpanData) -> Route {
+        let Some(connection_string) = self.connection_string_for_span(span) else {
+            return Route::Default;
+        };
+        self.routes.get(&connection_string)
+    }
+
+    fn connection_string_for_span(&self, span: &SpanData) -> Option<String> {
+        Some(match self.span_router {
+            Some(ref router) => router(span)?,
+            None => span
+                .attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == CONNECTION_STRING)
+                .map(|kv| kv.value.clone())
+                .or_else(|| self.resource.get(&Key::from_static_str(CONNECTION_STRING)))?
+                .as_str()
+                .into_owned(),
+        })
+    }
+
     fn create_envelopes_for_span(&self, span: SpanData, resource: &Resource) -> Vec<Envelope> {
         let mut result = Vec::with_capacity(1 + span.events.len());
+        let max_value_len = self.overflow_policy.max_value_len();
 
-        let (data, tags, name) = match span.span_kind {
+        let (mut data, tags, name) = match span.span_kind {
+            _ if is_availability(&span.attributes) => (
+                Data::Availability(SpanAndResource(&span, resource, max_value_len).into()),
+                get_tags_for_span(&span, resource, &self.context_tags),
+                "Microsoft.ApplicationInsights.Availability",
+            ),
             SpanKind::Server | SpanKind::Consumer => {
-                let data: RequestData = SpanAndResource(&span, resource).into();
-                let tags = get_tags_for_span(&span, resource);
+                let data: RequestData = SpanAndResource(&span, resource, max_value_len).into();
+                let tags = get_tags_for_span(&span, resource, &self.context_tags);
                 (
                     Data::Request(data),
                     tags,
@@ -79,8 +119,9 @@ impl<C> Exporter<C> {
                 )
             }
             SpanKind::Client | SpanKind::Producer | SpanKind::Internal => {
-                let data: RemoteDependencyData = SpanAndResource(&span, resource).into();
-                let tags = get_tags_for_span(&span, resource);
+                let data: RemoteDependencyData =
+                    SpanAndResource(&span, resource, max_value_len).into();
+                let tags = get_tags_for_span(&span, resource, &self.context_tags);
                 (
                     Data::RemoteDependency(data),
                     tags,
@@ -88,6 +129,9 @@ impl<C> Exporter<C> {
                 )
             }
         };
+        if let Some(ref measurement_attributes) = self.measurement_attributes {
+            measurement_attributes.apply(&mut data, span.attributes.iter());
+        }
         result.push(Envelope {
             name,
             time: time_to_string(span.start_time).into(),
@@ -103,26 +147,57 @@ impl<C> Exporter<C> {
             None
         };
         for event in span.events.iter() {
-            let (data, name) = match event.name.as_ref() {
+            let mut tags = None;
+            let (mut data, name) = match event.name.as_ref() {
+                _ if is_availability(&event.attributes) => (
+                    Data::Availability(availability_for_event(
+                        event,
+                        event_resource,
+                        max_value_len,
+                    )),
+                    "Microsoft.ApplicationInsights.Availability",
+                ),
+                x if x == EVENT_NAME_PAGE_VIEW => {
+                    tags = Some(get_tags_for_page_view(
+                        &span,
+                        event,
+                        resource,
+                        &self.context_tags,
+                    ));
+                    (
+                        Data::PageView(page_view_for_event(
+                            &span,
+                            event,
+                            event_resource,
+                            max_value_len,
+                        )),
+                        "Microsoft.ApplicationInsights.PageView",
+                    )
+                }
                 x if x == EVENT_NAME_CUSTOM => (
-                    Data::Event(EventAndResource(event, event_resource).into()),
+                    Data::Event(EventAndResource(event, event_resource, max_value_len).into()),
                     "Microsoft.ApplicationInsights.Event",
                 ),
                 x if x == EVENT_NAME_EXCEPTION => (
-                    Data::Exception(EventAndResource(event, event_resource).into()),
+                    Data::Exception(EventAndResource(event, event_resource, max_value_len).into()),
                     "Microsoft.ApplicationInsights.Exception",
                 ),
                 _ => (
-                    Data::Message(EventAndResource(event, event_resource).into()),
+                    Data::Message(EventAndResource(event, event_resource, max_value_len).into()),
                     "Microsoft.ApplicationInsights.Message",
                 ),
             };
+            if let Some(ref measurement_attributes) = self.measurement_attributes {
+                measurement_attributes.apply(&mut data, event.attributes.iter());
+            }
             result.push(Envelope {
                 name,
                 time: time_to_string(event.timestamp).into(),
                 sample_rate: Some(self.sample_rate),
                 i_key: Some(self.instrumentation_key.clone().into()),
-                tags: Some(get_tags_for_event(&span, resource)),
+                tags: Some(
+                    tags.unwrap_or_else(|| get_tags_for_event(&span, resource, &self.context_tags)),
+                ),
                 data: Some(data),
             });
         }
@@ -138,21 +213,21 @@ where
 {
     /// Export spans to Application Insights
     async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
-        let client = Arc::clone(&self.client);
-        let endpoint = Arc::clone(&self.track_endpoint);
-        let envelopes: Vec<_> = batch
-            .into_iter()
-            .flat_map(|span| self.create_envelopes_for_span(span, &self.resource))
-            .collect();
+        let mut envelopes = RoutedEnvelopes::default();
+        let mut dropped = 0;
+        for span in batch {
+            let route = self.route_for_span(&span);
+            dropped += envelopes.push(route, self.create_envelopes_for_span(span, &self.resource));
+        }
+        self.stats.traces.record_dropped(dropped);
 
-        crate::uploader::send(
-            client.as_ref(),
-            endpoint.as_ref(),
-            envelopes,
-            self.retry_notify.clone(),
-        )
-        .await
-        .map_err(Into::into)
+        self.upload(|stats| &stats.traces, envelopes)
+            .await
+            .map_err(Into::into)
+    }
+
+    fn shutdown_with_timeout(&mut self, _timeout: Duration) -> OTelSdkResult {
+        self.shutdown_storage().map_err(Into::into)
     }
 
     fn set_resource(&mut self, resource: &Resource) {
@@ -220,10 +295,147 @@ pub(crate) fn is_remote_dependency_success(span: &SpanData) -> Option<bool> {
     }
 }
 
-struct SpanAndResource<'a>(&'a SpanData, &'a Resource);
+fn is_availability(attrs: &[KeyValue]) -> bool {
+    attrs
+        .iter()
+        .any(|kv| kv.key.as_str() == AVAILABILITY_RUN_LOCATION)
+}
+
+fn without_availability_attrs(attrs: &[KeyValue]) -> impl Iterator<Item = &KeyValue> {
+    attrs
+        .iter()
+        .filter(|kv| !kv.key.as_str().starts_with(AVAILABILITY_PREFIX))
+}
+
+fn availability_attr<'a>(attrs: &'a [KeyValue], key: &str) -> Option<&'a Value> {
+    attrs
+        .iter()
+        .find(|kv| kv.key.as_str() == key)
+        .map(|kv| &kv.value)
+}
+
+fn availability_success(attrs: &[KeyValue]) -> Option<bool> {
+    match availability_attr(attrs, AVAILABILITY_SUCCESS)? {
+        Value::Bool(success) => Some(*success),
+        value => value.as_str().parse().ok(),
+    }
+}
+
+fn availability_duration(attrs: &[KeyValue]) -> Option<Duration> {
+    let ms = match availability_attr(attrs, AVAILABILITY_DURATION)? {
+        Value::F64(ms) => *ms,
+        Value::I64(ms) => *ms as f64,
+        value => value.as_str().parse().ok()?,
+    };
+    Some(ms)
+        .filter(|ms| ms.is_finite() && *ms >= 0.0)
+        .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+}
+
+fn availability_for_event(
+    event: &Event,
+    resource: Option<&Resource>,
+    max_value_len: usize,
+) -> AvailabilityData {
+    let attrs = &event.attributes;
+    AvailabilityData {
+        ver: 2,
+        id: availability_attr(attrs, AVAILABILITY_ID)
+            .map(Into::into)
+            .unwrap_or_else(|| {
+                RandomIdGenerator::default()
+                    .new_span_id()
+                    .to_string()
+                    .into()
+            }),
+        name: event.name.clone().into(),
+        duration: duration_to_string(availability_duration(attrs).unwrap_or_default()),
+        success: availability_success(attrs).unwrap_or(true),
+        run_location: availability_attr(attrs, AVAILABILITY_RUN_LOCATION).map(Into::into),
+        message: availability_attr(attrs, AVAILABILITY_MESSAGE).map(Into::into),
+        properties: attrs_to_properties(
+            without_availability_attrs(attrs),
+            resource,
+            max_value_len,
+            &[],
+        ),
+        measurements: None,
+    }
+}
+
+fn page_view_for_event(
+    span: &SpanData,
+    event: &Event,
+    resource: Option<&Resource>,
+    max_value_len: usize,
+) -> PageViewData {
+    let mut attrs = attrs_to_map(event.attributes.iter());
+    let duration = attrs
+        .remove(PAGE_VIEW_DURATION)
+        .and_then(|duration| duration.as_str().parse::<f64>().ok())
+        .filter(|ms| ms.is_finite() && *ms >= 0.0)
+        .map(|ms| Duration::from_secs_f64(ms / 1000.0))
+        .unwrap_or_else(|| get_duration(span));
+    PageViewData {
+        ver: 2,
+        name: attrs
+            .remove(PAGE_VIEW_NAME)
+            .map(Into::into)
+            .unwrap_or_else(|| span.name.clone().into()),
+        id: None,
+        url: attrs.remove(PAGE_VIEW_URL).map(Into::into).or_else(|| {
+            span.attributes
+                .iter()
+                .find(|kv| kv.key.as_str() == semcov::trace::URL_FULL)
+                .map(|kv| (&kv.value).into())
+        }),
+        duration: Some(duration_to_string(duration)),
+        referrer_uri: attrs.remove(PAGE_VIEW_REFERRER_URI).map(Into::into),
+        properties: attrs_map_to_properties(attrs, resource, max_value_len),
+        measurements: None,
+    }
+}
+
+/// A span, the resource and the maximum length of custom property values.
+struct SpanAndResource<'a>(&'a SpanData, &'a Resource, usize);
+
+impl<'a> From<SpanAndResource<'a>> for AvailabilityData {
+    fn from(
+        SpanAndResource(span, resource, max_value_len): SpanAndResource<'a>,
+    ) -> AvailabilityData {
+        let attrs = &span.attributes;
+        AvailabilityData {
+            ver: 2,
+            id: availability_attr(attrs, AVAILABILITY_ID)
+                .map(Into::into)
+                .unwrap_or_else(|| span.span_context.span_id().to_string().into()),
+            name: span.name.clone().into(),
+            duration: duration_to_string(
+                availability_duration(attrs).unwrap_or_else(|| get_duration(span)),
+            ),
+            success: availability_success(attrs).unwrap_or_else(|| is_request_success(span)),
+            run_location: availability_attr(attrs, AVAILABILITY_RUN_LOCATION).map(Into::into),
+            message: availability_attr(attrs, AVAILABILITY_MESSAGE)
+                .map(Into::into)
+                .or_else(|| match span.status {
+                    Status::Error { ref description } if !description.is_empty() => {
+                        Some(description.clone().into())
+                    }
+                    _ => None,
+                }),
+            properties: attrs_to_properties(
+                without_availability_attrs(attrs),
+                Some(resource),
+                max_value_len,
+                &span.links.links,
+            ),
+            measurements: None,
+        }
+    }
+}
 
 impl<'a> From<SpanAndResource<'a>> for RequestData {
-    fn from(SpanAndResource(span, resource): SpanAndResource<'a>) -> RequestData {
+    fn from(SpanAndResource(span, resource, max_value_len): SpanAndResource<'a>) -> RequestData {
         let mut data = RequestData {
             ver: 2,
             id: span.span_context.span_id().to_string().into(),
@@ -237,8 +449,10 @@ impl<'a> From<SpanAndResource<'a>> for RequestData {
             properties: attrs_to_properties(
                 span.attributes.iter(),
                 Some(resource),
+                max_value_len,
                 &span.links.links,
             ),
+            measurements: None,
         };
 
         let attrs: HashMap<&str, &Value> = span
@@ -317,7 +531,9 @@ impl<'a> From<SpanAndResource<'a>> for RequestData {
 }
 
 impl<'a> From<SpanAndResource<'a>> for RemoteDependencyData {
-    fn from(SpanAndResource(span, resource): SpanAndResource<'a>) -> RemoteDependencyData {
+    fn from(
+        SpanAndResource(span, resource, max_value_len): SpanAndResource<'a>,
+    ) -> RemoteDependencyData {
         let mut data = RemoteDependencyData {
             ver: 2,
             id: Some(span.span_context.span_id().to_string().into()),
@@ -331,8 +547,10 @@ impl<'a> From<SpanAndResource<'a>> for RemoteDependencyData {
             properties: attrs_to_properties(
                 span.attributes.iter(),
                 Some(resource),
+                max_value_len,
                 &span.links.links,
             ),
+            measurements: None,
         };
 
         let attrs: HashMap<&str, &Value> = span
@@ -449,35 +667,42 @@ impl<'a> From<SpanAndResource<'a>> for RemoteDependencyData {
     }
 }
 
-struct EventAndResource<'a>(&'a Event, Option<&'a Resource>);
+/// An event, the resource and the maximum length of custom property values.
+struct EventAndResource<'a>(&'a Event, Option<&'a Resource>, usize);
 
 impl From<EventAndResource<'_>> for ExceptionData {
-    fn from(EventAndResource(event, resource): EventAndResource<'_>) -> Self {
+    fn from(EventAndResource(event, resource, max_value_len): EventAndResource<'_>) -> Self {
         let mut attrs = attrs_to_map(event.attributes.iter());
-        let exception = ExceptionDetails {
-            type_name: attrs
-                .remove(semcov::trace::EXCEPTION_TYPE)
-                .map(Into::into)
-                .unwrap_or_else(|| "<no type>".into()),
-            message: attrs
-                .remove(semcov::trace::EXCEPTION_MESSAGE)
-                .map(Into::into)
-                .unwrap_or_else(|| "<no message>".into()),
-            stack: attrs
-                .remove(semcov::trace::EXCEPTION_STACKTRACE)
-                .map(Into::into),
+        let exceptions = exceptions_from_attrs(&mut attrs, "<no type>", "<no message>");
+        let severity_level = if let Some(severity_level) =
+            attrs.get(LEVEL).and_then(|&x| value_to_severity_level(x))
+        {
+            attrs.remove(LEVEL);
+            Some(severity_level)
+        } else {
+            #[allow(deprecated)]
+            let escaped = attrs.get(semcov::attribute::EXCEPTION_ESCAPED);
+            // Escaped exceptions are unhandled, which the Application Insights SDKs report as
+            // critical.
+            match escaped.map(|x| x.as_str()).as_deref() {
+                Some("true") => Some(SeverityLevel::Critical),
+                Some("false") => Some(SeverityLevel::Error),
+                _ => None,
+            }
         };
         ExceptionData {
             ver: 2,
-            exceptions: vec![exception],
-            severity_level: None,
-            properties: attrs_map_to_properties(attrs, resource),
+            problem_id: problem_id(&mut attrs, &exceptions),
+            exceptions,
+            severity_level,
+            properties: attrs_map_to_properties(attrs, resource, max_value_len),
+            measurements: None,
         }
     }
 }
 
 impl From<EventAndResource<'_>> for EventData {
-    fn from(EventAndResource(event, resource): EventAndResource<'_>) -> Self {
+    fn from(EventAndResource(event, resource, max_value_len): EventAndResource<'_>) -> Self {
         let mut attrs = attrs_to_map(event.attributes.iter());
         EventData {
             ver: 2,
@@ -485,7 +710,8 @@ impl From<EventAndResource<'_>> for EventData {
                 .remove(CUSTOM_EVENT_NAME)
                 .map(Into::into)
                 .unwrap_or_else(|| "<no name>".into()),
-            properties: attrs_map_to_properties(attrs, resource),
+            properties: attrs_map_to_properties(attrs, resource, max_value_len),
+            measurements: None,
         }
     }
 }
@@ -496,7 +722,7 @@ impl From<EventAndResource<'_>> for EventData {
 const LEVEL: &str = "level";
 
 impl From<EventAndResource<'_>> for MessageData {
-    fn from(EventAndResource(event, resource): EventAndResource<'_>) -> Self {
+    fn from(EventAndResource(event, resource, max_value_len): EventAndResource<'_>) -> Self {
         let mut attrs = attrs_to_map(event.attributes.iter());
         let severity_level = attrs.get(LEVEL).and_then(|&x| value_to_severity_level(x));
         if severity_level.is_some() {
@@ -510,7 +736,7 @@ impl From<EventAndResource<'_>> for MessageData {
             } else {
                 event.name.clone().into_owned().into()
             },
-            properties: attrs_map_to_properties(attrs, resource),
+            properties: attrs_map_to_properties(attrs, resource, max_value_len),
         }
     }
 }
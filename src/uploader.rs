@@ -1,29 +1,209 @@
-use crate::{models::Envelope, Error, HttpClient};
-use backon::{ExponentialBuilder, FuturesTimerSleeper, RetryableWithContext};
+#[cfg(any(feature = "statsbeat", test))]
+use crate::models::Envelope;
+#[cfg(feature = "statsbeat")]
+use crate::statsbeat::{RequestOutcome, StatsbeatState};
+use crate::{
+    credential::{self, TokenCache},
+    dead_letter::{DeadLetter, DeadLetterNotify},
+    payload::{self, BufferPool, Compression, PayloadWriter},
+    replace_host,
+    retry::RetryPolicy,
+    stats::Counters,
+    storage::LocalStorage,
+    Error, HttpClient,
+};
+use backon::{ExponentialBackoff, FuturesTimerSleeper, RetryableWithContext};
 use bytes::Bytes;
-use flate2::{write::GzEncoder, Compression};
+use chrono::{DateTime, Utc};
 use http::{Request, Response, Uri};
-use serde::{Deserialize, Serialize};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     io::Write,
-    sync::{Arc, Mutex},
-    time::Duration,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
 };
 
 // We need these constants because HTTP 439 is not part of the official HTTP
 // status code registry.
 const STATUS_OK: u16 = 200;
-const STATUS_PARTIAL_CONTENT: u16 = 206;
-const STATUS_REQUEST_TIMEOUT: u16 = 408;
-const STATUS_TOO_MANY_REQUESTS: u16 = 429;
-const STATUS_APPLICATION_INACTIVE: u16 = 439; // Quota
-const STATUS_INTERNAL_SERVER_ERROR: u16 = 500;
-const STATUS_SERVICE_UNAVAILABLE: u16 = 503;
+pub(crate) const STATUS_PARTIAL_CONTENT: u16 = 206;
+const STATUS_TEMPORARY_REDIRECT: u16 = 307;
+const STATUS_PERMANENT_REDIRECT: u16 = 308;
+const STATUS_UNAUTHORIZED: u16 = 401;
+const STATUS_FORBIDDEN: u16 = 403;
+pub(crate) const STATUS_REQUEST_TIMEOUT: u16 = 408;
+pub(crate) const STATUS_TOO_MANY_REQUESTS: u16 = 429;
+pub(crate) const STATUS_APPLICATION_INACTIVE: u16 = 439; // Quota
+pub(crate) const STATUS_INTERNAL_SERVER_ERROR: u16 = 500;
+pub(crate) const STATUS_SERVICE_UNAVAILABLE: u16 = 503;
+
+/// Maximum number of stored batches sent after a successful export. This keeps exports short,
+/// while still draining the offline storage over time.
+const MAX_STORED_BATCHES_PER_EXPORT: usize = 10;
+
+/// Maximum number of redirects followed for a single request, which protects against redirect
+/// loops.
+const MAX_REDIRECTS: usize = 10;
+
+/// How long uploads are paused after a 439 response without `Retry-After` header. The daily cap of
+/// a resource is only reset once a day, so retrying sooner just sends more requests that fail.
+const DEFAULT_QUOTA_RETRY_AFTER: Duration = Duration::from_secs(60 * 60);
+
+/// Limits for a single request. Batches exceeding these are split into multiple requests, since
+/// the ingestion endpoint rejects requests, which are too large or contain too many items.
+const MAX_PAYLOAD_SIZE: usize = 4 * 1024 * 1024;
+const MAX_ITEMS_PER_REQUEST: usize = 1000;
+
+/// Location of a telemetry item in the buffer, which contains all items of an export serialized to
+/// JSON.
+type SerializedItem = Range<usize>;
+
+pub(crate) type RetryNotify = Arc<Mutex<dyn FnMut(&Error, Duration) + Send + 'static>>;
+
+/// Everything needed to upload telemetry to an ingestion endpoint.
+#[derive(Clone, Copy)]
+pub(crate) struct Upload<'a> {
+    pub(crate) client: &'a dyn HttpClient,
+    pub(crate) endpoint: &'a Endpoint,
+    pub(crate) retry_policy: &'a RetryPolicy,
+    pub(crate) retry_notify: Option<&'a RetryNotify>,
+    pub(crate) dead_letter: Option<&'a DeadLetterNotify>,
+    pub(crate) storage: Option<&'a LocalStorage>,
+    pub(crate) credential: Option<&'a TokenCache>,
+    pub(crate) throttle: &'a Throttle,
+    pub(crate) counters: &'a Counters,
+    #[cfg(feature = "statsbeat")]
+    pub(crate) statsbeat: Option<&'a StatsbeatState>,
+    pub(crate) compression: Compression,
+    pub(crate) buffers: &'a BufferPool,
+}
+
+impl Upload<'_> {
+    fn notify(&self, err: &Error, duration: Duration) {
+        if let Some(notify) = self.retry_notify {
+            let mut notify = notify.lock().unwrap();
+            notify(err, duration);
+        }
+    }
+}
 
-const RETRY_MIN_DELAY: Duration = Duration::from_millis(500);
-const RETRY_MAX_DELAY: Duration = Duration::from_secs(5);
-const RETRY_TOTAL_DELAY: Duration = Duration::from_secs(35);
+/// Ingestion endpoint, which follows redirects.
+///
+/// Regional ingestion endpoints may redirect requests to another host using a 307 or 308 response.
+/// The endpoint is shared by all clones of an exporter, so later exports go straight to the
+/// redirected host.
+#[derive(Debug)]
+pub(crate) struct Endpoint {
+    uri: RwLock<Uri>,
+}
+
+impl Endpoint {
+    pub(crate) fn new(uri: Uri) -> Self {
+        Self {
+            uri: RwLock::new(uri),
+        }
+    }
+
+    pub(crate) fn uri(&self) -> Uri {
+        self.uri.read().unwrap().clone()
+    }
+
+    fn redirect(&self, location: Uri) {
+        let mut uri = self.uri.write().unwrap();
+        *uri = replace_host(uri.clone(), location);
+    }
+}
+
+/// Circuit breaker, which stops uploads while the server throttles requests.
+///
+/// It opens when the server responds with a throttling status code (429, 439 or 503) and a
+/// `Retry-After` header, or with 439 (daily cap exceeded) without header. It's shared by all
+/// clones of an exporter, so later exports don't send requests until the retry window has passed.
+#[derive(Debug, Default)]
+pub(crate) struct Throttle {
+    until: Mutex<Option<Instant>>,
+}
+
+impl Throttle {
+    fn open(&self, retry_after: Duration) {
+        let until = Instant::now() + retry_after;
+        let mut current = self.until.lock().unwrap();
+        if current.map_or(true, |current| current < until) {
+            *current = Some(until);
+        }
+    }
+
+    /// Time until uploads are allowed again, or `None` if they are allowed now.
+    fn remaining(&self) -> Option<Duration> {
+        let mut current = self.until.lock().unwrap();
+        let remaining = (*current)?.checked_duration_since(Instant::now());
+        if remaining.is_none() {
+            *current = None;
+        }
+        remaining
+    }
+}
+
+/// Total delay, which is left for retries of an export. It's shared by all requests of an export,
+/// so splitting a batch into multiple requests doesn't multiply the time an export can take.
+struct RetryBudget {
+    remaining: Mutex<Duration>,
+    exhausted: AtomicBool,
+}
+
+impl RetryBudget {
+    fn new(total_delay: Duration) -> Self {
+        Self {
+            remaining: Mutex::new(total_delay),
+            exhausted: AtomicBool::new(false),
+        }
+    }
+
+    /// Takes `delay` from the budget, or marks the budget as exhausted if not enough is left.
+    fn take(&self, delay: Duration) -> bool {
+        let mut remaining = self.remaining.lock().unwrap();
+        match remaining.checked_sub(delay) {
+            Some(left) => {
+                *remaining = left;
+                true
+            }
+            None => {
+                self.exhausted.store(true, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.exhausted.load(Ordering::Relaxed)
+    }
+}
+
+/// Exponential backoff, which waits at least until the retry window reported by the server has
+/// passed. It stops once the retry budget is exceeded, e.g. because the server asks to wait longer
+/// than that.
+struct ThrottledBackoff<'a> {
+    backoff: ExponentialBackoff,
+    throttle: &'a Throttle,
+    budget: &'a RetryBudget,
+}
+
+impl Iterator for ThrottledBackoff<'_> {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let mut delay = self.backoff.next()?;
+        if let Some(retry_after) = self.throttle.remaining() {
+            delay = delay.max(retry_after);
+        }
+        self.budget.take(delay).then_some(delay)
+    }
+}
 
 /// Response containing the status of each telemetry item.
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,19 +225,111 @@ struct ErrorDetails {
     index: usize,
     /// The item specific HTTP Response status code.
     status_code: u16,
+    /// The error message.
+    #[serde(default)]
+    message: Option<String>,
 }
 
 /// Sends a telemetry items to the server.
-pub(crate) async fn send(
-    client: &dyn HttpClient,
-    endpoint: &Uri,
-    items: Vec<Envelope>,
-    retry_notify: Option<Arc<Mutex<dyn FnMut(&Error, Duration) + Send + 'static>>>,
+///
+/// Items are split into multiple requests if they exceed the size or item count limits. Each
+/// request is retried on its own, but all requests share the total delay of the retry policy. Once
+/// it is used up, remaining requests are not sent anymore. Items, which could not be sent because
+/// of a retryable error or because the token was rejected, are persisted in the offline storage, if
+/// configured.
+#[cfg(any(feature = "statsbeat", test))]
+pub(crate) async fn send(upload: &Upload<'_>, items: Vec<Envelope>) -> Result<(), Error> {
+    let serialized = serialize(upload.buffers, &items)?;
+    // Envelopes are not needed anymore, so free their memory before sending.
+    drop(items);
+    send_serialized(upload, serialized).await
+}
+
+/// Telemetry items of an export serialized to JSON.
+pub(crate) struct Serialized {
+    buffer: Vec<u8>,
+    items: Vec<SerializedItem>,
+}
+
+impl Serialized {
+    /// Builds a request body of all items, like the first request of an export with a single
+    /// request, and returns the buffer of serialized items to the pool.
+    pub(crate) fn into_request_body(
+        self,
+        buffers: &BufferPool,
+        compression: Compression,
+    ) -> Result<Vec<u8>, Error> {
+        let body = serialize_items(compression, buffers, &self.buffer, &self.items);
+        buffers.give(self.buffer);
+        body
+    }
+}
+
+/// Serializes telemetry items into a buffer from the pool.
+pub(crate) fn serialize<T: Serialize>(
+    buffers: &BufferPool,
+    items: &[T],
+) -> Result<Serialized, Error> {
+    let mut buffer = buffers.take();
+    let mut serialized = Vec::with_capacity(items.len());
+    for item in items {
+        let start = buffer.len();
+        serde_json::to_writer(&mut buffer, item).map_err(Error::UploadSerializeRequest)?;
+        serialized.push(start..buffer.len());
+    }
+    Ok(Serialized {
+        buffer,
+        items: serialized,
+    })
+}
+
+/// Sends serialized telemetry items to the server. See [`send`].
+pub(crate) async fn send_serialized(
+    upload: &Upload<'_>,
+    serialized: Serialized,
+) -> Result<(), Error> {
+    let Serialized { buffer, items } = serialized;
+    let budget = RetryBudget::new(upload.retry_policy.total_delay());
+    let mut result = Ok(());
+    for chunk in chunk_items(items, MAX_PAYLOAD_SIZE, MAX_ITEMS_PER_REQUEST) {
+        if budget.is_exhausted() {
+            // An earlier request already failed after using up the retry budget.
+            persist(upload, &buffer, &chunk);
+            continue;
+        }
+        let chunk_result = send_chunk(upload, &buffer, chunk, &budget).await;
+        if result.is_ok() {
+            result = chunk_result;
+        }
+    }
+
+    upload.buffers.give(buffer);
+    result
+}
+
+async fn send_chunk(
+    upload: &Upload<'_>,
+    buffer: &[u8],
+    items: Vec<SerializedItem>,
+    budget: &RetryBudget,
 ) -> Result<(), Error> {
-    let attempt = |mut items: Vec<Envelope>| async {
-        match send_internal(client, endpoint, &items).await {
+    if let Some(retry_after) = upload.throttle.remaining() {
+        persist(upload, buffer, &items);
+        let err = Error::UploadThrottled(retry_after);
+        upload.notify(&err, retry_after);
+        return Err(err);
+    }
+
+    let first_attempt = AtomicBool::new(true);
+    // The request body is reused for retries as long as the same items are sent.
+    let cached_payload = Mutex::new(None);
+    let attempt = |mut items: Vec<SerializedItem>| async {
+        if !first_attempt.swap(false, Ordering::Relaxed) {
+            upload.counters.record_retried(items.len());
+        }
+        match send_internal(upload, buffer, &items, &cached_payload).await {
             result @ Ok(()) => (Vec::new(), result),
-            result @ Err(UploadError::RetryAll(_)) => (items, result),
+            result @ Err(UploadError::RetryAll(_) | UploadError::RetryLater(_)) => (items, result),
             Err(UploadError::RetrySome { err, to_retry, .. }) => {
                 let mut index: usize = 0;
                 items.retain(|_| {
@@ -65,6 +337,7 @@ pub(crate) async fn send(
                     index += 1;
                     retry
                 });
+                reclaim_payload(upload, cached_payload.lock().unwrap().take());
                 if items.is_empty() {
                     return (items, Ok(()));
                 }
@@ -74,21 +347,12 @@ pub(crate) async fn send(
         }
     };
 
-    let (_, result) = attempt
-        .retry(
-            ExponentialBuilder::new()
-                .with_min_delay(RETRY_MIN_DELAY)
-                .with_max_delay(RETRY_MAX_DELAY)
-                .with_jitter()
-                // No max delay or max times should needed, because the batch span processor already
-                // enforces a `max_export_timeout`. However, as of `opentelemetry_sdk` v0.30.0:
-                // - the option is only respected for ::span_processor_with_async_runtime::BatchSpanProcessor
-                // - the option doesn't exist for metric or log exports or the SimpleSpanProcessor
-                // Therefore, add a total delay here, which is slightly larger than the default
-                // `max_export_timeout`.
-                .without_max_times()
-                .with_total_delay(Some(RETRY_TOTAL_DELAY)),
-        )
+    let (items, result) = attempt
+        .retry(ThrottledBackoff {
+            backoff: upload.retry_policy.backoff(),
+            throttle: upload.throttle,
+            budget,
+        })
         .sleep(FuturesTimerSleeper)
         .context(items)
         .when(|err| {
@@ -97,57 +361,318 @@ pub(crate) async fn send(
                 UploadError::RetryAll(_) | UploadError::RetrySome { .. }
             )
         })
-        .notify(|error, duration| {
-            if let Some(ref notify) = retry_notify {
-                let mut notify = notify.lock().unwrap();
-                notify(error.error(), duration);
-            }
-        })
+        .notify(|error, duration| upload.notify(error.error(), duration))
         .await;
+
+    let cached_payload = cached_payload.into_inner().unwrap();
+    if let Err(
+        UploadError::RetryAll(_) | UploadError::RetryLater(_) | UploadError::RetrySome { .. },
+    ) = result
+    {
+        match cached_payload {
+            Some(ref payload) => persist_payload(upload, payload, items.len()),
+            None => persist(upload, buffer, &items),
+        }
+    }
+    reclaim_payload(upload, cached_payload);
+
     result.map_err(|err| err.into_error())
 }
 
+/// Stores items in the offline storage, if configured, so they are sent again later. Otherwise
+/// they are dropped.
+fn persist(upload: &Upload<'_>, buffer: &[u8], items: &[SerializedItem]) {
+    if upload.storage.is_none() {
+        upload.counters.record_dropped(items.len());
+        return;
+    }
+    match serialize_items(upload.compression, upload.buffers, buffer, items) {
+        Ok(payload) => {
+            persist_payload(upload, &payload, items.len());
+            upload.buffers.give(payload);
+        }
+        Err(err) => {
+            upload.counters.record_dropped(items.len());
+            opentelemetry::otel_warn!(name: "ApplicationInsights.OfflineStorage.PersistFailed", error = err.to_string().as_str());
+        }
+    }
+}
+
+/// Stores a serialized request body in the offline storage, if configured.
+fn persist_payload(upload: &Upload<'_>, payload: &[u8], items: usize) {
+    let Some(storage) = upload.storage else {
+        upload.counters.record_dropped(items);
+        return;
+    };
+    match storage.put(payload) {
+        Ok(()) => upload.counters.record_persisted(items),
+        Err(err) => {
+            upload.counters.record_dropped(items);
+            opentelemetry::otel_warn!(name: "ApplicationInsights.OfflineStorage.PersistFailed", error = err.to_string().as_str());
+        }
+    }
+}
+
+/// Returns the buffer of a request body to the pool, unless the HTTP client still holds on to it.
+fn reclaim_payload(upload: &Upload<'_>, payload: Option<Bytes>) {
+    if let Some(Ok(payload)) = payload.map(Bytes::try_into_mut) {
+        upload.buffers.give(payload.into());
+    }
+}
+
+/// Splits items into chunks, which stay within the size and item count limits of a single
+/// request. Items, which are larger than the size limit on their own, are sent in a separate
+/// request.
+fn chunk_items(
+    items: Vec<SerializedItem>,
+    max_size: usize,
+    max_items: usize,
+) -> Vec<Vec<SerializedItem>> {
+    let mut chunks = Vec::new();
+    let mut chunk = Vec::new();
+    // Account for the surrounding `[` and `]`.
+    let mut chunk_size = 2;
+    for item in items {
+        // Account for the separating `,`.
+        let item_size = item.len() + 1;
+        if !chunk.is_empty() && (chunk_size + item_size > max_size || chunk.len() >= max_items) {
+            chunks.push(std::mem::take(&mut chunk));
+            chunk_size = 2;
+        }
+        chunk_size += item_size;
+        chunk.push(item);
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+/// Sends batches persisted in the offline storage to the server.
+///
+/// Stops at the first batch, which fails with a retryable error or an authentication error.
+/// Authentication errors are not caused by the data, so those batches are kept for a later export.
+/// Batches, which partially succeeded, are stored again with only the retryable items.
+pub(crate) async fn send_stored(upload: &Upload<'_>) {
+    let Some(storage) = upload.storage else {
+        return;
+    };
+    for _ in 0..MAX_STORED_BATCHES_PER_EXPORT {
+        if upload.throttle.remaining().is_some() {
+            break;
+        }
+
+        let Some(blob) = storage.lease_next() else {
+            break;
+        };
+        let payload = match blob.read() {
+            Ok(payload) => payload,
+            Err(_) => {
+                let _ = blob.delete();
+                continue;
+            }
+        };
+
+        let Some((items, uncompressed_size)) = stored_items(&payload) else {
+            let _ = blob.delete();
+            continue;
+        };
+        upload.counters.record_retried(items);
+        match send_payload(
+            upload,
+            Bytes::from(payload.clone()),
+            items,
+            uncompressed_size,
+        )
+        .await
+        {
+            Err(UploadError::RetryAll(_) | UploadError::RetryLater(_)) => {
+                let _ = blob.release();
+                break;
+            }
+            Ok(()) | Err(UploadError::Fatal(_)) => {
+                let _ = blob.delete();
+            }
+            Err(UploadError::RetrySome { to_retry, .. }) => {
+                match retain_stored_items(&payload, &to_retry, upload.compression) {
+                    Ok(payload) if storage.put(&payload).is_ok() => {
+                        let _ = blob.delete();
+                    }
+                    _ => {
+                        let _ = blob.release();
+                    }
+                }
+                break;
+            }
+        }
+    }
+}
+
 async fn send_internal(
-    client: &dyn HttpClient,
-    endpoint: &Uri,
-    items: &[Envelope],
+    upload: &Upload<'_>,
+    buffer: &[u8],
+    items: &[SerializedItem],
+    cached_payload: &Mutex<Option<Bytes>>,
+) -> Result<(), UploadError> {
+    let cached = cached_payload.lock().unwrap().clone();
+    let payload = match cached {
+        Some(payload) => payload,
+        None => {
+            let payload = Bytes::from(
+                serialize_items(upload.compression, upload.buffers, buffer, items)
+                    .map_err(UploadError::Fatal)?,
+            );
+            *cached_payload.lock().unwrap() = Some(payload.clone());
+            payload
+        }
+    };
+    send_payload(upload, payload, items.len(), serialized_size(items)).await
+}
+
+async fn send_payload(
+    upload: &Upload<'_>,
+    payload: Bytes,
+    items: usize,
+    uncompressed_size: usize,
 ) -> Result<(), UploadError> {
-    let payload = Bytes::from(serialize_envelopes(items).map_err(|err| UploadError::Fatal(err))?);
+    upload
+        .counters
+        .record_request(items, uncompressed_size, payload.len());
 
-    let request = Request::post(endpoint)
-        .header(http::header::CONTENT_TYPE, "application/json")
-        .header(http::header::CONTENT_ENCODING, "gzip")
-        .body(payload)
-        .expect("request should be valid");
+    let token = match upload.credential {
+        Some(credential) => Some(
+            credential
+                .get_token()
+                .await
+                .map_err(UploadError::RetryAll)?,
+        ),
+        None => None,
+    };
 
-    let response = client
-        .send_bytes(request)
-        .await
-        .map_err(|err| UploadError::RetryAll(Error::UploadConnection(err)))?;
+    let mut redirects = 0;
+    let response = loop {
+        let endpoint = upload.endpoint.uri();
+        let mut request_builder =
+            Request::post(&endpoint).header(http::header::CONTENT_TYPE, "application/json");
+        if payload::is_compressed(&payload) {
+            request_builder = request_builder.header(http::header::CONTENT_ENCODING, "gzip");
+        }
+        if let Some(ref token) = token {
+            credential::check_endpoint(&endpoint).map_err(UploadError::RetryLater)?;
+            request_builder =
+                request_builder.header(http::header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = request_builder
+            .body(payload.clone())
+            .expect("request should be valid");
+
+        #[cfg(feature = "statsbeat")]
+        let start = Instant::now();
+        let response = upload.client.send_bytes(request).await;
+        #[cfg(feature = "statsbeat")]
+        if let Some(statsbeat) = upload.statsbeat {
+            let outcome = match response {
+                Ok(ref response) => {
+                    request_outcome(response.status().as_u16(), upload.retry_policy)
+                }
+                Err(_) => RequestOutcome::Exception("ConnectionError"),
+            };
+            statsbeat.record(&endpoint, outcome, start.elapsed());
+        }
+        let response =
+            response.map_err(|err| UploadError::RetryAll(Error::UploadConnection(err)))?;
+
+        match redirect_location(&response) {
+            Some(location) if redirects < MAX_REDIRECTS => {
+                redirects += 1;
+                upload.endpoint.redirect(location);
+            }
+            _ => break response,
+        }
+    };
 
-    handle_upload_response(response)
+    if let Some(retry_after) = retry_after(&response) {
+        upload.throttle.open(retry_after);
+    }
+
+    let mut rejected = Vec::new();
+    let result = handle_upload_response(
+        response,
+        upload.retry_policy,
+        upload.counters,
+        items,
+        &mut rejected,
+    );
+    if !rejected.is_empty() {
+        dead_letter(upload, &payload, rejected);
+    }
+    if let (Some(credential), Err(UploadError::RetryLater(Error::UploadUnauthorized(_)))) =
+        (upload.credential, &result)
+    {
+        credential.invalidate();
+    }
+    result
 }
 
-fn serialize_envelopes(items: &[Envelope]) -> Result<Vec<u8>, Error> {
-    let serialized = serde_json::to_vec(items).map_err(Error::UploadSerializeRequest)?;
-    serialize_request_body(&serialized)
+/// Builds a request body from serialized items and compresses it, if configured.
+fn serialize_items(
+    compression: Compression,
+    buffers: &BufferPool,
+    buffer: &[u8],
+    items: &[SerializedItem],
+) -> Result<Vec<u8>, Error> {
+    let mut writer = PayloadWriter::new(compression, buffers.take());
+    let mut write = || -> std::io::Result<()> {
+        writer.write_all(b"[")?;
+        for (index, item) in items.iter().enumerate() {
+            if index > 0 {
+                writer.write_all(b",")?;
+            }
+            writer.write_all(&buffer[item.clone()])?;
+        }
+        writer.write_all(b"]")
+    };
+    write().map_err(Error::UploadCompressRequest)?;
+    writer.finish()
 }
 
-pub(crate) fn serialize_request_body(data: &[u8]) -> Result<Vec<u8>, Error> {
-    // Weirdly gzip_encoder.write_all(serde_json::to_vec()) seems to be faster than
-    // serde_json::to_writer(gzip_encoder). In a local test operating on items that result in
-    // ~13MiB of JSON, this is what I've seen:
-    // gzip_encoder.write_all(serde_json::to_vec()): 159ms
-    // serde_json::to_writer(gzip_encoder):          247ms
-    let mut gzip_encoder = GzEncoder::new(Vec::new(), Compression::default());
-    gzip_encoder
-        .write_all(&data)
-        .map_err(Error::UploadCompressRequest)?;
-    gzip_encoder.finish().map_err(Error::UploadCompressRequest)
+/// Size of the JSON array containing the items.
+fn serialized_size(items: &[SerializedItem]) -> usize {
+    // Items are separated by `,` and surrounded by `[` and `]`.
+    items.iter().map(|item| item.len() + 1).sum::<usize>() + 1
+}
+
+/// Returns the number of items and the uncompressed size of a serialized request body.
+fn stored_items(payload: &[u8]) -> Option<(usize, usize)> {
+    let serialized = payload::decode(payload).ok()?;
+    let items: Vec<IgnoredAny> = serde_json::from_slice(&serialized).ok()?;
+    Some((items.len(), serialized.len()))
+}
+
+/// Keep only the items with the given indices in a serialized request body.
+fn retain_stored_items(
+    payload: &[u8],
+    to_retry: &HashSet<usize>,
+    compression: Compression,
+) -> Result<Vec<u8>, Error> {
+    let serialized = payload::decode(payload).map_err(Error::UploadCompressRequest)?;
+    let mut items: Vec<serde_json::Value> =
+        serde_json::from_slice(&serialized).map_err(Error::UploadSerializeRequest)?;
+    let mut index: usize = 0;
+    items.retain(|_| {
+        let retry = to_retry.contains(&index);
+        index += 1;
+        retry
+    });
+    let serialized = serde_json::to_vec(&items).map_err(Error::UploadSerializeRequest)?;
+    payload::serialize_request_body(&serialized, compression)
 }
 
 enum UploadError {
     RetryAll(Error),
+    /// Not retried during this export, because retrying wouldn't help right away (e.g. the token
+    /// was rejected), but not caused by the data either. Items are kept for a later export.
+    RetryLater(Error),
     RetrySome {
         err: Error,
         to_retry: HashSet<usize>,
@@ -158,7 +683,7 @@ enum UploadError {
 impl UploadError {
     fn error(&self) -> &Error {
         match self {
-            Self::RetryAll(err) => err,
+            Self::RetryAll(err) | Self::RetryLater(err) => err,
             Self::RetrySome { err, .. } => err,
             Self::Fatal(err) => err,
         }
@@ -166,83 +691,200 @@ impl UploadError {
 
     fn into_error(self) -> Error {
         match self {
-            Self::RetryAll(err) => err,
+            Self::RetryAll(err) | Self::RetryLater(err) => err,
             Self::RetrySome { err, .. } => err,
             Self::Fatal(err) => err,
         }
     }
 }
 
-fn handle_upload_response(response: Response<Bytes>) -> Result<(), UploadError> {
+fn handle_upload_response(
+    response: Response<Bytes>,
+    retry_policy: &RetryPolicy,
+    counters: &Counters,
+    items: usize,
+    rejected: &mut Vec<ErrorDetails>,
+) -> Result<(), UploadError> {
     match response.status().as_u16() {
-        STATUS_OK => Ok(()),
+        STATUS_OK => {
+            counters.record_accepted(items);
+            Ok(())
+        }
         status_code @ STATUS_PARTIAL_CONTENT => {
             let content: TrackResponse = match serde_json::from_slice(response.body()) {
                 Ok(content) => content,
                 Err(err) => return Err(UploadError::Fatal(Error::UploadDeserializeResponse(err))),
             };
 
+            counters.record_accepted(content.items_accepted);
             if content.items_received == content.items_accepted {
                 return Ok(());
             }
 
-            let to_retry = content
-                .errors
-                .iter()
-                .filter(|error| can_retry_status_code(error.status_code))
-                .map(|error| error.index)
-                .collect::<HashSet<_>>();
-            if to_retry.is_empty() {
-                Err(UploadError::Fatal(Error::Upload(format!(
-                    "{status_code}: Accepted {}/{} items; none were retryable.",
-                    content.items_accepted, content.items_received
-                ))))
-            } else {
-                Err(UploadError::RetrySome {
-                    err: status_code_error(status_code),
-                    to_retry,
-                })
-            }
+            partial_retry(status_code, content, retry_policy, counters, rejected)
+        }
+        status_code @ (STATUS_UNAUTHORIZED | STATUS_FORBIDDEN) => Err(UploadError::RetryLater(
+            Error::UploadUnauthorized(status_code),
+        )),
+        status_code if !retry_policy.is_retryable(status_code) => {
+            counters.record_rejected(status_code, items);
+            reject_all(status_code, response.body(), items, rejected);
+            Err(UploadError::Fatal(status_code_error(status_code)))
         }
-        status_code @ (STATUS_REQUEST_TIMEOUT
-        | STATUS_TOO_MANY_REQUESTS
-        | STATUS_APPLICATION_INACTIVE
-        | STATUS_SERVICE_UNAVAILABLE) => Err(UploadError::RetryAll(status_code_error(status_code))),
         status_code @ STATUS_INTERNAL_SERVER_ERROR => {
-            let content = match serde_json::from_slice::<TrackResponse>(response.body()) {
-                Ok(content) => content,
-                Err(_) => return Err(UploadError::RetryAll(status_code_error(status_code))),
-            };
+            match serde_json::from_slice::<TrackResponse>(response.body()) {
+                Ok(content) => {
+                    counters.record_accepted(content.items_accepted);
+                    partial_retry(status_code, content, retry_policy, counters, rejected)
+                }
+                Err(_) => Err(UploadError::RetryAll(status_code_error(status_code))),
+            }
+        }
+        status_code => Err(UploadError::RetryAll(status_code_error(status_code))),
+    }
+}
 
-            let to_retry = content
+/// Retries the items of a partially successful response, which failed with a retryable status
+/// code. Nothing is retried if the status code of the response itself (e.g. 206) is not retryable.
+fn partial_retry(
+    status_code: u16,
+    content: TrackResponse,
+    retry_policy: &RetryPolicy,
+    counters: &Counters,
+    rejected: &mut Vec<ErrorDetails>,
+) -> Result<(), UploadError> {
+    let retry_partial = retry_policy.is_retryable(status_code);
+    let mut to_retry = HashSet::new();
+    for error in content.errors {
+        if retry_partial && retry_policy.is_retryable(error.status_code) {
+            to_retry.insert(error.index);
+        } else {
+            counters.record_rejected(error.status_code, 1);
+            rejected.push(error);
+        }
+    }
+    if to_retry.is_empty() {
+        Err(UploadError::Fatal(Error::Upload(format!(
+            "{status_code}: Accepted {}/{} items; none were retryable.",
+            content.items_accepted, content.items_received
+        ))))
+    } else {
+        Err(UploadError::RetrySome {
+            err: status_code_error(status_code),
+            to_retry,
+        })
+    }
+}
+
+/// Rejects all items of a request, which failed with a status code that is not retried. Uses the
+/// error messages of individual items, if the response contains them.
+fn reject_all(status_code: u16, body: &[u8], items: usize, rejected: &mut Vec<ErrorDetails>) {
+    let mut messages: HashMap<usize, String> = serde_json::from_slice::<TrackResponse>(body)
+        .map(|content| {
+            content
                 .errors
-                .iter()
-                .filter(|error| can_retry_status_code(error.status_code))
-                .map(|error| error.index)
-                .collect::<HashSet<_>>();
-            if to_retry.is_empty() {
-                Err(UploadError::Fatal(Error::Upload(format!(
-                    "{status_code}: Accepted {}/{} items; none were retryable.",
-                    content.items_accepted, content.items_received
-                ))))
-            } else {
-                Err(UploadError::RetrySome {
-                    err: status_code_error(status_code),
-                    to_retry,
-                })
-            }
+                .into_iter()
+                .filter_map(|error| Some((error.index, error.message?)))
+                .collect()
+        })
+        .unwrap_or_default();
+    rejected.extend((0..items).map(|index| ErrorDetails {
+        index,
+        status_code,
+        message: messages.remove(&index),
+    }));
+}
+
+/// Passes rejected items to the dead letter function, if configured.
+fn dead_letter(upload: &Upload<'_>, payload: &[u8], rejected: Vec<ErrorDetails>) {
+    let Some(dead_letter) = upload.dead_letter else {
+        return;
+    };
+    // Items are only parsed again in this rare case, so the common path doesn't need to keep them
+    // around.
+    let Some(items) = payload::decode(payload)
+        .ok()
+        .and_then(|serialized| serde_json::from_slice::<Vec<serde_json::Value>>(&serialized).ok())
+    else {
+        return;
+    };
+    let dead_letters: Vec<_> = rejected
+        .into_iter()
+        .filter_map(|error| {
+            Some(DeadLetter {
+                item: items.get(error.index)?.to_string(),
+                status_code: error.status_code,
+                message: error.message,
+            })
+        })
+        .collect();
+    let mut dead_letter = dead_letter.lock().unwrap();
+    dead_letter(&dead_letters);
+}
+
+#[cfg(feature = "statsbeat")]
+fn request_outcome(status_code: u16, retry_policy: &RetryPolicy) -> RequestOutcome {
+    match status_code {
+        STATUS_OK => RequestOutcome::Success,
+        STATUS_TOO_MANY_REQUESTS | STATUS_APPLICATION_INACTIVE => {
+            RequestOutcome::Throttle(status_code)
         }
-        status_code => Err(UploadError::Fatal(status_code_error(status_code))),
+        status_code if retry_policy.is_retryable(status_code) => RequestOutcome::Retry(status_code),
+        status_code => RequestOutcome::Failure(status_code),
     }
 }
 
-fn can_retry_status_code(code: u16) -> bool {
-    code == STATUS_PARTIAL_CONTENT
-        || code == STATUS_REQUEST_TIMEOUT
-        || code == STATUS_TOO_MANY_REQUESTS
-        || code == STATUS_APPLICATION_INACTIVE
-        || code == STATUS_INTERNAL_SERVER_ERROR
-        || code == STATUS_SERVICE_UNAVAILABLE
+/// Returns the host the server redirects us to, if the response is a redirect.
+fn redirect_location(response: &Response<Bytes>) -> Option<Uri> {
+    if !matches!(
+        response.status().as_u16(),
+        STATUS_TEMPORARY_REDIRECT | STATUS_PERMANENT_REDIRECT
+    ) {
+        return None;
+    }
+
+    let location: Uri = response
+        .headers()
+        .get(http::header::LOCATION)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()?;
+    // Relative locations are not supported, since we only keep the host of the redirect.
+    location.authority()?;
+    Some(location)
+}
+
+/// Returns how long the server asks us to wait before sending again, if the response is throttled.
+fn retry_after(response: &Response<Bytes>) -> Option<Duration> {
+    let status_code = response.status().as_u16();
+    if !matches!(
+        status_code,
+        STATUS_TOO_MANY_REQUESTS | STATUS_APPLICATION_INACTIVE | STATUS_SERVICE_UNAVAILABLE
+    ) {
+        return None;
+    }
+
+    let retry_after = response
+        .headers()
+        .get(http::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_retry_after);
+    match retry_after {
+        None if status_code == STATUS_APPLICATION_INACTIVE => Some(DEFAULT_QUOTA_RETRY_AFTER),
+        retry_after => retry_after,
+    }
+}
+
+/// Parses a `Retry-After` header value, which is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
 }
 
 fn status_code_error(status_code: u16) -> Error {
@@ -250,28 +892,32 @@ fn status_code_error(status_code: u16) -> Error {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use crate::{credential::tests::TestCredential, OfflineStorage};
     use async_trait::async_trait;
     use bytes::Bytes;
-    use flate2::read::GzDecoder;
     use http::{Request, Response};
     use opentelemetry_http::{HttpClient, HttpError};
-    use std::{collections::VecDeque, sync::Mutex};
+    use std::{
+        collections::{BTreeMap, VecDeque},
+        sync::Mutex,
+    };
+    use test_case::test_case;
 
     #[derive(Default, Debug)]
-    struct TestClient {
-        requests: Mutex<Vec<Request<Bytes>>>,
+    pub(crate) struct TestClient {
+        pub(crate) requests: Mutex<Vec<Request<Bytes>>>,
         responses: Mutex<VecDeque<Result<Response<Bytes>, HttpError>>>,
     }
 
     impl TestClient {
-        fn with_response(self, response: Result<Response<Bytes>, HttpError>) -> Self {
+        pub(crate) fn with_response(self, response: Result<Response<Bytes>, HttpError>) -> Self {
             self.responses.lock().unwrap().push_back(response);
             self
         }
 
-        fn with_200(self) -> Self {
+        pub(crate) fn with_200(self) -> Self {
             self.with_response(Ok(Response::builder()
                 .status(200)
                 .body(Bytes::from("{}"))
@@ -292,7 +938,7 @@ mod tests {
                 .expect("")))
         }
 
-        fn with_connection_error(self) -> Self {
+        pub(crate) fn with_connection_error(self) -> Self {
             self.with_response(Err("connection error".into()))
         }
     }
@@ -313,6 +959,45 @@ mod tests {
         Uri::from_static("https://example.com/track")
     }
 
+    /// Owns the configuration, which is borrowed by `Upload`.
+    struct TestUpload {
+        endpoint: Endpoint,
+        retry_policy: RetryPolicy,
+        throttle: Throttle,
+        counters: Counters,
+        buffers: BufferPool,
+    }
+
+    impl TestUpload {
+        fn new() -> Self {
+            Self {
+                endpoint: Endpoint::new(endpoint()),
+                retry_policy: RetryPolicy::default(),
+                throttle: Throttle::default(),
+                counters: Counters::default(),
+                buffers: BufferPool::default(),
+            }
+        }
+
+        fn upload<'a>(&'a self, client: &'a TestClient) -> Upload<'a> {
+            Upload {
+                client,
+                endpoint: &self.endpoint,
+                retry_policy: &self.retry_policy,
+                retry_notify: None,
+                dead_letter: None,
+                storage: None,
+                credential: None,
+                throttle: &self.throttle,
+                counters: &self.counters,
+                #[cfg(feature = "statsbeat")]
+                statsbeat: None,
+                compression: Compression::default(),
+                buffers: &self.buffers,
+            }
+        }
+    }
+
     fn envelopes(n: usize) -> Vec<Envelope> {
         let mut items = Vec::with_capacity(n);
         for index in 0..n {
@@ -328,10 +1013,13 @@ mod tests {
         items
     }
 
+    fn serialize_envelopes(items: &[Envelope]) -> Result<Vec<u8>, Error> {
+        payload::serialize_request_body(&serde_json::to_vec(items).unwrap(), Compression::Default)
+    }
+
     fn envelopes_ids_from_request_body(body: &[u8]) -> Vec<usize> {
-        let gzip_decoder = GzDecoder::new(body);
         let mut envelopes: Vec<serde_json::Value> =
-            serde_json::from_reader(gzip_decoder).expect("body is json array");
+            serde_json::from_slice(&payload::decode(body).unwrap()).expect("body is json array");
         envelopes
             .drain(..)
             .map(|envelope| {
@@ -351,7 +1039,7 @@ mod tests {
     #[tokio::test]
     async fn success() {
         let client = TestClient::default().with_200();
-        let result = send(&client, &endpoint(), envelopes(1), None).await;
+        let result = send(&TestUpload::new().upload(&client), envelopes(1)).await;
         assert!(result.is_ok());
         assert_eq!(client.requests.lock().unwrap().len(), 1, "request count");
     }
@@ -363,7 +1051,7 @@ mod tests {
             items_accepted: 2,
             errors: Vec::new(),
         });
-        let result = send(&client, &endpoint(), envelopes(2), None).await;
+        let result = send(&TestUpload::new().upload(&client), envelopes(2)).await;
         assert!(result.is_ok());
         assert_eq!(client.requests.lock().unwrap().len(), 1, "request count");
     }
@@ -371,20 +1059,292 @@ mod tests {
     #[tokio::test]
     async fn fatal() {
         let client = TestClient::default().with_400();
-        let result = send(&client, &endpoint(), envelopes(1), None).await;
+        let result = send(&TestUpload::new().upload(&client), envelopes(1)).await;
         assert!(result.is_err());
         assert_eq!(client.requests.lock().unwrap().len(), 1, "request count");
         assert_eq!(result.unwrap_err().to_string(), "upload failed with 400");
     }
 
+    fn dead_letter_recorder() -> (DeadLetterNotify, Arc<Mutex<Vec<DeadLetter>>>) {
+        let dead_letters = Arc::new(Mutex::new(Vec::new()));
+        let notify: DeadLetterNotify = {
+            let dead_letters = dead_letters.clone();
+            Arc::new(Mutex::new(move |items: &[DeadLetter]| {
+                dead_letters.lock().unwrap().extend_from_slice(items);
+            }))
+        };
+        (notify, dead_letters)
+    }
+
+    fn dead_letter_ids(dead_letters: &[DeadLetter]) -> Vec<(usize, u16, Option<&str>)> {
+        dead_letters
+            .iter()
+            .map(|dead_letter| {
+                let item: serde_json::Value = serde_json::from_str(&dead_letter.item).unwrap();
+                (
+                    item["time"].as_str().unwrap().parse().unwrap(),
+                    dead_letter.status_code,
+                    dead_letter.message.as_deref(),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn dead_letter_partial() {
+        let client = TestClient::default().with_206(TrackResponse {
+            items_received: 3,
+            items_accepted: 1,
+            errors: vec![
+                ErrorDetails {
+                    index: 0,
+                    status_code: 400,
+                    message: Some("Field 'name' on type 'RequestData' is required".into()),
+                },
+                ErrorDetails {
+                    index: 2,
+                    status_code: 400,
+                    message: None,
+                },
+            ],
+        });
+        let (notify, dead_letters) = dead_letter_recorder();
+        let result = send(
+            &Upload {
+                dead_letter: Some(&notify),
+                ..TestUpload::new().upload(&client)
+            },
+            envelopes(3),
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(
+            dead_letter_ids(&dead_letters.lock().unwrap()),
+            vec![
+                (
+                    0,
+                    400,
+                    Some("Field 'name' on type 'RequestData' is required")
+                ),
+                (2, 400, None),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn dead_letter_rejected_request() {
+        let client = TestClient::default().with_response(Ok(Response::builder()
+            .status(400)
+            .body(Bytes::from(
+                r#"{"itemsReceived":2,"itemsAccepted":0,"errors":[{"index":1,"statusCode":400,"message":"Invalid instrumentation key"}]}"#,
+            ))
+            .expect("")));
+        let (notify, dead_letters) = dead_letter_recorder();
+        let result = send(
+            &Upload {
+                dead_letter: Some(&notify),
+                ..TestUpload::new().upload(&client)
+            },
+            envelopes(2),
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(
+            dead_letter_ids(&dead_letters.lock().unwrap()),
+            vec![
+                (0, 400, None),
+                (1, 400, Some("Invalid instrumentation key"))
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn retry_connection_error() {
         let client = TestClient::default().with_connection_error().with_200();
-        let result = send(&client, &endpoint(), envelopes(1), None).await;
+        let result = send(&TestUpload::new().upload(&client), envelopes(1)).await;
         assert!(result.is_ok());
         assert_eq!(client.requests.lock().unwrap().len(), 2, "request count");
     }
 
+    #[tokio::test]
+    async fn retry_reuses_payload() {
+        let client = TestClient::default().with_connection_error().with_200();
+        let result = send(&TestUpload::new().upload(&client), envelopes(3)).await;
+        assert!(result.is_ok());
+        let requests = client.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2, "request count");
+        assert_eq!(
+            requests[0].body().as_ptr(),
+            requests[1].body().as_ptr(),
+            "same request body"
+        );
+    }
+
+    #[tokio::test]
+    async fn no_compression() {
+        let client = TestClient::default().with_200();
+        let result = send(
+            &Upload {
+                compression: Compression::None,
+                ..TestUpload::new().upload(&client)
+            },
+            envelopes(2),
+        )
+        .await;
+        assert!(result.is_ok());
+        let requests = client.requests.lock().unwrap();
+        assert!(requests[0]
+            .headers()
+            .get(http::header::CONTENT_ENCODING)
+            .is_none());
+        let envelopes: Vec<serde_json::Value> = serde_json::from_slice(requests[0].body()).unwrap();
+        assert_eq!(envelopes.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn reuse_buffers() {
+        let client = TestClient::default().with_200().with_200();
+        let test = TestUpload::new();
+        let result = send(&test.upload(&client), envelopes(2)).await;
+        assert!(result.is_ok());
+        // The test client keeps the request body, so only the serialized items are returned.
+        let buffer = test.buffers.take();
+        assert!(buffer.capacity() > 0);
+        test.buffers.give(buffer);
+
+        let result = send(&test.upload(&client), envelopes(2)).await;
+        assert!(result.is_ok());
+        assert!(test.buffers.take().capacity() > 0);
+    }
+
+    #[tokio::test]
+    async fn no_retries_persists_items() {
+        let (storage, path) = storage("no_retries_persists_items");
+        let client = TestClient::default().with_connection_error();
+        let retry_policy = RetryPolicy::no_retries();
+        let result = send(
+            &Upload {
+                retry_policy: &retry_policy,
+                storage: Some(&storage),
+                ..TestUpload::new().upload(&client)
+            },
+            envelopes(2),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::UploadConnection(_))));
+        assert_eq!(client.requests.lock().unwrap().len(), 1, "request count");
+
+        let blob = storage.lease_next().unwrap();
+        assert_eq!(
+            envelopes_ids_from_request_body(&blob.read().unwrap()),
+            vec![0, 1]
+        );
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test_case(502, true ; "added status code is retried")]
+    #[test_case(503, false ; "removed status code is not retried")]
+    #[tokio::test]
+    async fn retryable_status_codes(status_code: u16, retried: bool) {
+        let client = TestClient::default()
+            .with_response(Ok(Response::builder()
+                .status(status_code)
+                .body(Bytes::new())
+                .expect("")))
+            .with_200();
+        let retry_policy = RetryPolicy::new()
+            .with_min_delay(Duration::from_millis(1))
+            .with_retryable_status_codes([STATUS_PARTIAL_CONTENT, 502]);
+        let result = send(
+            &Upload {
+                retry_policy: &retry_policy,
+                ..TestUpload::new().upload(&client)
+            },
+            envelopes(1),
+        )
+        .await;
+        assert_eq!(result.is_ok(), retried);
+        assert_eq!(
+            client.requests.lock().unwrap().len(),
+            if retried { 2 } else { 1 },
+            "request count"
+        );
+    }
+
+    #[tokio::test]
+    async fn partial_content_not_retryable() {
+        let client = TestClient::default()
+            .with_206(TrackResponse {
+                items_received: 2,
+                items_accepted: 1,
+                errors: vec![ErrorDetails {
+                    index: 1,
+                    status_code: STATUS_SERVICE_UNAVAILABLE,
+                    message: None,
+                }],
+            })
+            .with_200();
+        let retry_policy = RetryPolicy::new()
+            .with_min_delay(Duration::from_millis(1))
+            .with_retryable_status_codes([STATUS_SERVICE_UNAVAILABLE]);
+        let test = TestUpload::new();
+        let result = send(
+            &Upload {
+                retry_policy: &retry_policy,
+                ..test.upload(&client)
+            },
+            envelopes(2),
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(client.requests.lock().unwrap().len(), 1, "request count");
+        assert_eq!(
+            test.counters.snapshot().rejected,
+            BTreeMap::from([(STATUS_SERVICE_UNAVAILABLE, 1)])
+        );
+    }
+
+    #[tokio::test]
+    async fn record_stats() {
+        let client = TestClient::default()
+            .with_206(TrackResponse {
+                items_received: 3,
+                items_accepted: 1,
+                errors: vec![
+                    ErrorDetails {
+                        index: 1,
+                        status_code: 400,
+                        message: None,
+                    },
+                    ErrorDetails {
+                        index: 2,
+                        status_code: STATUS_SERVICE_UNAVAILABLE,
+                        message: None,
+                    },
+                ],
+            })
+            .with_connection_error()
+            .with_200()
+            .with_400();
+        let test = TestUpload::new();
+        let result = send(&test.upload(&client), envelopes(3)).await;
+        assert!(result.is_ok());
+        let result = send(&test.upload(&client), envelopes(2)).await;
+        assert!(result.is_err());
+
+        let stats = test.counters.snapshot();
+        assert_eq!(stats.sent, 7);
+        assert_eq!(stats.accepted, 2);
+        assert_eq!(stats.rejected, BTreeMap::from([(400, 3)]));
+        assert_eq!(stats.retried, 2);
+        assert_eq!(stats.dropped, 0);
+        let requests = client.requests.lock().unwrap();
+        let compressed_bytes: usize = requests.iter().map(|request| request.body().len()).sum();
+        assert_eq!(stats.compressed_bytes, compressed_bytes as u64);
+        assert!(stats.uncompressed_bytes > 0);
+    }
+
     #[tokio::test]
     async fn retry_partial_content() {
         let client = TestClient::default()
@@ -395,18 +1355,22 @@ mod tests {
                     ErrorDetails {
                         index: 1,
                         status_code: 400,
+                        message: None,
                     },
                     ErrorDetails {
                         index: 7,
                         status_code: STATUS_REQUEST_TIMEOUT,
+                        message: None,
                     },
                     ErrorDetails {
                         index: 8,
                         status_code: STATUS_REQUEST_TIMEOUT,
+                        message: None,
                     },
                     ErrorDetails {
                         index: 9,
                         status_code: STATUS_REQUEST_TIMEOUT,
+                        message: None,
                     },
                 ],
             })
@@ -416,10 +1380,11 @@ mod tests {
                 errors: vec![ErrorDetails {
                     index: 2,
                     status_code: STATUS_TOO_MANY_REQUESTS,
+                    message: None,
                 }],
             })
             .with_200();
-        let result = send(&client, &endpoint(), envelopes(10), None).await;
+        let result = send(&TestUpload::new().upload(&client), envelopes(10)).await;
         assert!(result.is_ok());
         let requests = client.requests.lock().unwrap();
         assert_eq!(requests.len(), 3, "request count");
@@ -430,4 +1395,545 @@ mod tests {
         let items2 = envelopes_ids_from_request_body(requests[2].body());
         assert_eq!(items2, vec![9]);
     }
+
+    #[test]
+    fn chunk_by_size_and_count() {
+        let items = vec![0..10, 10..20, 20..50, 50..55];
+        let chunks = chunk_items(items.clone(), 25, 10);
+        assert_eq!(
+            chunks,
+            vec![
+                vec![items[0].clone(), items[1].clone()],
+                vec![items[2].clone()],
+                vec![items[3].clone()]
+            ]
+        );
+
+        let chunks = chunk_items(items.clone(), 1000, 3);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].len(), 3);
+        assert_eq!(chunks[1].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn split_large_batch() {
+        let client = TestClient::default()
+            .with_200()
+            .with_206(TrackResponse {
+                items_received: 2,
+                items_accepted: 1,
+                errors: vec![ErrorDetails {
+                    index: 1,
+                    status_code: STATUS_SERVICE_UNAVAILABLE,
+                    message: None,
+                }],
+            })
+            .with_200();
+        let result = send(
+            &TestUpload::new().upload(&client),
+            envelopes(MAX_ITEMS_PER_REQUEST + 2),
+        )
+        .await;
+        assert!(result.is_ok());
+        let requests = client.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3, "request count");
+        let items0 = envelopes_ids_from_request_body(requests[0].body());
+        assert_eq!(items0, (0..MAX_ITEMS_PER_REQUEST).collect::<Vec<_>>());
+        let items1 = envelopes_ids_from_request_body(requests[1].body());
+        assert_eq!(
+            items1,
+            vec![MAX_ITEMS_PER_REQUEST, MAX_ITEMS_PER_REQUEST + 1]
+        );
+        let items2 = envelopes_ids_from_request_body(requests[2].body());
+        assert_eq!(items2, vec![MAX_ITEMS_PER_REQUEST + 1]);
+    }
+
+    #[tokio::test]
+    async fn split_batch_shares_retry_budget() {
+        let (storage, path) = storage("split_batch_shares_retry_budget");
+        let client = TestClient::default()
+            .with_connection_error()
+            .with_connection_error()
+            .with_connection_error();
+        let retry_policy = RetryPolicy::new()
+            .with_min_delay(Duration::from_millis(10))
+            .with_max_delay(Duration::from_millis(10))
+            .with_total_delay(Duration::from_millis(25))
+            .with_jitter(false);
+        let result = send(
+            &Upload {
+                retry_policy: &retry_policy,
+                storage: Some(&storage),
+                ..TestUpload::new().upload(&client)
+            },
+            envelopes(2 * MAX_ITEMS_PER_REQUEST + 1),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::UploadConnection(_))));
+        // The first request uses up the budget with two retries. The other requests are not sent.
+        assert_eq!(client.requests.lock().unwrap().len(), 3, "request count");
+
+        let mut stored = Vec::new();
+        while let Some(blob) = storage.lease_next() {
+            stored.extend(envelopes_ids_from_request_body(&blob.read().unwrap()));
+            blob.delete().unwrap();
+        }
+        stored.sort_unstable();
+        assert_eq!(
+            stored,
+            (0..2 * MAX_ITEMS_PER_REQUEST + 1).collect::<Vec<_>>()
+        );
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    fn with_retry_after(client: TestClient, status_code: u16, retry_after: &str) -> TestClient {
+        client.with_response(Ok(Response::builder()
+            .status(status_code)
+            .header(http::header::RETRY_AFTER, retry_after)
+            .body(Bytes::new())
+            .expect("")))
+    }
+
+    #[test_case("120", Some(Duration::from_secs(120)) ; "seconds")]
+    #[test_case(" 5 ", Some(Duration::from_secs(5)) ; "seconds with whitespace")]
+    #[test_case("Wed, 21 Oct 2015 07:28:00 GMT", None ; "date in the past")]
+    #[test_case("soon", None ; "invalid")]
+    fn parse_retry_after_header(value: &str, expected: Option<Duration>) {
+        assert_eq!(parse_retry_after(value), expected);
+    }
+
+    #[test]
+    fn parse_retry_after_date() {
+        let date = (Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+        let retry_after = parse_retry_after(&date).unwrap();
+        assert!(retry_after > Duration::from_secs(55) && retry_after <= Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn retry_after_delays_retry() {
+        let client = with_retry_after(TestClient::default(), 429, "1").with_200();
+        let test = TestUpload::new();
+        let start = Instant::now();
+        let result = send(&test.upload(&client), envelopes(1)).await;
+        assert!(result.is_ok());
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(client.requests.lock().unwrap().len(), 2, "request count");
+    }
+
+    #[test_case(429, Some("3600") ; "too many requests")]
+    #[test_case(439, Some("3600") ; "application inactive")]
+    #[test_case(439, None ; "application inactive without retry after")]
+    #[test_case(503, Some("3600") ; "service unavailable")]
+    #[tokio::test]
+    async fn retry_after_opens_circuit(status_code: u16, retry_after: Option<&str>) {
+        let (storage, path) = storage(&format!(
+            "retry_after_opens_circuit_{status_code}_{}",
+            retry_after.is_some()
+        ));
+        let client = match retry_after {
+            Some(retry_after) => with_retry_after(TestClient::default(), status_code, retry_after),
+            None => TestClient::default().with_response(Ok(Response::builder()
+                .status(status_code)
+                .body(Bytes::new())
+                .expect(""))),
+        };
+        let test = TestUpload::new();
+        let notified = Arc::new(Mutex::new(Vec::new()));
+        let retry_notify: RetryNotify = {
+            let notified = notified.clone();
+            Arc::new(Mutex::new(move |err: &Error, duration: Duration| {
+                notified.lock().unwrap().push((err.to_string(), duration));
+            }))
+        };
+
+        // The retry window is longer than the total retry delay, so the request is not retried.
+        let result = send(
+            &Upload {
+                retry_notify: Some(&retry_notify),
+                storage: Some(&storage),
+                ..test.upload(&client)
+            },
+            envelopes(1),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::Upload(_))));
+
+        // Later exports don't send requests until the retry window has passed.
+        let result = send(
+            &Upload {
+                retry_notify: Some(&retry_notify),
+                storage: Some(&storage),
+                ..test.upload(&client)
+            },
+            envelopes(2),
+        )
+        .await;
+        assert!(
+            matches!(result, Err(Error::UploadThrottled(retry_after)) if retry_after > Duration::from_secs(3500))
+        );
+        send_stored(&Upload {
+            storage: Some(&storage),
+            ..test.upload(&client)
+        })
+        .await;
+        assert_eq!(client.requests.lock().unwrap().len(), 1, "request count");
+        assert_eq!(notified.lock().unwrap().len(), 1, "notify count");
+
+        // Both batches are kept in the offline storage.
+        let mut stored = Vec::new();
+        while let Some(blob) = storage.lease_next() {
+            stored.push(envelopes_ids_from_request_body(&blob.read().unwrap()));
+        }
+        stored.sort();
+        assert_eq!(stored, vec![vec![0], vec![0, 1]]);
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    fn with_redirect(client: TestClient, status_code: u16, location: &str) -> TestClient {
+        client.with_response(Ok(Response::builder()
+            .status(status_code)
+            .header(http::header::LOCATION, location)
+            .body(Bytes::new())
+            .expect("")))
+    }
+
+    #[test_case(307 ; "temporary redirect")]
+    #[test_case(308 ; "permanent redirect")]
+    #[tokio::test]
+    async fn follow_redirect(status_code: u16) {
+        let client = with_redirect(
+            TestClient::default(),
+            status_code,
+            "https://westeurope-5.in.applicationinsights.azure.com/v2.1/track",
+        )
+        .with_200()
+        .with_200();
+        let test = TestUpload::new();
+        let result = send(&test.upload(&client), envelopes(1)).await;
+        assert!(result.is_ok());
+
+        // Later exports go straight to the redirected host.
+        let result = send(&test.upload(&client), envelopes(1)).await;
+        assert!(result.is_ok());
+        let uris: Vec<_> = client
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| request.uri().to_string())
+            .collect();
+        assert_eq!(
+            uris,
+            vec![
+                "https://example.com/track",
+                "https://westeurope-5.in.applicationinsights.azure.com/track",
+                "https://westeurope-5.in.applicationinsights.azure.com/track",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn redirect_limit() {
+        let mut client = TestClient::default();
+        for index in 0..=MAX_REDIRECTS {
+            client = with_redirect(client, 307, &format!("https://host{index}.example.com"));
+        }
+        let result = send(&TestUpload::new().upload(&client), envelopes(1)).await;
+        assert_eq!(result.unwrap_err().to_string(), "upload failed with 307");
+        assert_eq!(
+            client.requests.lock().unwrap().len(),
+            MAX_REDIRECTS + 1,
+            "request count"
+        );
+    }
+
+    #[tokio::test]
+    async fn relative_redirect_is_not_followed() {
+        let client = with_redirect(TestClient::default(), 307, "/v2/track");
+        let test = TestUpload::new();
+        let result = send(&test.upload(&client), envelopes(1)).await;
+        assert!(result.is_err());
+        assert_eq!(client.requests.lock().unwrap().len(), 1, "request count");
+        assert_eq!(test.endpoint.uri(), endpoint());
+    }
+
+    #[tokio::test]
+    async fn bearer_token() {
+        let client = TestClient::default().with_200().with_200();
+        let credential = TokenCache::new(
+            Arc::new(TestCredential::new(Duration::from_secs(3600))),
+            "https://monitor.azure.com/.default".into(),
+        );
+        for _ in 0..2 {
+            let result = send(
+                &Upload {
+                    credential: Some(&credential),
+                    ..TestUpload::new().upload(&client)
+                },
+                envelopes(1),
+            )
+            .await;
+            assert!(result.is_ok());
+        }
+        let requests = client.requests.lock().unwrap();
+        for request in requests.iter() {
+            assert_eq!(
+                request.headers().get(http::header::AUTHORIZATION).unwrap(),
+                "Bearer token0"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn bearer_token_requires_https() {
+        let client = TestClient::default();
+        let credential = TokenCache::new(
+            Arc::new(TestCredential::new(Duration::from_secs(3600))),
+            "https://monitor.azure.com/.default".into(),
+        );
+        let endpoint = Endpoint::new(Uri::from_static("http://example.com/track"));
+        let result = send(
+            &Upload {
+                endpoint: &endpoint,
+                credential: Some(&credential),
+                ..TestUpload::new().upload(&client)
+            },
+            envelopes(1),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::TokenCredential(_))));
+        assert!(client.requests.lock().unwrap().is_empty());
+    }
+
+    #[test_case(401 ; "unauthorized")]
+    #[test_case(403 ; "forbidden")]
+    #[tokio::test]
+    async fn unauthorized(status_code: u16) {
+        let client = TestClient::default()
+            .with_response(Ok(Response::builder()
+                .status(status_code)
+                .body(Bytes::new())
+                .expect("")))
+            .with_200();
+        let test_credential = Arc::new(TestCredential::new(Duration::from_secs(3600)));
+        let credential = TokenCache::new(
+            test_credential.clone(),
+            "https://monitor.azure.com/.default".into(),
+        );
+        let result = send(
+            &Upload {
+                credential: Some(&credential),
+                ..TestUpload::new().upload(&client)
+            },
+            envelopes(1),
+        )
+        .await;
+        assert!(matches!(result, Err(Error::UploadUnauthorized(code)) if code == status_code));
+        assert_eq!(client.requests.lock().unwrap().len(), 1, "request count");
+
+        // The rejected token is not used again.
+        let result = send(
+            &Upload {
+                credential: Some(&credential),
+                ..TestUpload::new().upload(&client)
+            },
+            envelopes(1),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(
+            client.requests.lock().unwrap()[1]
+                .headers()
+                .get(http::header::AUTHORIZATION)
+                .unwrap(),
+            "Bearer token1"
+        );
+    }
+
+    fn storage(name: &str) -> (LocalStorage, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!(
+            "opentelemetry-application-insights-uploader-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&path);
+        (LocalStorage::new(OfflineStorage::new(&path)), path)
+    }
+
+    #[tokio::test]
+    async fn send_stored_batches() {
+        let (storage, path) = storage("send_stored_batches");
+        storage
+            .put(&serialize_envelopes(&envelopes(2)).unwrap())
+            .unwrap();
+        storage
+            .put(&serialize_envelopes(&envelopes(3)).unwrap())
+            .unwrap();
+        let client = TestClient::default().with_200().with_206(TrackResponse {
+            items_received: 3,
+            items_accepted: 2,
+            errors: vec![ErrorDetails {
+                index: 1,
+                status_code: STATUS_SERVICE_UNAVAILABLE,
+                message: None,
+            }],
+        });
+
+        send_stored(&Upload {
+            storage: Some(&storage),
+            ..TestUpload::new().upload(&client)
+        })
+        .await;
+        assert_eq!(client.requests.lock().unwrap().len(), 2, "request count");
+
+        // Only the retryable item of the second batch is left.
+        let blob = storage.lease_next().unwrap();
+        assert_eq!(
+            envelopes_ids_from_request_body(&blob.read().unwrap()),
+            vec![1]
+        );
+        blob.delete().unwrap();
+        assert!(storage.lease_next().is_none());
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn send_stored_uncompressed_batch() {
+        let (storage, path) = storage("send_stored_uncompressed_batch");
+        storage
+            .put(&serde_json::to_vec(&envelopes(2)).unwrap())
+            .unwrap();
+        let client = TestClient::default().with_200();
+
+        send_stored(&Upload {
+            storage: Some(&storage),
+            ..TestUpload::new().upload(&client)
+        })
+        .await;
+        let requests = client.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1, "request count");
+        assert!(requests[0]
+            .headers()
+            .get(http::header::CONTENT_ENCODING)
+            .is_none());
+        assert_eq!(
+            envelopes_ids_from_request_body(requests[0].body()),
+            vec![0, 1]
+        );
+        assert!(storage.lease_next().is_none());
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn send_stored_stops_on_retryable_error() {
+        let (storage, path) = storage("send_stored_stops_on_retryable_error");
+        storage
+            .put(&serialize_envelopes(&envelopes(1)).unwrap())
+            .unwrap();
+        storage
+            .put(&serialize_envelopes(&envelopes(1)).unwrap())
+            .unwrap();
+        let client = TestClient::default().with_connection_error();
+
+        send_stored(&Upload {
+            storage: Some(&storage),
+            ..TestUpload::new().upload(&client)
+        })
+        .await;
+        assert_eq!(client.requests.lock().unwrap().len(), 1, "request count");
+        assert_eq!(std::fs::read_dir(&path).unwrap().count(), 2);
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test_case(401 ; "unauthorized")]
+    #[test_case(403 ; "forbidden")]
+    #[tokio::test]
+    async fn send_stored_keeps_batches_on_unauthorized(status_code: u16) {
+        let (storage, path) = storage(&format!("send_stored_keeps_batches_{status_code}"));
+        storage
+            .put(&serialize_envelopes(&envelopes(1)).unwrap())
+            .unwrap();
+        let client = TestClient::default().with_response(Ok(Response::builder()
+            .status(status_code)
+            .body(Bytes::new())
+            .expect("")));
+
+        send_stored(&Upload {
+            storage: Some(&storage),
+            ..TestUpload::new().upload(&client)
+        })
+        .await;
+        assert_eq!(client.requests.lock().unwrap().len(), 1, "request count");
+        let blob = storage.lease_next().expect("batch is still stored");
+        assert_eq!(
+            envelopes_ids_from_request_body(&blob.read().unwrap()),
+            vec![0]
+        );
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test_case(401 ; "unauthorized")]
+    #[test_case(403 ; "forbidden")]
+    #[tokio::test]
+    async fn unauthorized_batches_are_kept_without_dead_letters(status_code: u16) {
+        let (storage, path) = storage(&format!("unauthorized_batches_{status_code}"));
+        let unauthorized = || {
+            Ok(Response::builder()
+                .status(status_code)
+                .body(Bytes::new())
+                .expect(""))
+        };
+        let client = TestClient::default()
+            .with_response(unauthorized())
+            .with_response(unauthorized())
+            .with_response(unauthorized());
+        let (dead_letter, dead_letters) = dead_letter_recorder();
+        let test = TestUpload::new();
+        let upload = Upload {
+            storage: Some(&storage),
+            dead_letter: Some(&dead_letter),
+            ..test.upload(&client)
+        };
+
+        // A fresh batch is stored instead of being dropped.
+        let result = send(&upload, envelopes(1)).await;
+        assert!(matches!(result, Err(Error::UploadUnauthorized(code)) if code == status_code));
+        assert_eq!(test.counters.snapshot().persisted, 1);
+
+        // The stored batch is rejected on every export, but kept and never dead-lettered.
+        for _ in 0..2 {
+            send_stored(&upload).await;
+        }
+        assert_eq!(client.requests.lock().unwrap().len(), 3, "request count");
+        assert!(dead_letters.lock().unwrap().is_empty());
+        assert!(test.counters.snapshot().rejected.is_empty());
+        assert!(storage.lease_next().is_some());
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn send_stored_deletes_rejected_batches() {
+        let (storage, path) = storage("send_stored_deletes_rejected_batches");
+        storage
+            .put(&serialize_envelopes(&envelopes(1)).unwrap())
+            .unwrap();
+        let client = TestClient::default().with_400();
+
+        send_stored(&Upload {
+            storage: Some(&storage),
+            ..TestUpload::new().upload(&client)
+        })
+        .await;
+        assert_eq!(client.requests.lock().unwrap().len(), 1, "request count");
+        assert!(storage.lease_next().is_none());
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
 }
@@ -1,4 +1,9 @@
-use crate::{models::QuickPulseEnvelope, uploader::serialize_request_body, Error, HttpClient};
+use crate::{
+    credential::{self, TokenCache},
+    models::QuickPulseEnvelope,
+    payload::{serialize_request_body, Compression},
+    Error, HttpClient,
+};
 use bytes::Bytes;
 use http::{HeaderName, Request, Uri};
 use std::{
@@ -52,6 +57,7 @@ pub(crate) async fn send(
     endpoint: &Uri,
     post_or_ping: PostOrPing,
     envelope: QuickPulseEnvelope,
+    credential: Option<&TokenCache>,
 ) -> Result<QuickPulseResponse, Error> {
     let payload = serialize_envelope(&envelope, &post_or_ping)?;
 
@@ -73,6 +79,12 @@ pub(crate) async fn send(
             request_builder = request_builder.header(QPS_ROLE_NAME, role_name);
         }
     }
+    if let Some(credential) = credential {
+        credential::check_endpoint(endpoint)?;
+        let token = credential.get_token().await?;
+        request_builder =
+            request_builder.header(http::header::AUTHORIZATION, format!("Bearer {}", token));
+    }
 
     let request = request_builder
         .body(Bytes::from(payload))
@@ -106,6 +118,13 @@ pub(crate) async fn send(
             redirected_host,
             polling_interval_hint,
         })
+    } else if response.status() == http::StatusCode::UNAUTHORIZED
+        || response.status() == http::StatusCode::FORBIDDEN
+    {
+        if let Some(credential) = credential {
+            credential.invalidate();
+        }
+        Err(Error::UploadUnauthorized(response.status().as_u16()))
     } else {
         Err(Error::Upload(format!(
             "{}: Failed to upload live metrics",
@@ -123,7 +142,7 @@ fn serialize_envelope(
         PostOrPing::Ping => serde_json::to_vec(&envelope),
     }
     .map_err(Error::UploadSerializeRequest)?;
-    serialize_request_body(&serialized)
+    serialize_request_body(&serialized, Compression::Default)
 }
 
 /// Time the request was made.
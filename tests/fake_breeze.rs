@@ -0,0 +1,499 @@
+//! End to end tests against the fake ingestion server of the `test-util` feature.
+#![cfg(feature = "test-util")]
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::{Request, Response};
+use opentelemetry::{
+    logs::{LogRecord as _, Logger as _, LoggerProvider as _, Severity},
+    trace::{SpanKind, TraceContextExt as _, Tracer, TracerProvider},
+    KeyValue,
+};
+use opentelemetry_application_insights::{
+    attrs as ai,
+    test_util::{FakeBreeze, FakeResponse, RequestKind},
+    AccessToken, Decision, Error, Exporter, OfflineStorage, OverflowPolicy, RetryPolicy,
+    TelemetryItem, TokenCredential,
+};
+use opentelemetry_http::{HttpClient, HttpError};
+use opentelemetry_sdk::{logs::SdkLoggerProvider, trace::SdkTracerProvider};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use test_case::test_case;
+
+/// Returns responses as they are. The `HttpClient` implementations of reqwest turn error status
+/// codes into errors and follow redirects on their own, which hides them from the exporter.
+#[derive(Debug, Clone)]
+struct Client(reqwest::blocking::Client);
+
+#[async_trait]
+impl HttpClient for Client {
+    async fn send_bytes(&self, request: Request<Bytes>) -> Result<Response<Bytes>, HttpError> {
+        let response = self.0.execute(request.try_into()?)?;
+        let mut builder = Response::builder().status(response.status());
+        for (name, value) in response.headers() {
+            builder = builder.header(name, value);
+        }
+        Ok(builder.body(response.bytes()?)?)
+    }
+}
+
+/// Gets tokens from the fake token endpoint.
+#[derive(Debug)]
+struct FakeCredential {
+    endpoint: String,
+}
+
+#[async_trait]
+impl TokenCredential for FakeCredential {
+    async fn get_token(
+        &self,
+        scopes: &[&str],
+    ) -> Result<AccessToken, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        assert_eq!(scopes, &["https://monitor.azure.com/.default"]);
+        let body = reqwest::blocking::get(&self.endpoint)?.text()?;
+        let response: serde_json::Value = serde_json::from_str(&body)?;
+        Ok(AccessToken {
+            token: response["access_token"].as_str().unwrap_or_default().into(),
+            expires_on: SystemTime::now()
+                + Duration::from_secs(response["expires_in"].as_u64().unwrap_or_default()),
+        })
+    }
+}
+
+fn exporter(server: &FakeBreeze) -> Exporter<Client> {
+    exporter_with_connection_string(server.connection_string())
+}
+
+fn exporter_with_connection_string(connection_string: String) -> Exporter<Client> {
+    let client = reqwest::blocking::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("client can be built");
+    Exporter::new_from_connection_string(connection_string, Client(client))
+        .expect("connection string is valid")
+        .with_retry_policy(
+            RetryPolicy::new()
+                .with_min_delay(Duration::from_millis(10))
+                .with_jitter(false),
+        )
+}
+
+fn export_spans(exporter: Exporter<Client>, names: &[&str]) {
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter)
+        .build();
+    let tracer = provider.tracer("test");
+    for name in names {
+        tracer
+            .span_builder(name.to_string())
+            .with_kind(SpanKind::Server)
+            .start(&tracer);
+    }
+    provider.shutdown().unwrap();
+}
+
+fn track_requests(server: &FakeBreeze) -> Vec<usize> {
+    server
+        .received_requests()
+        .iter()
+        .filter(|request| request.kind == RequestKind::Track)
+        .map(|request| request.envelopes().unwrap().len())
+        .collect()
+}
+
+#[test]
+fn traces_and_logs() {
+    let server = FakeBreeze::start();
+
+    let tracer_provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter(&server))
+        .build();
+    let tracer = tracer_provider.tracer("test");
+    tracer.in_span("GET /users", |_| {
+        tracer
+            .span_builder("SELECT users")
+            .with_kind(SpanKind::Client)
+            .start(&tracer);
+    });
+    tracer_provider.shutdown().unwrap();
+
+    let logger_provider = SdkLoggerProvider::builder()
+        .with_simple_exporter(exporter(&server))
+        .build();
+    let logger = logger_provider.logger("test");
+    let mut record = logger.create_log_record();
+    record.set_severity_number(Severity::Warn);
+    record.set_body("cache miss".into());
+    logger.emit(record);
+    logger_provider.shutdown().unwrap();
+
+    let dependencies = server.dependencies();
+    assert_eq!(dependencies.len(), 2);
+    assert!(dependencies.iter().any(|d| d.name == "SELECT users"));
+    assert!(server.requests().is_empty());
+    let traces = server.traces();
+    assert_eq!(traces.len(), 1);
+    assert_eq!(traces[0].message, "cache miss");
+    assert_eq!(traces[0].severity_level, Some(2));
+    assert!(server
+        .envelopes()
+        .iter()
+        .all(|envelope| envelope.i_key.as_deref()
+            == Some(opentelemetry_application_insights::test_util::INSTRUMENTATION_KEY)));
+}
+
+#[test]
+fn availability_events() {
+    let server = FakeBreeze::start();
+
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter(&server))
+        .build();
+    let tracer = provider.tracer("test");
+    tracer.in_span("probe", |cx| {
+        let span = cx.span();
+        for step in ["open", "login"] {
+            span.add_event(
+                step,
+                vec![KeyValue::new(ai::AVAILABILITY_RUN_LOCATION, "westeurope")],
+            );
+        }
+        span.add_event(
+            "checkout",
+            vec![
+                KeyValue::new(ai::AVAILABILITY_RUN_LOCATION, "westeurope"),
+                KeyValue::new(ai::AVAILABILITY_ID, "checkout-1"),
+                KeyValue::new(ai::AVAILABILITY_DURATION, 1500),
+            ],
+        );
+    });
+    provider.shutdown().unwrap();
+
+    let availability: Vec<_> = server
+        .envelopes()
+        .into_iter()
+        .filter_map(|envelope| envelope.data)
+        .filter(|data| data.base_type == "AvailabilityData")
+        .map(|data| data.base_data)
+        .collect();
+    assert_eq!(availability.len(), 3);
+    assert_ne!(availability[0]["id"], availability[1]["id"]);
+    assert_eq!(availability[1]["duration"], "0.00:00:00.000000");
+    assert_eq!(availability[2]["id"], "checkout-1");
+    assert_eq!(availability[2]["duration"], "0.00:00:01.500000");
+}
+
+#[test_case(OverflowPolicy::Truncate, &[("long", 8192)] ; "truncate")]
+#[test_case(OverflowPolicy::Split, &[("long", 8192), ("long_1", 8192), ("long_2", 3616)] ; "split")]
+fn long_property_values(policy: OverflowPolicy, expected: &[(&str, usize)]) {
+    let server = FakeBreeze::start();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter(&server).with_overflow_policy(policy))
+        .build();
+    let tracer = provider.tracer("test");
+    tracer
+        .span_builder("request")
+        .with_kind(SpanKind::Server)
+        .with_attributes([KeyValue::new("long", "x".repeat(20_000))])
+        .start(&tracer);
+    provider.shutdown().unwrap();
+
+    let requests = server.requests();
+    let lengths: Vec<_> = requests[0]
+        .properties
+        .iter()
+        .filter(|(key, _)| key.starts_with("long"))
+        .map(|(key, value)| (key.as_str(), value.len()))
+        .collect();
+    assert_eq!(lengths, expected);
+}
+
+#[test]
+fn partial_success_is_retried() {
+    let server = FakeBreeze::start();
+    server.respond_to_track(FakeResponse::partial(&[(1, 500)]));
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter(&server))
+        .build();
+    let tracer = provider.tracer("test");
+    for name in ["first", "second", "third"] {
+        tracer
+            .span_builder(name)
+            .with_kind(SpanKind::Server)
+            .start(&tracer);
+    }
+    provider.shutdown().unwrap();
+
+    assert_eq!(track_requests(&server), vec![3, 1]);
+    let requests = server.requests();
+    assert_eq!(requests.len(), 4);
+    assert_eq!(
+        requests.last().unwrap().name.as_deref(),
+        requests[1].name.as_deref()
+    );
+}
+
+#[test]
+fn spans_routed_to_invalid_connection_strings_are_dropped() {
+    let server = FakeBreeze::start();
+    let exporter = exporter(&server).with_span_router(|span| {
+        (span.name == "invalid").then(|| "not a connection string".to_string())
+    });
+
+    export_spans(exporter.clone(), &["valid", "invalid", "invalid"]);
+
+    assert_eq!(track_requests(&server), vec![1]);
+    let stats = exporter.stats();
+    assert_eq!(stats.traces.accepted, 1);
+    assert_eq!(stats.traces.dropped, 2);
+}
+
+#[test]
+fn items_dropped_by_processors_are_counted() {
+    let server = FakeBreeze::start();
+    let exporter = exporter(&server).with_telemetry_processor(|item: &mut TelemetryItem<'_>| {
+        if item.name() == Some("health") {
+            Decision::Drop
+        } else {
+            Decision::Keep
+        }
+    });
+
+    export_spans(exporter.clone(), &["request", "health"]);
+
+    assert_eq!(track_requests(&server), vec![1]);
+    let stats = exporter.stats();
+    assert_eq!(stats.traces.accepted, 1);
+    assert_eq!(stats.traces.dropped, 1);
+}
+
+#[test_case(FakeResponse::throttled(439, 3600) ; "with retry after")]
+#[test_case(FakeResponse::status(439) ; "daily cap without retry after")]
+fn throttled(response: FakeResponse) {
+    let server = FakeBreeze::start();
+    server.respond_to_track(response);
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let exporter = exporter(&server).with_retry_notify({
+        let errors = errors.clone();
+        move |error: &Error, _| {
+            errors
+                .lock()
+                .unwrap()
+                .push(matches!(error, Error::UploadThrottled(_)))
+        }
+    });
+
+    export_spans(exporter, &["first", "second"]);
+
+    // The second export is skipped without sending a request.
+    assert_eq!(track_requests(&server), vec![1]);
+    assert_eq!(*errors.lock().unwrap(), vec![true]);
+}
+
+#[test]
+fn stored_items_are_counted_separately() {
+    let server = FakeBreeze::start();
+    server.respond_to_track(FakeResponse::status(503));
+    let path = std::env::temp_dir().join(format!(
+        "opentelemetry-application-insights-fake-breeze-stored-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&path);
+    let exporter = exporter(&server)
+        .with_retry_policy(RetryPolicy::no_retries())
+        .with_offline_storage(OfflineStorage::new(&path));
+
+    // The span is persisted and sent again with the log record.
+    export_spans(exporter.clone(), &["first"]);
+    let logger_provider = SdkLoggerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let mut record = logger_provider.logger("test").create_log_record();
+    record.set_body("log".into());
+    logger_provider.logger("test").emit(record);
+    logger_provider.shutdown().unwrap();
+
+    assert_eq!(track_requests(&server), vec![1, 1, 1]);
+    let stats = exporter.stats();
+    assert_eq!(stats.traces.persisted, 1);
+    assert_eq!(stats.traces.accepted, 0);
+    assert_eq!(stats.logs.accepted, 1);
+    assert_eq!(stats.stored.sent, 1);
+    assert_eq!(stats.stored.accepted, 1);
+
+    std::fs::remove_dir_all(path).unwrap();
+}
+
+#[test]
+fn redirect() {
+    let server = FakeBreeze::start();
+    let redirected = FakeBreeze::start();
+    server.respond_to_track(FakeResponse::redirect(
+        307,
+        format!("{}/v2/track", redirected.endpoint()),
+    ));
+
+    export_spans(exporter(&server), &["first", "second"]);
+
+    assert_eq!(track_requests(&server), vec![1]);
+    assert_eq!(track_requests(&redirected), vec![1, 1]);
+}
+
+#[test]
+fn token_authentication() {
+    let server = FakeBreeze::start();
+    server.respond_to_track(FakeResponse::status(401));
+    let exporter = exporter_with_connection_string(server.aad_connection_string())
+        .with_token_credential(FakeCredential {
+            endpoint: server.token_endpoint(),
+        });
+
+    export_spans(exporter, &["first", "second", "third"]);
+
+    // The rejected token is requested again, later exports use the cached token.
+    let requests = server.received_requests();
+    let authorization: Vec<_> = requests
+        .iter()
+        .filter(|request| request.kind == RequestKind::Track)
+        .map(|request| request.headers["authorization"].as_str())
+        .collect();
+    assert_eq!(
+        authorization,
+        vec![
+            "Bearer fake-token-0",
+            "Bearer fake-token-1",
+            "Bearer fake-token-1"
+        ]
+    );
+    let token_requests = requests
+        .iter()
+        .filter(|request| request.kind == RequestKind::Token)
+        .count();
+    assert_eq!(token_requests, 2);
+}
+
+/// Returns tokens named after the requested scope.
+#[derive(Debug)]
+struct ScopeCredential;
+
+#[async_trait]
+impl TokenCredential for ScopeCredential {
+    async fn get_token(
+        &self,
+        scopes: &[&str],
+    ) -> Result<AccessToken, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        Ok(AccessToken {
+            token: format!("token-for-{}", scopes.join(" ")),
+            expires_on: SystemTime::now() + Duration::from_secs(3600),
+        })
+    }
+}
+
+#[test]
+fn token_per_destination() {
+    let server = FakeBreeze::start();
+    let destination = |instrumentation_key: &str, authorization: &str| {
+        format!(
+            "InstrumentationKey={instrumentation_key};IngestionEndpoint={}{authorization}",
+            server.endpoint()
+        )
+    };
+    let exporter = exporter_with_connection_string(server.aad_connection_string())
+        .with_additional_connection_string(destination("ikey-only", ""))
+        .unwrap()
+        .with_additional_connection_string(destination(
+            "other-cloud",
+            ";Authorization=AAD;AADAudience=https://monitor.azure.us/",
+        ))
+        .unwrap()
+        .with_token_credential(ScopeCredential);
+
+    export_spans(exporter, &["span"]);
+
+    let mut authorization: Vec<_> = server
+        .received_requests()
+        .iter()
+        .filter(|request| request.kind == RequestKind::Track)
+        .map(|request| {
+            (
+                request.envelopes().unwrap()[0].i_key.clone().unwrap(),
+                request.headers.get("authorization").cloned(),
+            )
+        })
+        .collect();
+    authorization.sort();
+    assert_eq!(
+        authorization,
+        vec![
+            (
+                opentelemetry_application_insights::test_util::INSTRUMENTATION_KEY.to_string(),
+                Some("Bearer token-for-https://monitor.azure.com/.default".to_string())
+            ),
+            ("ikey-only".to_string(), None),
+            (
+                "other-cloud".to_string(),
+                Some("Bearer token-for-https://monitor.azure.us/.default".to_string())
+            ),
+        ]
+    );
+}
+
+#[test]
+fn missing_token_credential() {
+    let server = FakeBreeze::start();
+
+    export_spans(
+        exporter_with_connection_string(server.aad_connection_string()),
+        &["first"],
+    );
+
+    assert!(server.received_requests().is_empty());
+}
+
+#[cfg(feature = "live-metrics")]
+#[tokio::test(flavor = "multi_thread")]
+async fn live_metrics() {
+    use opentelemetry_application_insights::LiveMetricsSpanProcessor;
+
+    let server = FakeBreeze::start();
+    server.set_subscribed(true);
+    let exporter =
+        Exporter::new_from_connection_string(server.connection_string(), reqwest::Client::new())
+            .expect("connection string is valid");
+    let provider = SdkTracerProvider::builder()
+        .with_span_processor(LiveMetricsSpanProcessor::new(
+            exporter,
+            opentelemetry_sdk::runtime::Tokio,
+        ))
+        .build();
+    let tracer = provider.tracer("test");
+
+    let has_request = |kind| {
+        server
+            .received_requests()
+            .iter()
+            .any(|request| request.kind == kind)
+    };
+    for _ in 0..150 {
+        tracer
+            .span_builder("live-metrics")
+            .with_kind(SpanKind::Server)
+            .start(&tracer);
+        if has_request(RequestKind::QuickPulsePost) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    provider.shutdown().unwrap();
+
+    assert!(has_request(RequestKind::QuickPulsePing));
+    let post = server
+        .received_requests()
+        .into_iter()
+        .find(|request| request.kind == RequestKind::QuickPulsePost)
+        .expect("live metrics were posted");
+    assert!(post.json().unwrap().is_array());
+}
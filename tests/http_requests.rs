@@ -7,7 +7,6 @@
 //! ```
 
 use format::requests_to_string;
-#[cfg(feature = "live-metrics")]
 use opentelemetry::trace::{Span, Status};
 use opentelemetry::{
     logs::{LogRecord as _, Logger as _, LoggerProvider as _, Severity},
@@ -17,7 +16,11 @@ use opentelemetry::{
     },
     Context, KeyValue,
 };
-use opentelemetry_application_insights::{attrs as ai, Exporter};
+use opentelemetry_application_insights::{
+    attrs as ai, AvailabilityTelemetry, EventTelemetry, ExceptionTelemetry, Exporter,
+    MeasurementAttributes, MetricTelemetry, PageViewPerformanceTelemetry, PageViewTelemetry,
+    SeverityLevel, TelemetryClient, TraceTelemetry,
+};
 use opentelemetry_sdk::{logs::SdkLoggerProvider, trace::SdkTracerProvider, Resource};
 use opentelemetry_semantic_conventions as semcov;
 use recording_client::record;
@@ -26,6 +29,7 @@ use tick::{NoTick, TokioTick};
 
 // Fake instrumentation key (this is a random uuid)
 const CONNECTION_STRING: &str = "InstrumentationKey=0fdcec70-0ce5-4085-89d9-9ae8ead9af66";
+const ADDITIONAL_CONNECTION_STRING: &str = "InstrumentationKey=5c3a7f3e-2d4b-4e3f-9c1a-8b7d6e5f4a3b;IngestionEndpoint=https://westeurope-5.in.applicationinsights.azure.com/";
 
 #[test]
 fn traces() {
@@ -193,6 +197,320 @@ fn traces_with_resource_attributes_in_events_and_logs() {
     insta::assert_snapshot!(traces_with_resource_attributes_in_events_and_logs);
 }
 
+#[test]
+fn traces_fan_out() {
+    let requests = record(NoTick, |client| {
+        let exporter = Exporter::new_from_connection_string(CONNECTION_STRING, client)
+            .expect("connection string is valid")
+            .with_additional_connection_string(ADDITIONAL_CONNECTION_STRING)
+            .expect("connection string is valid");
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter)
+            .build();
+        let tracer = tracer_provider.tracer("test");
+
+        tracer.in_span("fan out", |_cx| {});
+
+        tracer_provider.shutdown().unwrap();
+    });
+    let traces_fan_out = requests_to_string(requests);
+    insta::assert_snapshot!(traces_fan_out);
+}
+
+#[test]
+fn traces_routing() {
+    let requests = record(NoTick, |client| {
+        let exporter = Exporter::new_from_connection_string(CONNECTION_STRING, client)
+            .expect("connection string is valid");
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter)
+            .build();
+        let tracer = tracer_provider.tracer("test");
+
+        tracer.in_span("default", |_cx| {});
+        drop(
+            tracer
+                .span_builder("routed")
+                .with_attributes([KeyValue::new(
+                    ai::CONNECTION_STRING,
+                    ADDITIONAL_CONNECTION_STRING,
+                )])
+                .start(&tracer),
+        );
+
+        tracer_provider.shutdown().unwrap();
+    });
+    let traces_routing = requests_to_string(requests);
+    insta::assert_snapshot!(traces_routing);
+}
+
+#[test]
+fn traces_availability() {
+    let requests = record(NoTick, |client| {
+        let exporter = Exporter::new_from_connection_string(CONNECTION_STRING, client)
+            .expect("connection string is valid");
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter)
+            .build();
+        let tracer = tracer_provider.tracer("test");
+
+        let mut span = tracer
+            .span_builder("home page probe")
+            .with_kind(SpanKind::Client)
+            .with_attributes([
+                KeyValue::new(ai::AVAILABILITY_RUN_LOCATION, "westeurope"),
+                KeyValue::new("probe.url", "https://example.com"),
+            ])
+            .start(&tracer);
+        span.add_event(
+            "login step",
+            vec![
+                KeyValue::new(ai::AVAILABILITY_RUN_LOCATION, "westeurope"),
+                KeyValue::new(ai::AVAILABILITY_SUCCESS, false),
+                KeyValue::new(ai::AVAILABILITY_MESSAGE, "login form missing"),
+            ],
+        );
+        span.set_status(Status::error("login failed"));
+        span.end();
+
+        tracer_provider.shutdown().unwrap();
+    });
+    let traces_availability = requests_to_string(requests);
+    insta::assert_snapshot!(traces_availability);
+}
+
+#[test]
+fn traces_exceptions() {
+    let requests = record(NoTick, |client| {
+        let exporter = Exporter::new_from_connection_string(CONNECTION_STRING, client)
+            .expect("connection string is valid");
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter)
+            .build();
+        let tracer = tracer_provider.tracer("test");
+
+        let mut span = tracer.start("checkout");
+        span.add_event(
+            "exception",
+            vec![
+                KeyValue::new(semcov::trace::EXCEPTION_TYPE, "PaymentError"),
+                KeyValue::new(semcov::trace::EXCEPTION_MESSAGE, "payment failed"),
+                KeyValue::new(
+                    semcov::trace::EXCEPTION_STACKTRACE,
+                    "   0: std::backtrace::Backtrace::capture\n   1: app::checkout::pay\n             at ./src/checkout.rs:42:9\n   2: app::main\n             at ./src/main.rs:4:5\n",
+                ),
+                KeyValue::new("exception.cause.0.type", "std::io::Error"),
+                KeyValue::new("exception.cause.0.message", "connection reset"),
+                KeyValue::new("exception.escaped", true),
+            ],
+        );
+        span.add_event(
+            "exception",
+            vec![
+                KeyValue::new(semcov::trace::EXCEPTION_TYPE, "ValidationError"),
+                KeyValue::new(semcov::trace::EXCEPTION_MESSAGE, "invalid card"),
+                KeyValue::new(ai::EXCEPTION_PROBLEM_ID, "invalid card"),
+                KeyValue::new("level", "WARN"),
+            ],
+        );
+        span.end();
+
+        tracer_provider.shutdown().unwrap();
+    });
+    let traces_exceptions = requests_to_string(requests);
+    insta::assert_snapshot!(traces_exceptions);
+}
+
+#[test]
+fn traces_with_measurement_attributes() {
+    let requests = record(NoTick, |client| {
+        let exporter = Exporter::new_from_connection_string(CONNECTION_STRING, client)
+            .expect("connection string is valid")
+            .with_measurement_attributes(MeasurementAttributes::prefix("ai.measurement."));
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter)
+            .build();
+        let tracer = tracer_provider.tracer("test");
+
+        let mut span = tracer
+            .span_builder("checkout")
+            .with_kind(SpanKind::Server)
+            .with_attributes([
+                KeyValue::new("ai.measurement.items", 3),
+                KeyValue::new("cart.id", "42"),
+            ])
+            .start(&tracer);
+        span.add_event(
+            "ai.custom",
+            vec![
+                KeyValue::new(ai::CUSTOM_EVENT_NAME, "payment"),
+                KeyValue::new("ai.measurement.total", 42.5),
+                KeyValue::new("ai.measurement.label", "not a number"),
+            ],
+        );
+        span.end();
+
+        tracer_provider.shutdown().unwrap();
+    });
+    let traces_with_measurement_attributes = requests_to_string(requests);
+    insta::assert_snapshot!(traces_with_measurement_attributes);
+}
+
+#[test]
+fn traces_page_view() {
+    let requests = record(NoTick, |client| {
+        let exporter = Exporter::new_from_connection_string(CONNECTION_STRING, client)
+            .expect("connection string is valid");
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter)
+            .build();
+        let tracer = tracer_provider.tracer("test");
+
+        let mut span = tracer
+            .span_builder("GET /home")
+            .with_kind(SpanKind::Server)
+            .with_attributes([
+                KeyValue::new(semcov::trace::HTTP_REQUEST_METHOD, "GET"),
+                KeyValue::new(semcov::trace::URL_FULL, "https://example.com/home"),
+            ])
+            .start(&tracer);
+        span.add_event(
+            "ai.pageView",
+            vec![
+                KeyValue::new(ai::PAGE_VIEW_NAME, "Home"),
+                KeyValue::new(ai::PAGE_VIEW_REFERRER_URI, "https://example.com/login"),
+                KeyValue::new(ai::PAGE_VIEW_DURATION, 250),
+                KeyValue::new(ai::SESSION_ID, "session"),
+                KeyValue::new("theme", "dark"),
+            ],
+        );
+        span.end();
+
+        tracer_provider.shutdown().unwrap();
+    });
+    let traces_page_view = requests_to_string(requests);
+    insta::assert_snapshot!(traces_page_view);
+}
+
+#[test]
+fn traces_with_context_tags_and_common_properties() {
+    let requests = record(NoTick, |client| {
+        let exporter = Exporter::new_from_connection_string(CONNECTION_STRING, client)
+            .expect("connection string is valid")
+            .with_context_tags([(ai::CLOUD_ROLE, "role"), (ai::APPLICATION_VERSION, "1.2.3")])
+            .with_common_properties([("region", "westeurope"), ("ring", "0")]);
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_simple_exporter(exporter)
+            .with_resource(
+                Resource::builder_empty()
+                    .with_attribute(KeyValue::new(semcov::resource::SERVICE_NAME, "service"))
+                    .build(),
+            )
+            .build();
+        let tracer = tracer_provider.tracer("test");
+
+        tracer.in_span("common", |_cx| {
+            get_active_span(|span| {
+                span.add_event("An event!", vec![]);
+            });
+        });
+        drop(
+            tracer
+                .span_builder("overridden")
+                .with_attributes([
+                    KeyValue::new(ai::APPLICATION_VERSION, "4.5.6"),
+                    KeyValue::new("ring", "1"),
+                ])
+                .start(&tracer),
+        );
+
+        tracer_provider.shutdown().unwrap();
+    });
+    let traces_with_context_tags_and_common_properties = requests_to_string(requests);
+    insta::assert_snapshot!(traces_with_context_tags_and_common_properties);
+}
+
+#[test]
+fn telemetry_client() {
+    let requests = record(NoTick, |client| {
+        let exporter = Exporter::new_from_connection_string(CONNECTION_STRING, client)
+            .expect("connection string is valid")
+            .with_context_tags([(ai::CLOUD_ROLE, "role")]);
+        let client = TelemetryClient::new(exporter);
+
+        client.track_event(
+            EventTelemetry::new("checkout")
+                .with_property("payment", "card")
+                .with_measurement("total", 42.5)
+                .with_tag(ai::OPERATION_ID, "4bf92f3577b34da6a3ce929d0e0e4736"),
+        );
+        client.track_metric(MetricTelemetry::new("queue length", 7.0).with_namespace("orders"));
+        client.track_exception(
+            ExceptionTelemetry::new("PaymentError", "card declined")
+                .with_stack("at checkout")
+                .with_severity_level(SeverityLevel::Error),
+        );
+        client.track_trace(
+            TraceTelemetry::new("payment retried").with_severity_level(SeverityLevel::Warning),
+        );
+        client.track_availability(
+            AvailabilityTelemetry::new("home page", Duration::from_millis(1500), true)
+                .with_id("probe-1")
+                .with_run_location("westeurope")
+                .with_message("passed")
+                .with_measurement("bytes", 1024.0),
+        );
+        client.track_page_view(
+            PageViewTelemetry::new("Home")
+                .with_url("https://example.com/?q=1")
+                .with_duration(Duration::from_millis(250))
+                .with_referrer_uri("https://example.com/login"),
+        );
+        client.track_page_view_performance(
+            PageViewPerformanceTelemetry::new("Home")
+                .with_url("https://example.com/?q=1")
+                .with_perf_total(Duration::from_millis(900))
+                .with_network_connect(Duration::from_millis(50))
+                .with_sent_request(Duration::from_millis(100))
+                .with_received_response(Duration::from_millis(300))
+                .with_dom_processing(Duration::from_millis(450)),
+        );
+
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(client.flush())
+            .unwrap();
+    });
+    let telemetry_client = requests_to_string(requests);
+    insta::assert_snapshot!(telemetry_client);
+}
+
+#[test]
+fn telemetry_client_drops_items_when_buffer_is_full() {
+    let requests = record(NoTick, |client| {
+        let exporter = Exporter::new_from_connection_string(CONNECTION_STRING, client)
+            .expect("connection string is valid");
+        let client = TelemetryClient::new(exporter.clone()).with_max_buffered(2);
+
+        client.track_event(EventTelemetry::new("first"));
+        client.track_trace(TraceTelemetry::new("second"));
+        client.track_event(EventTelemetry::new("dropped"));
+        client.track_metric(MetricTelemetry::new("dropped", 1.0));
+        let stats = exporter.stats();
+        assert_eq!(stats.logs.dropped, 1);
+        assert_eq!(stats.metrics.dropped, 1);
+
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(client.flush())
+            .unwrap();
+        assert_eq!(exporter.stats().logs.sent, 2);
+    });
+    assert_eq!(requests.len(), 1);
+}
+
 #[test]
 fn logs() {
     let requests = record(NoTick, |client| {